@@ -1,9 +1,15 @@
 //! Physical and virtual addresses manipulation
 
+use core::convert::TryFrom;
 use core::fmt;
+#[cfg(feature = "step_trait")]
+use core::iter::Step;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::ptr::NonNull;
+#[cfg(feature = "instructions")]
+use core::sync::atomic::{AtomicU8, Ordering};
 
-use crate::structures::paging::{PageOffset, PageTableIndex};
+use crate::structures::paging::{PageOffset, PageTableIndex, PageTableLevel};
 use bit_field::BitField;
 
 /// A canonical 64-bit virtual memory address.
@@ -43,19 +49,25 @@ pub struct PhysAddr(u64);
 pub struct VirtAddrNotValid(u64);
 
 impl VirtAddr {
-    /// Creates a new canonical virtual address.
-    ///
-    /// This function performs sign extension of bit 47 to make the address canonical.
-    ///
-    /// ## Panics
-    ///
-    /// This function panics if the bits in the range 48 to 64 contain data (i.e. are not null and no sign extension).
-    #[inline]
-    pub fn new(addr: u64) -> VirtAddr {
-        Self::try_new(addr).expect(
-            "address passed to VirtAddr::new must not contain any data \
-             in bits 48 to 64",
-        )
+    const_fn! {
+        /// Creates a new canonical virtual address.
+        ///
+        /// This function performs sign extension of bit 47 to make the address canonical.
+        ///
+        /// ## Panics
+        ///
+        /// This function panics if the bits in the range 48 to 64 contain data (i.e. are not null and no sign extension).
+        #[inline]
+        pub fn new(addr: u64) -> VirtAddr {
+            match addr >> 47 {
+                0 | 0x1ffff => VirtAddr(addr),     // address is canonical
+                1 => VirtAddr::new_truncate(addr), // address needs sign extension
+                _ => panic!(
+                    "address passed to VirtAddr::new must not contain any data \
+                     in bits 48 to 64"
+                ),
+            }
+        }
     }
 
     /// Tries to create a new canonical virtual address.
@@ -114,8 +126,22 @@ impl VirtAddr {
     // doesn't truncate.
     #[cfg(any(target_pointer_width = "32", target_pointer_width = "64"))]
     #[inline]
-    pub fn from_ptr<T>(ptr: *const T) -> Self {
-        Self::new(ptr as u64)
+    pub fn from_ptr<T: ?Sized>(ptr: *const T) -> Self {
+        Self::new(ptr as *const () as u64)
+    }
+
+    /// Creates a virtual address from the given non-null pointer.
+    #[cfg(any(target_pointer_width = "32", target_pointer_width = "64"))]
+    #[inline]
+    pub fn from_non_null<T: ?Sized>(ptr: NonNull<T>) -> Self {
+        Self::from_ptr(ptr.as_ptr())
+    }
+
+    /// Creates a virtual address from the given reference.
+    #[cfg(any(target_pointer_width = "32", target_pointer_width = "64"))]
+    #[inline]
+    pub fn from_ref<T: ?Sized>(r: &T) -> Self {
+        Self::from_ptr(r)
     }
 
     /// Converts the address to a raw pointer.
@@ -132,6 +158,15 @@ impl VirtAddr {
         self.as_ptr::<T>() as *mut T
     }
 
+    /// Converts the address to a `NonNull` pointer.
+    ///
+    /// Returns `None` if the address is zero.
+    #[cfg(target_pointer_width = "64")]
+    #[inline]
+    pub fn as_non_null_ptr<T>(self) -> Option<NonNull<T>> {
+        NonNull::new(self.as_mut_ptr())
+    }
+
     /// Convenience method for checking if a virtual address is null.
     #[inline]
     pub const fn is_null(self) -> bool {
@@ -140,24 +175,64 @@ impl VirtAddr {
 
     /// Aligns the virtual address upwards to the given alignment.
     ///
-    /// See the `align_up` function for more information.
+    /// See the `align_up` function for more information. The result is made canonical by
+    /// sign extending bit 47 (see [`VirtAddr::new_truncate`]), so aligning upwards across
+    /// the end of the lower half wraps into the higher half. Use [`VirtAddr::checked_align_up`]
+    /// to detect this.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two or if the aligned address would overflow.
     #[inline]
     pub fn align_up<U>(self, align: U) -> Self
     where
         U: Into<u64>,
     {
-        VirtAddr(align_up(self.0, align.into()))
+        VirtAddr::new_truncate(align_up(self.0, align.into()))
     }
 
     /// Aligns the virtual address downwards to the given alignment.
     ///
-    /// See the `align_down` function for more information.
+    /// See the `align_down` function for more information. The result is made canonical by
+    /// sign extending bit 47 (see [`VirtAddr::new_truncate`]).
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two.
     #[inline]
     pub fn align_down<U>(self, align: U) -> Self
     where
         U: Into<u64>,
     {
-        VirtAddr(align_down(self.0, align.into()))
+        VirtAddr::new_truncate(align_down(self.0, align.into()))
+    }
+
+    /// Aligns the virtual address upwards to the given alignment.
+    ///
+    /// Returns `None` if `align` is not a power of two or if the aligned address would
+    /// overflow or not be canonical.
+    #[inline]
+    pub fn checked_align_up<U>(self, align: U) -> Option<Self>
+    where
+        U: Into<u64>,
+    {
+        checked_align_up(self.0, align.into())
+            .filter(|&addr| is_canonical(addr))
+            .map(VirtAddr)
+    }
+
+    /// Aligns the virtual address downwards to the given alignment.
+    ///
+    /// Returns `None` if `align` is not a power of two or if the aligned address would not
+    /// be canonical.
+    #[inline]
+    pub fn checked_align_down<U>(self, align: U) -> Option<Self>
+    where
+        U: Into<u64>,
+    {
+        checked_align_down(self.0, align.into())
+            .filter(|&addr| is_canonical(addr))
+            .map(VirtAddr)
     }
 
     /// Checks whether the virtual address has the demanded alignment.
@@ -169,6 +244,40 @@ impl VirtAddr {
         self.align_down(align) == self
     }
 
+    /// Checked addition. Computes `self + rhs`, returning `None` if the addition overflows
+    /// or the result is not a canonical address.
+    ///
+    /// In contrast to the `Add` implementation, no sign extension is performed, so crossing
+    /// from the lower half into the non-canonical hole of the address space returns `None`.
+    #[inline]
+    pub fn checked_add(self, rhs: u64) -> Option<Self> {
+        match self.0.checked_add(rhs) {
+            Some(addr) if is_canonical(addr) => Some(VirtAddr(addr)),
+            _ => None,
+        }
+    }
+
+    /// Checked subtraction. Computes `self - rhs`, returning `None` if the subtraction
+    /// underflows or the result is not a canonical address.
+    ///
+    /// In contrast to the `Sub` implementation, no sign extension is performed, so crossing
+    /// from the higher half into the non-canonical hole of the address space returns `None`.
+    #[inline]
+    pub fn checked_sub(self, rhs: u64) -> Option<Self> {
+        match self.0.checked_sub(rhs) {
+            Some(addr) if is_canonical(addr) => Some(VirtAddr(addr)),
+            _ => None,
+        }
+    }
+
+    /// Returns the signed distance in bytes from `origin` to `self`, i.e. `self - origin`.
+    ///
+    /// Returns `None` if the distance does not fit into an `i64`.
+    #[inline]
+    pub fn checked_offset_from(self, origin: VirtAddr) -> Option<i64> {
+        i64::try_from(i128::from(self.0) - i128::from(origin.0)).ok()
+    }
+
     /// Returns the 12-bit page offset of this virtual address.
     #[inline]
     pub const fn page_offset(self) -> PageOffset {
@@ -198,8 +307,27 @@ impl VirtAddr {
     pub const fn p4_index(self) -> PageTableIndex {
         PageTableIndex::new_truncate((self.0 >> 12 >> 9 >> 9 >> 9) as u16)
     }
+
+    /// Returns the 9-bit page table index of the given level.
+    #[inline]
+    pub const fn page_table_index(self, level: PageTableLevel) -> PageTableIndex {
+        PageTableIndex::new_truncate((self.0 >> 12 >> ((level as u8 - 1) * 9)) as u16)
+    }
 }
 
+// Only canonical addresses are accepted, `try_new` would sign extend some non-canonical ones.
+#[cfg(feature = "serde")]
+serde_via_int!(
+    VirtAddr,
+    u64,
+    "a canonical virtual address",
+    |addr| addr.0,
+    |addr| match addr.get_bits(47..64) {
+        0 | 0x1ffff => Some(VirtAddr(addr)),
+        _ => None,
+    }
+);
+
 impl fmt::Debug for VirtAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("VirtAddr")
@@ -245,9 +373,18 @@ impl fmt::Pointer for VirtAddr {
 
 impl Add<u64> for VirtAddr {
     type Output = Self;
+    /// Adds `rhs` to the address.
+    ///
+    /// The result is passed to [`VirtAddr::new`], so it is sign extended if it lies directly
+    /// above the lower half of the address space.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the addition overflows or if the result is not a valid virtual address
+    /// (see [`VirtAddr::new`]). Use [`VirtAddr::checked_add`] for a non-panicking version.
     #[inline]
     fn add(self, rhs: u64) -> Self::Output {
-        VirtAddr::new(self.0 + rhs)
+        VirtAddr::new(self.0.checked_add(rhs).unwrap())
     }
 }
 
@@ -277,6 +414,12 @@ impl AddAssign<usize> for VirtAddr {
 
 impl Sub<u64> for VirtAddr {
     type Output = Self;
+    /// Subtracts `rhs` from the address.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the subtraction underflows or if the result is not a valid virtual address
+    /// (see [`VirtAddr::new`]). Use [`VirtAddr::checked_sub`] for a non-panicking version.
     #[inline]
     fn sub(self, rhs: u64) -> Self::Output {
         VirtAddr::new(self.0.checked_sub(rhs).unwrap())
@@ -309,32 +452,96 @@ impl SubAssign<usize> for VirtAddr {
 
 impl Sub<VirtAddr> for VirtAddr {
     type Output = u64;
+    /// Returns the distance in bytes from `rhs` to `self`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `rhs` is greater than `self`. Use [`VirtAddr::checked_offset_from`] for a
+    /// non-panicking version.
     #[inline]
     fn sub(self, rhs: VirtAddr) -> Self::Output {
         self.as_u64().checked_sub(rhs.as_u64()).unwrap()
     }
 }
 
+/// Stepping skips the non-canonical hole of the address space, i.e. the address after
+/// `0x7fff_ffff_ffff` is `0xffff_8000_0000_0000`.
+impl VirtAddr {
+    /// Returns the index of the address in the address space with the hole removed.
+    #[inline]
+    const fn linear_index(self) -> u64 {
+        self.0 & ((1 << 48) - 1)
+    }
+
+    pub(crate) fn steps_between_u64(start: &Self, end: &Self) -> Option<u64> {
+        end.linear_index().checked_sub(start.linear_index())
+    }
+
+    pub(crate) fn forward_checked_u64(start: Self, count: u64) -> Option<Self> {
+        match start.linear_index().checked_add(count) {
+            Some(index) if index < (1 << 48) => Some(VirtAddr::new_truncate(index)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn backward_checked_u64(start: Self, count: u64) -> Option<Self> {
+        let index = start.linear_index().checked_sub(count)?;
+        Some(VirtAddr::new_truncate(index))
+    }
+}
+
+#[cfg(feature = "step_trait")]
+impl Step for VirtAddr {
+    #[inline]
+    fn steps_between(start: &Self, end: &Self) -> Option<usize> {
+        let steps = VirtAddr::steps_between_u64(start, end)?;
+        usize::try_from(steps).ok()
+    }
+
+    #[inline]
+    fn forward_checked(start: Self, count: usize) -> Option<Self> {
+        VirtAddr::forward_checked_u64(start, u64::try_from(count).ok()?)
+    }
+
+    #[inline]
+    fn backward_checked(start: Self, count: usize) -> Option<Self> {
+        VirtAddr::backward_checked_u64(start, u64::try_from(count).ok()?)
+    }
+}
+
 /// A passed `u64` was not a valid physical address.
 ///
 /// This means that bits 52 to 64 were not all null.
 #[derive(Debug)]
 pub struct PhysAddrNotValid(u64);
 
+/// A passed `u64` was not a physical address supported by the current CPU.
+///
+/// This means that the address has bits set that are above the physical address width
+/// reported by the CPU (see [`PhysAddr::implemented_bits`]).
+#[cfg(feature = "instructions")]
+#[derive(Debug)]
+pub struct PhysAddrTooLarge(u64);
+
+/// Caches the result of [`PhysAddr::implemented_bits`], 0 means not yet queried.
+#[cfg(feature = "instructions")]
+static IMPLEMENTED_PHYS_ADDR_BITS: AtomicU8 = AtomicU8::new(0);
+
 impl PhysAddr {
-    /// Creates a new physical address.
-    ///
-    /// ## Panics
-    ///
-    /// This function panics if a bit in the range 52 to 64 is set.
-    #[inline]
-    pub fn new(addr: u64) -> PhysAddr {
-        assert_eq!(
-            addr.get_bits(52..64),
-            0,
-            "physical addresses must not have any bits in the range 52 to 64 set"
-        );
-        PhysAddr(addr)
+    const_fn! {
+        /// Creates a new physical address.
+        ///
+        /// ## Panics
+        ///
+        /// This function panics if a bit in the range 52 to 64 is set.
+        #[inline]
+        pub fn new(addr: u64) -> PhysAddr {
+            assert!(
+                addr >> 52 == 0,
+                "physical addresses must not have any bits in the range 52 to 64 set"
+            );
+            PhysAddr(addr)
+        }
     }
 
     /// Creates a new physical address, throwing bits 52..64 away.
@@ -364,6 +571,45 @@ impl PhysAddr {
         }
     }
 
+    /// Tries to create a new physical address that is supported by the current CPU.
+    ///
+    /// Fails if any bits at or above [`PhysAddr::implemented_bits`] are set. Mapping such an
+    /// address in a page table entry sets reserved bits, which results in a page fault on
+    /// access.
+    #[cfg(feature = "instructions")]
+    #[inline]
+    pub fn try_new_checked(addr: u64) -> Result<PhysAddr, PhysAddrTooLarge> {
+        if addr >> Self::implemented_bits() == 0 {
+            Ok(PhysAddr(addr))
+        } else {
+            Err(PhysAddrTooLarge(addr))
+        }
+    }
+
+    /// Returns the number of physical address bits implemented by the current CPU
+    /// (`MAXPHYADDR`).
+    ///
+    /// The value is read from CPUID leaf 0x80000008 on the first call and cached afterwards.
+    /// If that leaf is not supported, 36 is returned, as specified by the Intel manual.
+    #[cfg(feature = "instructions")]
+    pub fn implemented_bits() -> u8 {
+        let cached = IMPLEMENTED_PHYS_ADDR_BITS.load(Ordering::Relaxed);
+        if cached != 0 {
+            return cached;
+        }
+
+        let max_extended_leaf = unsafe { core::arch::x86_64::__cpuid(0x8000_0000) }.eax;
+        let bits = if max_extended_leaf >= 0x8000_0008 {
+            // physical address width is reported in bits 0 to 8 of eax
+            let cpuid = unsafe { core::arch::x86_64::__cpuid(0x8000_0008) };
+            (cpuid.eax.get_bits(0..8) as u8).min(52)
+        } else {
+            36
+        };
+        IMPLEMENTED_PHYS_ADDR_BITS.store(bits, Ordering::Relaxed);
+        bits
+    }
+
     /// Creates a physical address that points to `0`.
     #[inline]
     pub const fn zero() -> PhysAddr {
@@ -385,17 +631,26 @@ impl PhysAddr {
     /// Aligns the physical address upwards to the given alignment.
     ///
     /// See the `align_up` function for more information.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two or if the aligned address would have a bit
+    /// in the range 52 to 64 set.
     #[inline]
     pub fn align_up<U>(self, align: U) -> Self
     where
         U: Into<u64>,
     {
-        PhysAddr(align_up(self.0, align.into()))
+        PhysAddr::new(align_up(self.0, align.into()))
     }
 
     /// Aligns the physical address downwards to the given alignment.
     ///
     /// See the `align_down` function for more information.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two.
     #[inline]
     pub fn align_down<U>(self, align: U) -> Self
     where
@@ -404,6 +659,29 @@ impl PhysAddr {
         PhysAddr(align_down(self.0, align.into()))
     }
 
+    /// Aligns the physical address upwards to the given alignment.
+    ///
+    /// Returns `None` if `align` is not a power of two or if the aligned address would have
+    /// a bit in the range 52 to 64 set.
+    #[inline]
+    pub fn checked_align_up<U>(self, align: U) -> Option<Self>
+    where
+        U: Into<u64>,
+    {
+        checked_align_up(self.0, align.into()).and_then(|addr| PhysAddr::try_new(addr).ok())
+    }
+
+    /// Aligns the physical address downwards to the given alignment.
+    ///
+    /// Returns `None` if `align` is not a power of two.
+    #[inline]
+    pub fn checked_align_down<U>(self, align: U) -> Option<Self>
+    where
+        U: Into<u64>,
+    {
+        checked_align_down(self.0, align.into()).map(PhysAddr)
+    }
+
     /// Checks whether the physical address has the demanded alignment.
     #[inline]
     pub fn is_aligned<U>(self, align: U) -> bool
@@ -412,8 +690,43 @@ impl PhysAddr {
     {
         self.align_down(align) == self
     }
+
+    /// Checked addition. Computes `self + rhs`, returning `None` if the addition overflows
+    /// or the result has a bit in the range 52 to 64 set.
+    #[inline]
+    pub fn checked_add(self, rhs: u64) -> Option<Self> {
+        self.0
+            .checked_add(rhs)
+            .and_then(|addr| PhysAddr::try_new(addr).ok())
+    }
+
+    /// Checked subtraction. Computes `self - rhs`, returning `None` if the subtraction
+    /// underflows.
+    #[inline]
+    pub fn checked_sub(self, rhs: u64) -> Option<Self> {
+        self.0.checked_sub(rhs).map(PhysAddr)
+    }
+
+    /// Returns the signed distance in bytes from `origin` to `self`, i.e. `self - origin`.
+    ///
+    /// Since physical addresses are at most 52 bits wide, the distance always fits into an
+    /// `i64`. The `Option` return type exists for consistency with
+    /// [`VirtAddr::checked_offset_from`].
+    #[inline]
+    pub fn checked_offset_from(self, origin: PhysAddr) -> Option<i64> {
+        i64::try_from(i128::from(self.0) - i128::from(origin.0)).ok()
+    }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    PhysAddr,
+    u64,
+    "a physical address below 2^52",
+    |addr| addr.0,
+    |addr| PhysAddr::try_new(addr).ok()
+);
+
 impl fmt::Debug for PhysAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("PhysAddr")
@@ -459,9 +772,15 @@ impl fmt::Pointer for PhysAddr {
 
 impl Add<u64> for PhysAddr {
     type Output = Self;
+    /// Adds `rhs` to the address.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the addition overflows or if the result has a bit in the range 52 to 64 set.
+    /// Use [`PhysAddr::checked_add`] for a non-panicking version.
     #[inline]
     fn add(self, rhs: u64) -> Self::Output {
-        PhysAddr::new(self.0 + rhs)
+        PhysAddr::new(self.0.checked_add(rhs).unwrap())
     }
 }
 
@@ -491,6 +810,12 @@ impl AddAssign<usize> for PhysAddr {
 
 impl Sub<u64> for PhysAddr {
     type Output = Self;
+    /// Subtracts `rhs` from the address.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the subtraction underflows. Use [`PhysAddr::checked_sub`] for a
+    /// non-panicking version.
     #[inline]
     fn sub(self, rhs: u64) -> Self::Output {
         PhysAddr::new(self.0.checked_sub(rhs).unwrap())
@@ -523,34 +848,104 @@ impl SubAssign<usize> for PhysAddr {
 
 impl Sub<PhysAddr> for PhysAddr {
     type Output = u64;
+    /// Returns the distance in bytes from `rhs` to `self`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `rhs` is greater than `self`. Use [`PhysAddr::checked_offset_from`] for a
+    /// non-panicking version.
     #[inline]
     fn sub(self, rhs: PhysAddr) -> Self::Output {
         self.as_u64().checked_sub(rhs.as_u64()).unwrap()
     }
 }
 
-/// Align address downwards.
-///
-/// Returns the greatest x with alignment `align` so that x <= addr. The alignment must be
-///  a power of 2.
+/// Stepping stops at the 52-bit physical address limit.
+#[cfg(feature = "step_trait")]
+impl Step for PhysAddr {
+    #[inline]
+    fn steps_between(start: &Self, end: &Self) -> Option<usize> {
+        let steps = end.0.checked_sub(start.0)?;
+        usize::try_from(steps).ok()
+    }
+
+    #[inline]
+    fn forward_checked(start: Self, count: usize) -> Option<Self> {
+        start.checked_add(u64::try_from(count).ok()?)
+    }
+
+    #[inline]
+    fn backward_checked(start: Self, count: usize) -> Option<Self> {
+        start.checked_sub(u64::try_from(count).ok()?)
+    }
+}
+
+/// Returns whether bits 48 to 64 of the given address are copies of bit 47.
 #[inline]
-pub fn align_down(addr: u64, align: u64) -> u64 {
-    assert!(align.is_power_of_two(), "`align` must be a power of two");
-    addr & !(align - 1)
+const fn is_canonical(addr: u64) -> bool {
+    let high = addr >> 47;
+    high == 0 || high == 0x1ffff
+}
+
+const_fn! {
+    /// Align address downwards.
+    ///
+    /// Returns the greatest x with alignment `align` so that x <= addr. The alignment must be
+    ///  a power of 2.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    #[inline]
+    pub fn align_down(addr: u64, align: u64) -> u64 {
+        assert!(align.is_power_of_two(), "`align` must be a power of two");
+        addr & !(align - 1)
+    }
 }
 
 /// Align address upwards.
 ///
 /// Returns the smallest x with alignment `align` so that x >= addr. The alignment must be
 /// a power of 2.
+///
+/// ## Panics
+///
+/// Panics if `align` is not a power of two or if the result would not fit into an `u64`.
+/// The overflow check is performed in both debug and release builds, so the result never
+/// wraps around to zero. Use [`checked_align_up`] for a non-panicking version.
 #[inline]
 pub fn align_up(addr: u64, align: u64) -> u64 {
     assert!(align.is_power_of_two(), "`align` must be a power of two");
+    checked_align_up(addr, align).expect("aligning the address upwards overflowed")
+}
+
+/// Align address downwards.
+///
+/// Returns the greatest x with alignment `align` so that x <= addr, or `None` if the
+/// alignment is not a power of 2.
+#[inline]
+pub fn checked_align_down(addr: u64, align: u64) -> Option<u64> {
+    if align.is_power_of_two() {
+        Some(addr & !(align - 1))
+    } else {
+        None
+    }
+}
+
+/// Align address upwards.
+///
+/// Returns the smallest x with alignment `align` so that x >= addr, or `None` if the
+/// alignment is not a power of 2 or if the result would not fit into an `u64`.
+#[inline]
+pub fn checked_align_up(addr: u64, align: u64) -> Option<u64> {
+    if !align.is_power_of_two() {
+        return None;
+    }
     let align_mask = align - 1;
     if addr & align_mask == 0 {
-        addr // already aligned
+        Some(addr) // already aligned
     } else {
-        (addr | align_mask) + 1
+        (addr | align_mask).checked_add(1)
     }
 }
 
@@ -558,6 +953,33 @@ pub fn align_up(addr: u64, align: u64) -> u64 {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_addr_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        assert_tokens(&VirtAddr::new(0x1000), &[Token::U64(0x1000)]);
+        assert_tokens(
+            &VirtAddr::new(0xffff_8000_0000_0000),
+            &[Token::U64(0xffff_8000_0000_0000)],
+        );
+        // would be sign extended by `VirtAddr::try_new`
+        assert_de_tokens_error::<VirtAddr>(
+            &[Token::U64(0x8000_0000_0000)],
+            "invalid value: integer `140737488355328`, expected a canonical virtual address",
+        );
+        assert_de_tokens_error::<VirtAddr>(
+            &[Token::U64(0x1_0000_0000_0000)],
+            "invalid value: integer `281474976710656`, expected a canonical virtual address",
+        );
+
+        assert_tokens(&PhysAddr::new(0x1000), &[Token::U64(0x1000)]);
+        assert_de_tokens_error::<PhysAddr>(
+            &[Token::U64(1 << 52)],
+            "invalid value: integer `4503599627370496`, expected a physical address below 2^52",
+        );
+    }
+
     #[test]
     pub fn virtaddr_new_truncate() {
         assert_eq!(VirtAddr::new_truncate(0), VirtAddr(0));
@@ -566,6 +988,103 @@ mod tests {
         assert_eq!(VirtAddr::new_truncate(123 << 47), VirtAddr(0xfffff << 47));
     }
 
+    #[test]
+    pub fn virtaddr_checked_arithmetic() {
+        let addr = VirtAddr::new(0x1000);
+        assert_eq!(addr.checked_add(0x1000), Some(VirtAddr(0x2000)));
+        assert_eq!(addr.checked_sub(0x1000), Some(VirtAddr(0)));
+        assert_eq!(addr.checked_sub(0x1001), None);
+
+        // crossing into the non-canonical hole
+        let top_of_lower_half = VirtAddr::new(0x7fff_ffff_f000);
+        assert_eq!(
+            top_of_lower_half.checked_add(0xfff),
+            Some(VirtAddr(0x7fff_ffff_ffff))
+        );
+        assert_eq!(top_of_lower_half.checked_add(0x1000), None);
+        let bottom_of_higher_half = VirtAddr::new(0xffff_8000_0000_0000);
+        assert_eq!(bottom_of_higher_half.checked_sub(1), None);
+        assert_eq!(
+            VirtAddr::new(0xffff_ffff_ffff_f000).checked_add(0x1000),
+            None
+        );
+
+        assert_eq!(
+            addr.checked_offset_from(VirtAddr::new(0x3000)),
+            Some(-0x2000)
+        );
+        assert_eq!(
+            bottom_of_higher_half.checked_offset_from(VirtAddr::zero()),
+            None
+        );
+        assert_eq!(
+            VirtAddr::new(0x7fff_ffff_ffff).checked_offset_from(VirtAddr::zero()),
+            Some(0x7fff_ffff_ffff)
+        );
+    }
+
+    #[test]
+    pub fn virtaddr_pointer_conversions() {
+        let value = [1u8, 2, 3];
+        let slice: &[u8] = &value;
+        let addr = VirtAddr::from_ref(slice);
+        assert_eq!(addr, VirtAddr::from_ptr(value.as_ptr()));
+        assert_eq!(addr, VirtAddr::from_non_null(NonNull::from(slice)));
+        assert_eq!(
+            addr.as_non_null_ptr::<u8>().map(NonNull::as_ptr),
+            Some(value.as_ptr() as *mut u8)
+        );
+        assert_eq!(VirtAddr::zero().as_non_null_ptr::<u8>(), None);
+    }
+
+    #[test]
+    pub fn physaddr_checked_arithmetic() {
+        let addr = PhysAddr::new(0x1000);
+        assert_eq!(addr.checked_add(0x1000), Some(PhysAddr(0x2000)));
+        assert_eq!(addr.checked_sub(0x1001), None);
+        assert_eq!(PhysAddr::new(0x000f_ffff_ffff_ffff).checked_add(1), None);
+        assert_eq!(
+            addr.checked_offset_from(PhysAddr::new(0x3000)),
+            Some(-0x2000)
+        );
+    }
+
+    #[cfg(feature = "step_trait")]
+    #[test]
+    pub fn physaddr_step() {
+        let start = PhysAddr::new(0x1000);
+        assert_eq!((start..start + 0x10u64).count(), 0x10);
+        assert_eq!((start..=start).last(), Some(start));
+
+        let last = PhysAddr::new(0x000f_ffff_ffff_ffff);
+        assert_eq!(Step::forward_checked(last, 1), None);
+        assert_eq!(Step::backward_checked(PhysAddr::zero(), 1), None);
+        assert_eq!(Step::forward_checked(start, 0x10), Some(start + 0x10u64));
+    }
+
+    #[test]
+    pub fn addr_hex_formatting() {
+        let virt = VirtAddr::new(0xffff_8000_dead_beef);
+        assert_eq!(format!("{:#x}", virt), "0xffff8000deadbeef");
+        assert_eq!(format!("{:X}", virt), "FFFF8000DEADBEEF");
+        assert_eq!(format!("{:?}", virt), "VirtAddr(0xffff8000deadbeef)");
+
+        let phys = PhysAddr::new(0xcafe_b000);
+        assert_eq!(format!("{:#x}", phys), "0xcafeb000");
+        assert_eq!(format!("{:#X}", phys), "0xCAFEB000");
+        assert_eq!(format!("{:?}", phys), "PhysAddr(0xcafeb000)");
+    }
+
+    #[cfg(feature = "instructions")]
+    #[test]
+    pub fn physaddr_try_new_checked() {
+        let bits = PhysAddr::implemented_bits();
+        assert!((32..=52).contains(&bits));
+        assert_eq!(PhysAddr::implemented_bits(), bits);
+        assert!(PhysAddr::try_new_checked((1 << bits) - 1).is_ok());
+        assert!(PhysAddr::try_new_checked(1 << bits).is_err());
+    }
+
     #[test]
     pub fn test_align_up() {
         // align 1
@@ -582,4 +1101,73 @@ mod tests {
         assert_eq!(align_up(0, 2), 0);
         assert_eq!(align_up(0, 0x8000_0000_0000_0000), 0);
     }
+
+    #[test]
+    #[should_panic]
+    pub fn test_align_up_overflow() {
+        align_up(0xffff_ffff_ffff_ffff, 2);
+    }
+
+    #[test]
+    pub fn test_checked_align() {
+        // align 1
+        assert_eq!(
+            checked_align_up(0xffff_ffff_ffff_ffff, 1),
+            Some(0xffff_ffff_ffff_ffff)
+        );
+        assert_eq!(
+            checked_align_down(0xffff_ffff_ffff_ffff, 1),
+            Some(0xffff_ffff_ffff_ffff)
+        );
+        // overflow
+        assert_eq!(checked_align_up(0xffff_ffff_ffff_ffff, 2), None);
+        assert_eq!(checked_align_up(0xffff_ffff_ffff_f001, 0x1000), None);
+        assert_eq!(
+            checked_align_down(0xffff_ffff_ffff_ffff, 0x1000),
+            Some(0xffff_ffff_ffff_f000)
+        );
+        // invalid alignment
+        assert_eq!(checked_align_up(0x1000, 3), None);
+        assert_eq!(checked_align_down(0x1000, 0), None);
+    }
+
+    #[test]
+    pub fn virtaddr_checked_align() {
+        let top_of_lower_half = VirtAddr::new(0x7fff_ffff_ffff);
+        assert_eq!(
+            top_of_lower_half.checked_align_up(1u64),
+            Some(top_of_lower_half)
+        );
+        assert_eq!(top_of_lower_half.checked_align_up(0x1000u64), None);
+        assert_eq!(
+            top_of_lower_half.checked_align_down(0x1000u64),
+            Some(VirtAddr::new(0x7fff_ffff_f000))
+        );
+        let bottom_of_higher_half = VirtAddr::new(0xffff_8000_0000_0001);
+        assert_eq!(
+            bottom_of_higher_half.checked_align_down(0x1000u64),
+            Some(VirtAddr::new(0xffff_8000_0000_0000))
+        );
+        assert_eq!(bottom_of_higher_half.checked_align_down(1u64 << 48), None);
+        assert_eq!(
+            VirtAddr::new(0xffff_ffff_ffff_ffff).checked_align_up(2u64),
+            None
+        );
+        // the panicking version stays canonical
+        assert_eq!(
+            top_of_lower_half.align_up(0x1000u64),
+            VirtAddr::new(0xffff_8000_0000_0000)
+        );
+    }
+
+    #[test]
+    pub fn physaddr_checked_align() {
+        let addr = PhysAddr::new(0x000f_ffff_ffff_f001);
+        assert_eq!(addr.checked_align_up(1u64), Some(addr));
+        assert_eq!(addr.checked_align_up(0x1000u64), None);
+        assert_eq!(
+            addr.checked_align_down(0x1000u64),
+            Some(PhysAddr::new(0x000f_ffff_ffff_f000))
+        );
+    }
 }
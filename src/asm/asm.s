@@ -20,6 +20,14 @@ _x86_64_asm_interrupt_enable_and_hlt:
     hlt
     retq
 
+.global _x86_64_asm_interrupt_enable_and_nop
+.p2align 4
+_x86_64_asm_interrupt_enable_and_nop:
+    sti
+    nop
+    cli
+    retq
+
 .global _x86_64_asm_int3
 .p2align 4
 _x86_64_asm_int3:
@@ -88,12 +96,50 @@ _x86_64_asm_get_cs:
     mov %cs, %ax
     retq
 
+.global _x86_64_asm_get_ss
+.p2align 4
+_x86_64_asm_get_ss:
+    mov %ss, %ax
+    retq
+
+.global _x86_64_asm_get_ds
+.p2align 4
+_x86_64_asm_get_ds:
+    mov %ds, %ax
+    retq
+
+.global _x86_64_asm_get_es
+.p2align 4
+_x86_64_asm_get_es:
+    mov %es, %ax
+    retq
+
+.global _x86_64_asm_get_fs
+.p2align 4
+_x86_64_asm_get_fs:
+    mov %fs, %ax
+    retq
+
+.global _x86_64_asm_get_gs
+.p2align 4
+_x86_64_asm_get_gs:
+    mov %gs, %ax
+    retq
+
 .global _x86_64_asm_invlpg
 .p2align 4
 _x86_64_asm_invlpg:
     invlpg (%rdi)
     retq
 
+.global _x86_64_asm_invlpga
+.p2align 4
+_x86_64_asm_invlpga:
+    mov %rdi, %rax
+    mov %esi, %ecx
+    invlpga %rax, %ecx
+    retq
+
 .global _x86_64_asm_invpcid
 .p2align 4
 _x86_64_asm_invpcid:
@@ -119,6 +165,18 @@ _x86_64_asm_lidt:
     lidt (%rdi)
     retq
 
+.global _x86_64_asm_sgdt
+.p2align 4
+_x86_64_asm_sgdt:
+    sgdt (%rdi)
+    retq
+
+.global _x86_64_asm_sidt
+.p2align 4
+_x86_64_asm_sidt:
+    sidt (%rdi)
+    retq
+
 .global _x86_64_asm_write_rflags
 .p2align 4
 _x86_64_asm_write_rflags:
@@ -246,6 +304,28 @@ _x86_64_asm_nop:
     nop
     retq
 
+.global _x86_64_asm_read_rip
+.p2align 4
+_x86_64_asm_read_rip:
+    mov    (%rsp),%rax  # return address
+    retq
+
+.global _x86_64_asm_read_rsp
+.p2align 4
+_x86_64_asm_read_rsp:
+    lea    8(%rsp),%rax # skip return address
+    retq
+
+.global _x86_64_asm_rdpru
+.p2align 4
+_x86_64_asm_rdpru:
+    mov    %edi,%ecx
+    .byte  0x0f,0x01,0xfd  # rdpru
+    shl    $0x20,%rdx   # shift edx to upper 32bit
+    mov    %eax,%eax    # clear upper 32bit of rax
+    or     %rdx,%rax    # or with rdx
+    retq
+
 .global _x86_64_asm_rdfsbase
 .p2align 4
 _x86_64_asm_rdfsbase:
@@ -269,3 +349,19 @@ _x86_64_asm_rdgsbase:
 _x86_64_asm_wrgsbase:
     wrgsbase %rdi
     retq
+
+.global _x86_64_asm_rdpkru
+.p2align 4
+_x86_64_asm_rdpkru:
+    xorl %ecx, %ecx
+    rdpkru
+    retq
+
+.global _x86_64_asm_wrpkru
+.p2align 4
+_x86_64_asm_wrpkru:
+    movl %edi, %eax
+    xorl %ecx, %ecx
+    xorl %edx, %edx
+    wrpkru
+    retq
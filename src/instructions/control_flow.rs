@@ -0,0 +1,31 @@
+//! Instructions for the control-flow enforcement technology (CET).
+//!
+//! With indirect branch tracking enabled, every target of an indirect call or jump must start
+//! with an `endbr64` (or `endbr32`) instruction, otherwise the CPU raises a control protection
+//! exception (`#CP`). On CPUs without CET or with indirect branch tracking disabled, these
+//! instructions are executed as a `nop`, so they are always safe to execute.
+//!
+//! The instructions only have an effect if they are placed at the branch target, so they are
+//! only available with the `inline_asm` feature and are always inlined. Note that the compiler
+//! might still place other instructions (e.g. a function prologue) before them, so they are
+//! mainly useful at the start of naked functions or other code without a prologue.
+
+/// Emits the `endbr64` instruction, which marks a valid target for indirect branches in
+/// 64-bit mode.
+#[cfg(feature = "inline_asm")]
+#[inline(always)]
+pub fn endbr64() {
+    unsafe {
+        asm!("endbr64", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Emits the `endbr32` instruction, which marks a valid target for indirect branches in
+/// 32-bit and compatibility mode.
+#[cfg(feature = "inline_asm")]
+#[inline(always)]
+pub fn endbr32() {
+    unsafe {
+        asm!("endbr32", options(nomem, nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,115 @@
+//! Detection of the hypervisor that the CPU runs under, through the CPUID leaves that
+//! hypervisors reserve for themselves.
+
+use core::arch::x86_64::{__cpuid, CpuidResult};
+
+/// The first CPUID leaf of the range that is reserved for hypervisors.
+const HYPERVISOR_BASE_LEAF: u32 = 0x4000_0000;
+
+/// A hypervisor, identified by the vendor signature of CPUID leaf `0x4000_0000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HypervisorVendor {
+    /// The Linux Kernel-based Virtual Machine (`"KVMKVMKVM\0\0\0"`).
+    Kvm,
+    /// Microsoft Hyper-V or a hypervisor that emulates its interface (`"Microsoft Hv"`).
+    HyperV,
+    /// The Xen hypervisor (`"XenVMMXenVMM"`).
+    Xen,
+    /// VMware (`"VMwareVMware"`).
+    VMware,
+    /// A hypervisor with a different signature.
+    Unknown([u8; 12]),
+}
+
+impl HypervisorVendor {
+    /// Identifies the hypervisor by the signature that is stored in the `ebx`, `ecx` and
+    /// `edx` registers of CPUID leaf `0x4000_0000`, in that order.
+    pub fn from_signature(signature: [u8; 12]) -> Self {
+        match &signature {
+            b"KVMKVMKVM\0\0\0" => HypervisorVendor::Kvm,
+            b"Microsoft Hv" => HypervisorVendor::HyperV,
+            b"XenVMMXenVMM" => HypervisorVendor::Xen,
+            b"VMwareVMware" => HypervisorVendor::VMware,
+            _ => HypervisorVendor::Unknown(signature),
+        }
+    }
+}
+
+/// Returns whether the CPU runs under a hypervisor, as reported by the hypervisor bit of
+/// CPUID leaf 1 (bit 31 of `ecx`).
+///
+/// The bit is set by the hypervisor, so it can't be used to detect hypervisors that want to
+/// hide from the guest.
+pub fn is_virtualized() -> bool {
+    cpuid(1).ecx & (1 << 31) != 0
+}
+
+/// Returns the highest hypervisor CPUID leaf, or 0 if the CPU doesn't run under a hypervisor.
+///
+/// Some KVM versions report 0 instead of a leaf, which is interpreted as `0x4000_0001`, as
+/// recommended by the KVM documentation.
+pub fn max_hypervisor_leaf() -> u32 {
+    if !is_virtualized() {
+        return 0;
+    }
+    let max_leaf = cpuid(HYPERVISOR_BASE_LEAF).eax;
+    if max_leaf < HYPERVISOR_BASE_LEAF {
+        HYPERVISOR_BASE_LEAF + 1
+    } else {
+        max_leaf
+    }
+}
+
+/// Returns the vendor of the hypervisor, or `None` if the CPU doesn't run under a hypervisor.
+pub fn vendor() -> Option<HypervisorVendor> {
+    if !is_virtualized() {
+        return None;
+    }
+    let leaf = cpuid(HYPERVISOR_BASE_LEAF);
+    let mut signature = [0; 12];
+    signature[0..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    signature[4..8].copy_from_slice(&leaf.ecx.to_le_bytes());
+    signature[8..12].copy_from_slice(&leaf.edx.to_le_bytes());
+    Some(HypervisorVendor::from_signature(signature))
+}
+
+/// Reads the given hypervisor CPUID leaf, e.g. `0x4000_0001` for the KVM feature bits.
+///
+/// Returns `None` if the leaf is outside of the range from `0x4000_0000` to
+/// [`max_hypervisor_leaf`], i.e. also if the CPU doesn't run under a hypervisor. The meaning
+/// of the leaves depends on the [`vendor`].
+pub fn read_leaf(leaf: u32) -> Option<CpuidResult> {
+    if (HYPERVISOR_BASE_LEAF..=max_hypervisor_leaf()).contains(&leaf) {
+        Some(cpuid(leaf))
+    } else {
+        None
+    }
+}
+
+// `__cpuid` is only safe on newer compilers
+#[allow(unused_unsafe)]
+fn cpuid(leaf: u32) -> CpuidResult {
+    // SAFETY: CPUID is available on all x86_64 CPUs
+    unsafe { __cpuid(leaf) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vendor() {
+        assert_eq!(
+            HypervisorVendor::from_signature(*b"KVMKVMKVM\0\0\0"),
+            HypervisorVendor::Kvm
+        );
+        assert_eq!(
+            HypervisorVendor::from_signature(*b"TCGTCGTCGTCG"),
+            HypervisorVendor::Unknown(*b"TCGTCGTCGTCG")
+        );
+
+        assert_eq!(vendor().is_some(), is_virtualized());
+        assert_eq!(read_leaf(0x4000_0000).is_some(), is_virtualized());
+        assert_eq!(read_leaf(1), None);
+    }
+}
@@ -1,5 +1,8 @@
 //! Enabling and disabling interrupts
 
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 /// Returns whether interrupts are enabled.
 #[inline]
 pub fn are_enabled() -> bool {
@@ -8,6 +11,107 @@ pub fn are_enabled() -> bool {
     rflags::read().contains(RFlags::INTERRUPT_FLAG)
 }
 
+/// Panics if interrupts are disabled, in debug builds only.
+///
+/// This is intended for code that waits for something that only an interrupt can deliver,
+/// which deadlocks if it is called from within [`without_interrupts`]. The panic message
+/// contains the location of the caller. With the `interrupt_debug` feature, it additionally
+/// contains the nesting depth of `without_interrupts` and the location of the outermost call.
+///
+/// In release builds, this function does nothing.
+#[inline]
+#[track_caller]
+pub fn assert_enabled() {
+    if cfg!(debug_assertions) && !are_enabled() {
+        interrupt_state_panic("interrupts are disabled")
+    }
+}
+
+/// Panics if interrupts are enabled, in debug builds only.
+///
+/// This is the counterpart of [`assert_enabled`] for code that must not be interrupted.
+///
+/// In release builds, this function does nothing.
+#[inline]
+#[track_caller]
+pub fn assert_disabled() {
+    if cfg!(debug_assertions) && are_enabled() {
+        interrupt_state_panic("interrupts are enabled")
+    }
+}
+
+#[cold]
+#[track_caller]
+fn interrupt_state_panic(message: &str) -> ! {
+    #[cfg(feature = "interrupt_debug")]
+    match debug::outermost() {
+        Some(location) => panic!(
+            "{} (without_interrupts depth {}, outermost call at {})",
+            message,
+            debug::depth(),
+            location
+        ),
+        None => panic!("{} (without_interrupts depth 0)", message),
+    }
+
+    #[cfg(not(feature = "interrupt_debug"))]
+    panic!("{}", message)
+}
+
+/// Returns the current nesting depth of [`without_interrupts`] calls.
+///
+/// The depth is counted for all CPUs together, so it is only meaningful if a single CPU
+/// uses `without_interrupts` at a time, e.g. before the other CPUs are started.
+#[cfg(feature = "interrupt_debug")]
+#[inline]
+pub fn without_interrupts_depth() -> usize {
+    debug::depth()
+}
+
+/// The bookkeeping of the `interrupt_debug` feature.
+#[cfg(feature = "interrupt_debug")]
+mod debug {
+    use core::panic::Location;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+    static DEPTH: AtomicUsize = AtomicUsize::new(0);
+    static OUTERMOST: AtomicPtr<Location<'static>> = AtomicPtr::new(ptr::null_mut());
+
+    /// Tracks a `without_interrupts` call until it is dropped.
+    pub(super) struct DepthGuard;
+
+    impl DepthGuard {
+        #[inline]
+        #[track_caller]
+        pub(super) fn enter() -> Self {
+            if DEPTH.fetch_add(1, Ordering::Relaxed) == 0 {
+                let location: *const Location<'static> = Location::caller();
+                OUTERMOST.store(location as *mut _, Ordering::Relaxed);
+            }
+            DepthGuard
+        }
+    }
+
+    impl Drop for DepthGuard {
+        #[inline]
+        fn drop(&mut self) {
+            if DEPTH.fetch_sub(1, Ordering::Relaxed) == 1 {
+                OUTERMOST.store(ptr::null_mut(), Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(super) fn depth() -> usize {
+        DEPTH.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn outermost() -> Option<&'static Location<'static>> {
+        // SAFETY: the pointer is either null or comes from a `&'static Location`
+        unsafe { OUTERMOST.load(Ordering::Relaxed).as_ref() }
+    }
+}
+
 /// Enable interrupts.
 ///
 /// This is a wrapper around the `sti` instruction.
@@ -45,6 +149,12 @@ pub fn disable() {
 /// Afterwards, interrupts are enabling again if they were enabled before.
 ///
 /// If you have other `enable` and `disable` calls _within_ the closure, things may not work as expected.
+/// Use [`push_cli`] and [`pop_cli`] if critical sections need to nest reliably.
+///
+/// Interrupts are also restored if the closure unwinds, e.g. because of a failing assertion
+/// in a test harness with `panic = "unwind"`. Use [`disable_guarded`] instead if the critical
+/// section doesn't fit into a closure. For closures that return a `Result`,
+/// [`try_without_interrupts`] avoids annotating the error type.
 ///
 /// # Examples
 ///
@@ -60,28 +170,251 @@ pub fn disable() {
 /// // interrupts are enabled again
 /// ```
 #[inline]
+#[track_caller]
 pub fn without_interrupts<F, R>(f: F) -> R
 where
     F: FnOnce() -> R,
 {
-    // true if the interrupt flag is set (i.e. interrupts are enabled)
-    let saved_intpt_flag = are_enabled();
+    // the guard re-enables interrupts if they were enabled before, even if `f` unwinds
+    let _guard = disable_guarded();
+    #[cfg(feature = "interrupt_debug")]
+    let _depth = debug::DepthGuard::enter();
+    f()
+}
 
-    // if interrupts are enabled, disable them for now
-    if saved_intpt_flag {
+/// Runs a fallible closure with disabled interrupts.
+///
+/// This is [`without_interrupts`] for closures that return a `Result`, so `?` can be used
+/// inside the closure without annotating its return type. Interrupts are restored before
+/// the result is returned, regardless of whether it is an error.
+///
+/// # Examples
+///
+/// ```ignore
+/// fn pop_event(queue: &mut Queue) -> Result<Event, QueueError> {
+///     let event = try_without_interrupts(|| {
+///         let event = queue.pop()?;
+///         queue.acknowledge(&event)?;
+///         Ok(event)
+///     })?;
+///     // interrupts are enabled again
+///     Ok(event)
+/// }
+/// ```
+#[inline]
+#[track_caller]
+pub fn try_without_interrupts<F, T, E>(f: F) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    without_interrupts(f)
+}
+
+/// Disables interrupts until the returned guard is dropped.
+///
+/// Interrupts are disabled (if they aren't already disabled) and the guard re-enables them
+/// on drop if they were enabled before. This is an alternative to [`without_interrupts`] for
+/// critical sections that don't fit into a closure, e.g. ones with early returns.
+///
+/// As with `without_interrupts`, calling `enable` or `disable` while the guard is alive may
+/// not work as expected. Guards should be dropped in the reverse order of their creation.
+///
+/// # Examples
+///
+/// ```ignore
+/// // interrupts are enabled
+/// let guard = disable_guarded();
+/// // interrupts are disabled
+/// assert!(guard.were_enabled());
+/// drop(guard);
+/// // interrupts are enabled again
+/// ```
+#[inline]
+pub fn disable_guarded() -> InterruptGuard {
+    let were_enabled = are_enabled();
+    if were_enabled {
         disable();
     }
+    InterruptGuard {
+        were_enabled,
+        _not_send: PhantomData,
+    }
+}
+
+/// Keeps interrupts disabled while it is alive, created by [`disable_guarded`].
+///
+/// The guard is neither `Send` nor `Sync`, since the interrupt flag is specific to the
+/// current CPU.
+#[derive(Debug)]
+#[must_use = "interrupts are re-enabled when the guard is dropped"]
+pub struct InterruptGuard {
+    were_enabled: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl InterruptGuard {
+    /// Returns whether interrupts were enabled before the guard was created.
+    #[inline]
+    pub fn were_enabled(&self) -> bool {
+        self.were_enabled
+    }
+}
 
-    // do `f` while interrupts are disabled
-    let ret = f();
+impl Drop for InterruptGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.were_enabled {
+            enable();
+        }
+    }
+}
 
-    // re-enable interrupts if they were previously enabled
-    if saved_intpt_flag {
+/// Per-CPU nesting state for [`push_cli`] and [`pop_cli`].
+///
+/// The crate has no per-CPU storage of its own, so each CPU needs its own counter stored by
+/// the caller, e.g. in a per-CPU data structure reachable through the `gs` segment.
+#[derive(Debug, Default)]
+pub struct DisableCounter {
+    depth: usize,
+    were_enabled: bool,
+}
+
+impl DisableCounter {
+    /// Creates a new counter with no active `push_cli` calls.
+    #[inline]
+    pub const fn new() -> Self {
+        DisableCounter {
+            depth: 0,
+            were_enabled: false,
+        }
+    }
+
+    /// Returns the number of `push_cli` calls that were not yet matched by a `pop_cli`.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// Disables interrupts and increments the nesting depth of the given counter.
+///
+/// Every call must be matched by a call to [`pop_cli`] with the same counter. Interrupts are
+/// only re-enabled by the outermost `pop_cli`, and only if they were enabled before the
+/// outermost `push_cli`. In contrast to [`without_interrupts`], this stays correct when
+/// critical sections are nested across different code paths, as long as all of them use
+/// `push_cli`/`pop_cli` instead of calling `enable` directly.
+///
+/// The counter must belong to the current CPU.
+#[inline]
+pub fn push_cli(counter: &mut DisableCounter) {
+    let were_enabled = are_enabled();
+    disable();
+    if counter.depth == 0 {
+        counter.were_enabled = were_enabled;
+    }
+    counter.depth += 1;
+}
+
+/// Decrements the nesting depth of the given counter and re-enables interrupts when it
+/// reaches zero, if they were enabled before the matching [`push_cli`].
+///
+/// ## Panics
+///
+/// Panics if there is no matching `push_cli` call, i.e. if the depth is already zero.
+#[inline]
+pub fn pop_cli(counter: &mut DisableCounter) {
+    debug_assert!(
+        !are_enabled(),
+        "interrupts were enabled inside push_cli section"
+    );
+    assert!(
+        counter.depth > 0,
+        "pop_cli called without a matching push_cli"
+    );
+    counter.depth -= 1;
+    if counter.depth == 0 && counter.were_enabled {
         enable();
     }
+}
+
+/// Per-CPU interrupt nesting counter for tracking whether code runs in interrupt context.
+///
+/// The CPU has no flag that tells whether it is currently executing an interrupt handler,
+/// so handlers opt in by calling [`enter`](InInterrupt::enter) on entry and
+/// [`leave`](InInterrupt::leave) before returning. Other code can then use
+/// [`in_interrupt`](InInterrupt::in_interrupt) to decide e.g. whether it may sleep.
+///
+/// As with [`DisableCounter`], the crate has no per-CPU storage of its own, so each CPU needs
+/// its own counter stored by the caller. The counter only needs a shared reference, so it can
+/// also be placed in a `static` on single-core systems:
+///
+/// ```ignore
+/// static IN_INTERRUPT: InInterrupt = InInterrupt::new();
+///
+/// extern "x86-interrupt" fn timer_handler(_stack_frame: InterruptStackFrame) {
+///     IN_INTERRUPT.enter();
+///     // ...
+///     IN_INTERRUPT.leave();
+/// }
+///
+/// fn maybe_sleep() {
+///     if !IN_INTERRUPT.in_interrupt() {
+///         // sleeping is allowed here
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct InInterrupt {
+    depth: AtomicUsize,
+}
+
+impl InInterrupt {
+    /// Creates a new counter for a CPU that is not executing an interrupt handler.
+    #[inline]
+    pub const fn new() -> Self {
+        InInterrupt {
+            depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records that an interrupt handler was entered on the current CPU.
+    ///
+    /// Every call must be matched by a call to [`leave`](InInterrupt::leave).
+    #[inline]
+    pub fn enter(&self) {
+        self.depth.fetch_add(1, Ordering::Relaxed);
+    }
 
-    // return the result of `f` to the caller
-    ret
+    /// Records that the innermost interrupt handler is about to return.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if there is no matching `enter` call, i.e. if the depth is already zero. The
+    /// depth stays zero in this case.
+    #[inline]
+    pub fn leave(&self) {
+        let result = self
+            .depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+                depth.checked_sub(1)
+            });
+        assert!(
+            result.is_ok(),
+            "InInterrupt::leave called without a matching enter"
+        );
+    }
+
+    /// Returns whether the current CPU is executing an interrupt handler.
+    #[inline]
+    pub fn in_interrupt(&self) -> bool {
+        self.depth() > 0
+    }
+
+    /// Returns the number of nested interrupt handlers that are currently executing.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
 }
 
 /// Atomically enable interrupts and put the CPU to sleep
@@ -139,6 +472,43 @@ pub fn enable_and_hlt() {
     }
 }
 
+/// Briefly enables interrupts to let pending interrupts fire, then disables them again.
+///
+/// Executes the `sti; nop; cli` instruction sequence. Since the `sti` instruction keeps
+/// interrupts disabled until after the immediately following instruction (the "interrupt
+/// shadow"), the `nop` still runs with interrupts disabled. Pending interrupts are then
+/// delivered at exactly one instruction boundary, between the `nop` and the `cli`. When
+/// this function returns, interrupts are disabled, regardless of whether they were enabled
+/// before.
+///
+/// In contrast to [`enable_and_hlt`], the CPU is not put to sleep if no interrupt is pending.
+/// This is useful for draining pending interrupts at a controlled point, e.g. in a long
+/// running loop that otherwise runs with interrupts disabled:
+///
+/// ```ignore
+/// x86_64::instructions::interrupts::disable();
+/// for chunk in work {
+///     process(chunk);
+///     // let pending interrupts fire before continuing with interrupts disabled
+///     x86_64::instructions::interrupts::enable_and_nop();
+/// }
+/// ```
+///
+/// Note that an interrupt handler might itself enable interrupts, so more than one interrupt
+/// can be handled during the window.
+#[inline]
+pub fn enable_and_nop() {
+    // no `nomem`, since interrupt handlers that run in the window may access memory
+    #[cfg(feature = "inline_asm")]
+    unsafe {
+        asm!("sti; nop; cli", options(nostack));
+    }
+    #[cfg(not(feature = "inline_asm"))]
+    unsafe {
+        crate::asm::x86_64_asm_interrupt_enable_and_nop();
+    }
+}
+
 /// Cause a breakpoint exception by invoking the `int3` instruction.
 #[inline]
 pub fn int3() {
@@ -174,3 +544,90 @@ macro_rules! software_interrupt {
         compile_error!("software_interrupt not implemented for non-nightly");
     }};
 }
+
+/// Generates a software interrupt for a vector that is only known at runtime.
+///
+/// The `int` instruction only takes an immediate vector, so this dispatches to one of 256
+/// `int` instructions through a `match`. Prefer the [`software_interrupt`] macro if the
+/// vector is a constant, since this function adds about 1KiB of code and a 1KiB jump table
+/// to the binary. It is still a single function, so calling it from many places doesn't
+/// increase the size further.
+///
+/// # Examples
+///
+/// ```ignore
+/// const SCHEDULER_VECTOR: u8 = 0x81;
+///
+/// unsafe { software_interrupt_dynamic(config.yield_vector.unwrap_or(SCHEDULER_VECTOR)) };
+/// ```
+///
+/// ## Safety
+///
+/// Invoking an arbitrary interrupt handler is unsafe, e.g. if the handler expects an error
+/// code on the stack, which `int` doesn't push.
+#[cfg(feature = "inline_asm")]
+#[inline(never)]
+pub unsafe fn software_interrupt_dynamic(vector: u8) {
+    macro_rules! dispatch {
+        ($($n:literal)*) => {
+            match vector {
+                $($n => software_interrupt!($n),)*
+            }
+        };
+    }
+
+    dispatch!(
+        0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+        16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+        32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+        48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+        64 65 66 67 68 69 70 71 72 73 74 75 76 77 78 79
+        80 81 82 83 84 85 86 87 88 89 90 91 92 93 94 95
+        96 97 98 99 100 101 102 103 104 105 106 107 108 109 110 111
+        112 113 114 115 116 117 118 119 120 121 122 123 124 125 126 127
+        128 129 130 131 132 133 134 135 136 137 138 139 140 141 142 143
+        144 145 146 147 148 149 150 151 152 153 154 155 156 157 158 159
+        160 161 162 163 164 165 166 167 168 169 170 171 172 173 174 175
+        176 177 178 179 180 181 182 183 184 185 186 187 188 189 190 191
+        192 193 194 195 196 197 198 199 200 201 202 203 204 205 206 207
+        208 209 210 211 212 213 214 215 216 217 218 219 220 221 222 223
+        224 225 226 227 228 229 230 231 232 233 234 235 236 237 238 239
+        240 241 242 243 244 245 246 247 248 249 250 251 252 253 254 255
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assert_enabled() {
+        // user mode processes always run with enabled interrupts
+        assert_enabled();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "interrupts are enabled")]
+    fn test_assert_disabled() {
+        assert_disabled();
+    }
+
+    #[test]
+    fn test_in_interrupt() {
+        let counter = InInterrupt::new();
+        assert!(!counter.in_interrupt());
+        counter.enter();
+        counter.enter();
+        assert_eq!(counter.depth(), 2);
+        counter.leave();
+        assert!(counter.in_interrupt());
+        counter.leave();
+        assert!(!counter.in_interrupt());
+
+        // an unmatched `leave` panics without wrapping the depth around
+        let result = std::panic::catch_unwind(|| counter.leave());
+        assert!(result.is_err());
+        assert_eq!(counter.depth(), 0);
+    }
+}
@@ -2,12 +2,17 @@
 
 //! Special x86_64 instructions.
 
+pub mod control_flow;
+pub mod hypervisor;
 pub mod interrupts;
+pub mod pkru;
 pub mod port;
 pub mod random;
+pub mod reset;
 pub mod segmentation;
 pub mod tables;
 pub mod tlb;
+pub mod tsc;
 
 /// Halts the CPU until the next interrupt arrives.
 #[inline]
@@ -42,6 +47,55 @@ pub fn nop() {
     }
 }
 
+/// Reads the processor register with the given index using the AMD `rdpru` instruction.
+///
+/// The following register indices are defined:
+///
+/// - `0`: `MPERF`, the maximum frequency clock count.
+/// - `1`: `APERF`, the actual frequency clock count.
+///
+/// `Some(0)` is returned for unsupported indices.
+///
+/// Returns `None` if the CPU doesn't support the instruction, as reported by CPUID leaf
+/// 0x80000008 (bit 4 of `ebx`). The operating system can still disable it for user mode
+/// through the `HWCR` MSR (bit 35), in which case executing it outside of ring 0 raises an
+/// invalid opcode exception.
+// `__cpuid` is only safe on newer compilers
+#[allow(unused_unsafe)]
+#[inline]
+pub fn rdpru(ecx: u32) -> Option<u64> {
+    // RDPRU support indicated by CPUID page 80000008h, ebx bit 4
+    let max_extended_leaf = unsafe { core::arch::x86_64::__cpuid(0x8000_0000) }.eax;
+    if max_extended_leaf < 0x8000_0008 {
+        return None;
+    }
+    let cpuid = unsafe { core::arch::x86_64::__cpuid(0x8000_0008) };
+    if cpuid.ebx & (1 << 4) == 0 {
+        return None;
+    }
+
+    #[cfg(feature = "inline_asm")]
+    {
+        let (low, high): (u32, u32);
+        unsafe {
+            // `rdpru`, encoded manually for assemblers that don't know it
+            asm!(
+                ".byte 0x0f, 0x01, 0xfd",
+                in("ecx") ecx,
+                out("eax") low,
+                out("edx") high,
+                options(nomem, nostack),
+            );
+        }
+        Some(((high as u64) << 32) | (low as u64))
+    }
+
+    #[cfg(not(feature = "inline_asm"))]
+    unsafe {
+        Some(crate::asm::x86_64_asm_rdpru(ecx))
+    }
+}
+
 /// Emits a '[magic breakpoint](https://wiki.osdev.org/Bochs#Magic_Breakpoint)' instruction for the [Bochs](http://bochs.sourceforge.net/) CPU
 /// emulator. Make sure to set `magic_break: enabled=1` in your `.bochsrc` file.
 #[cfg(feature = "inline_asm")]
@@ -54,14 +108,49 @@ pub fn bochs_breakpoint() {
 
 /// Gets the current instruction pointer. Note that this is only approximate as it requires a few
 /// instructions to execute.
-#[cfg(feature = "inline_asm")]
+///
+/// Without the `inline_asm` feature, this returns the return address of the external assembly
+/// function, i.e. the address of the instruction directly after the call.
 #[inline(always)]
 pub fn read_rip() -> crate::VirtAddr {
-    let rip: u64;
-    unsafe {
-        asm!(
-            "lea {}, [rip]", out(reg) rip, options(nostack, nomem)
-        );
-    }
+    #[cfg(feature = "inline_asm")]
+    let rip: u64 = {
+        let rip: u64;
+        unsafe {
+            asm!(
+                "lea {}, [rip]", out(reg) rip, options(nostack, nomem)
+            );
+        }
+        rip
+    };
+
+    #[cfg(not(feature = "inline_asm"))]
+    let rip = unsafe { crate::asm::x86_64_asm_read_rip() };
+
     crate::VirtAddr::new(rip)
 }
+
+/// Gets the current stack pointer.
+///
+/// The value is captured at the point of the call, so it is only useful for checks that don't
+/// need to be exact, e.g. whether the current stack is close to its guard page. Without the
+/// `inline_asm` feature, the value accounts for the return address that the call to the
+/// external assembly function pushes, so it matches the stack pointer of the caller.
+#[inline(always)]
+pub fn read_rsp() -> crate::VirtAddr {
+    #[cfg(feature = "inline_asm")]
+    let rsp: u64 = {
+        let rsp: u64;
+        unsafe {
+            asm!(
+                "mov {}, rsp", out(reg) rsp, options(nostack, nomem, preserves_flags)
+            );
+        }
+        rsp
+    };
+
+    #[cfg(not(feature = "inline_asm"))]
+    let rsp = unsafe { crate::asm::x86_64_asm_read_rsp() };
+
+    crate::VirtAddr::new(rsp)
+}
@@ -0,0 +1,59 @@
+//! Access to the protection key rights register (PKRU) for user-mode pages.
+//!
+//! Protection keys allow changing the access rights of all user-mode pages tagged with a
+//! given key without modifying page tables or flushing the TLB. They require
+//! [`Cr4Flags::PROTECTION_KEY`](crate::registers::control::Cr4Flags::PROTECTION_KEY) to be
+//! set, otherwise the instructions in this module cause an invalid opcode exception.
+//!
+//! The register contains two bits for each of the 16 protection keys: bit `2 * key` disables
+//! all data accesses and bit `2 * key + 1` disables writes, see
+//! [`ProtectionKey::access_disable_mask`] and [`ProtectionKey::write_disable_mask`].
+//!
+//! [`ProtectionKey::access_disable_mask`]: crate::structures::paging::ProtectionKey::access_disable_mask
+//! [`ProtectionKey::write_disable_mask`]: crate::structures::paging::ProtectionKey::write_disable_mask
+
+/// Reads the PKRU register using the `rdpkru` instruction.
+#[inline]
+pub fn read() -> u32 {
+    let value: u32;
+
+    #[cfg(feature = "inline_asm")]
+    unsafe {
+        asm!(
+            "rdpkru",
+            in("ecx") 0,
+            out("eax") value,
+            out("edx") _,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+
+    #[cfg(not(feature = "inline_asm"))]
+    unsafe {
+        value = crate::asm::x86_64_asm_rdpkru();
+    }
+
+    value
+}
+
+/// Writes the PKRU register using the `wrpkru` instruction.
+///
+/// ## Safety
+///
+/// This function is unsafe because the caller must ensure that changing the access rights of
+/// user-mode pages doesn't violate memory safety, e.g. by granting access to pages that are
+/// supposed to be protected.
+#[inline]
+pub unsafe fn write(value: u32) {
+    #[cfg(feature = "inline_asm")]
+    asm!(
+        "wrpkru",
+        in("eax") value,
+        in("ecx") 0,
+        in("edx") 0,
+        options(nostack, preserves_flags)
+    );
+
+    #[cfg(not(feature = "inline_asm"))]
+    crate::asm::x86_64_asm_wrpkru(value);
+}
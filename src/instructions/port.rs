@@ -1,11 +1,54 @@
 //! Access to I/O ports
+//!
+//! # Examples
+//!
+//! Initializing the legacy 8259 PICs with the ICW1 to ICW4 command sequence, remapping their
+//! interrupts to vectors 32 to 47:
+//!
+//! ```no_run
+//! use x86_64::instructions::port::{io_wait, PortWriteOnly};
+//!
+//! let mut pic1_command = PortWriteOnly::<u8>::new(0x20);
+//! let mut pic1_data = PortWriteOnly::<u8>::new(0x21);
+//! let mut pic2_command = PortWriteOnly::<u8>::new(0xa0);
+//! let mut pic2_data = PortWriteOnly::<u8>::new(0xa1);
+//!
+//! unsafe {
+//!     // ICW1: start initialization, ICW4 follows
+//!     pic1_command.write(0x11);
+//!     io_wait();
+//!     pic2_command.write(0x11);
+//!     io_wait();
+//!     // ICW2: vector offsets
+//!     pic1_data.write(32);
+//!     io_wait();
+//!     pic2_data.write(40);
+//!     io_wait();
+//!     // ICW3: secondary PIC is connected to line 2 of the primary PIC
+//!     pic1_data.write(4);
+//!     io_wait();
+//!     pic2_data.write(2);
+//!     io_wait();
+//!     // ICW4: 8086 mode
+//!     pic1_data.write(0x01);
+//!     io_wait();
+//!     pic2_data.write(0x01);
+//!     io_wait();
+//! }
+//! ```
 
 use core::marker::PhantomData;
 
 pub use crate::structures::port::{PortRead, PortWrite};
 
 impl PortRead for u8 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn read_from_port(port: u16) -> u8 {
+        mock::read(port) as u8
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u8 {
         let value: u8;
@@ -13,7 +56,7 @@ impl PortRead for u8 {
         value
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u8 {
         crate::asm::x86_64_asm_read_from_port_u8(port)
@@ -21,7 +64,13 @@ impl PortRead for u8 {
 }
 
 impl PortRead for u16 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn read_from_port(port: u16) -> u16 {
+        mock::read(port) as u16
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u16 {
         let value: u16;
@@ -29,7 +78,7 @@ impl PortRead for u16 {
         value
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u16 {
         crate::asm::x86_64_asm_read_from_port_u16(port)
@@ -37,7 +86,13 @@ impl PortRead for u16 {
 }
 
 impl PortRead for u32 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn read_from_port(port: u16) -> u32 {
+        mock::read(port)
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u32 {
         let value: u32;
@@ -45,7 +100,7 @@ impl PortRead for u32 {
         value
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn read_from_port(port: u16) -> u32 {
         crate::asm::x86_64_asm_read_from_port_u32(port)
@@ -53,13 +108,19 @@ impl PortRead for u32 {
 }
 
 impl PortWrite for u8 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn write_to_port(port: u16, value: u8) {
+        mock::write(port, value.into())
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u8) {
         asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack));
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u8) {
         crate::asm::x86_64_asm_write_to_port_u8(port, value)
@@ -67,13 +128,19 @@ impl PortWrite for u8 {
 }
 
 impl PortWrite for u16 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn write_to_port(port: u16, value: u16) {
+        mock::write(port, value.into())
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u16) {
         asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack));
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u16) {
         crate::asm::x86_64_asm_write_to_port_u16(port, value)
@@ -81,13 +148,19 @@ impl PortWrite for u16 {
 }
 
 impl PortWrite for u32 {
-    #[cfg(feature = "inline_asm")]
+    #[cfg(feature = "mock_port")]
+    #[inline]
+    unsafe fn write_to_port(port: u16, value: u32) {
+        mock::write(port, value)
+    }
+
+    #[cfg(all(feature = "inline_asm", not(feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u32) {
         asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack));
     }
 
-    #[cfg(not(feature = "inline_asm"))]
+    #[cfg(not(any(feature = "inline_asm", feature = "mock_port")))]
     #[inline]
     unsafe fn write_to_port(port: u16, value: u32) {
         crate::asm::x86_64_asm_write_to_port_u32(port, value)
@@ -199,3 +272,124 @@ impl<T: PortWrite> Port<T> {
         T::write_to_port(self.port, value)
     }
 }
+
+/// Waits a short amount of time (usually 1 to 4 microseconds) by writing to an unused port.
+///
+/// This writes a dummy byte to port `0x80`, which is used for POST codes during boot and is
+/// otherwise unused, so the write has no side effects. It is a legacy technique to give slow
+/// devices such as the 8259 PIC time to react to a previous port access. Use a proper timer
+/// where a precise delay is needed.
+#[inline]
+pub fn io_wait() {
+    unsafe { u8::write_to_port(0x80, 0) }
+}
+
+/// An in-memory backend for the port types, enabled by the `mock_port` feature.
+///
+/// With the feature enabled, the `in` and `out` instructions are never executed. Instead, all
+/// port writes, including the one of [`io_wait`], are recorded in order, and reads return the
+/// values that were queued for the port, or 0 if the queue is empty. The state is local to
+/// the current thread, so tests running in parallel don't interfere.
+///
+/// This makes it possible to unit test the register sequences of drivers on a development
+/// host. The feature requires `std` and must not be enabled for kernels.
+///
+/// ```
+/// # #[cfg(feature = "mock_port")]
+/// # {
+/// use x86_64::instructions::port::{mock, Port};
+///
+/// let mut status = Port::<u8>::new(0x3fd);
+/// let mut data = Port::<u8>::new(0x3f8);
+/// mock::queue_read(0x3fd, 0x20);
+/// unsafe {
+///     if status.read() & 0x20 != 0 {
+///         data.write(b'x');
+///     }
+/// }
+/// assert_eq!(mock::take_writes(), [(0x3f8, u32::from(b'x'))]);
+/// # }
+/// ```
+#[cfg(feature = "mock_port")]
+pub mod mock {
+    use std::cell::RefCell;
+    use std::collections::{HashMap, VecDeque};
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct State {
+        reads: HashMap<u16, VecDeque<u32>>,
+        writes: Vec<(u16, u32)>,
+    }
+
+    std::thread_local! {
+        static STATE: RefCell<State> = RefCell::new(State::default());
+    }
+
+    /// Queues a value that a later read from the given port returns.
+    ///
+    /// Values are returned in the order they were queued and truncated to the width of the
+    /// read.
+    pub fn queue_read(port: u16, value: u32) {
+        STATE.with(|state| {
+            state
+                .borrow_mut()
+                .reads
+                .entry(port)
+                .or_default()
+                .push_back(value)
+        });
+    }
+
+    /// Returns the `(port, value)` pairs of all writes since the last call, in order.
+    pub fn take_writes() -> Vec<(u16, u32)> {
+        STATE.with(|state| core::mem::take(&mut state.borrow_mut().writes))
+    }
+
+    /// Removes all queued reads and recorded writes of the current thread.
+    pub fn reset() {
+        STATE.with(|state| *state.borrow_mut() = State::default());
+    }
+
+    pub(super) fn read(port: u16) -> u32 {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state
+                .reads
+                .get_mut(&port)
+                .and_then(VecDeque::pop_front)
+                .unwrap_or(0)
+        })
+    }
+
+    pub(super) fn write(port: u16, value: u32) {
+        STATE.with(|state| state.borrow_mut().writes.push((port, value)));
+    }
+}
+
+#[cfg(all(test, feature = "mock_port"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_port() {
+        mock::reset();
+        mock::queue_read(0x60, 0x1234);
+        mock::queue_read(0x60, 0xff);
+        let mut port = Port::<u16>::new(0x60);
+        let mut byte_port = PortReadOnly::<u8>::new(0x60);
+        unsafe {
+            assert_eq!(port.read(), 0x1234);
+            assert_eq!(byte_port.read(), 0xff);
+            assert_eq!(port.read(), 0);
+            port.write(0xabcd);
+            PortWriteOnly::<u32>::new(0xcf8).write(0x8000_0000);
+        }
+        io_wait();
+        assert_eq!(
+            mock::take_writes(),
+            [(0x60, 0xabcd), (0xcf8, 0x8000_0000), (0x80, 0)]
+        );
+        assert!(mock::take_writes().is_empty());
+    }
+}
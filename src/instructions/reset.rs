@@ -0,0 +1,87 @@
+//! Legacy mechanisms for resetting the machine.
+//!
+//! These are last-resort mechanisms for small kernels that don't implement a proper reset,
+//! e.g. through the ACPI reset register or the `FADT`, which is out of scope of this crate.
+//! None of them is guaranteed to work on modern hardware: the 8042 keyboard controller is
+//! often emulated or missing, and the behavior of a triple fault depends on the chipset
+//! (virtual machines might e.g. shut down instead of rebooting).
+
+use crate::instructions::port::{io_wait, PortReadOnly, PortWriteOnly};
+use crate::instructions::{hlt, interrupts, tables::lidt};
+use crate::structures::DescriptorTablePointer;
+use crate::VirtAddr;
+
+/// The status and command port of the 8042 keyboard controller.
+const KBC_PORT: u16 = 0x64;
+/// The bit of the status register that is set while the input buffer is full.
+const KBC_INPUT_FULL: u8 = 1 << 1;
+/// The command that pulses the reset line of the CPU.
+const KBC_RESET_COMMAND: u8 = 0xFE;
+
+/// Tries to reset the machine by pulsing the reset line through the 8042 keyboard controller.
+///
+/// This waits for the input buffer of the controller to be empty and then sends the reset
+/// command to port `0x64`. The reset happens asynchronously, so this function can return
+/// even if it succeeds. It also returns if there is no working keyboard controller.
+///
+/// ## Safety
+///
+/// The caller must ensure that port `0x64` doesn't belong to a different device, since the
+/// command might have arbitrary effects on it.
+pub unsafe fn kbc_reset() {
+    let mut status = PortReadOnly::<u8>::new(KBC_PORT);
+    let mut command = PortWriteOnly::<u8>::new(KBC_PORT);
+
+    // give up waiting for a missing or hung controller eventually
+    for _ in 0..0x1_0000 {
+        if status.read() & KBC_INPUT_FULL == 0 {
+            break;
+        }
+        io_wait();
+    }
+    command.write(KBC_RESET_COMMAND);
+}
+
+/// Resets the machine by causing a triple fault.
+///
+/// This loads an IDT with a limit of zero and then executes `int3`. The CPU then fails to
+/// deliver the breakpoint exception, the resulting general protection fault, and finally the
+/// double fault, which makes it enter the shutdown state. Most chipsets reset the machine
+/// when this happens.
+///
+/// Only the current CPU is affected if the chipset ignores the shutdown, in which case this
+/// function halts it forever.
+pub fn triple_fault() -> ! {
+    let idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    // SAFETY: the empty IDT makes every interrupt fail, which is the intended effect
+    unsafe { lidt(&idt) };
+    interrupts::int3();
+
+    // NMIs and the `int3` can't be handled without an IDT, so this is never reached
+    loop {
+        hlt();
+    }
+}
+
+/// Tries to reset the machine through [`kbc_reset`] and then falls back to [`triple_fault`].
+///
+/// Interrupts are disabled first, and the keyboard controller is given a few milliseconds to
+/// perform the reset before the triple fault is caused.
+///
+/// ## Safety
+///
+/// See [`kbc_reset`].
+pub unsafe fn best_effort_reset() -> ! {
+    interrupts::disable();
+
+    kbc_reset();
+    // each `io_wait` takes about one microsecond
+    for _ in 0..10_000 {
+        io_wait();
+    }
+
+    triple_fault()
+}
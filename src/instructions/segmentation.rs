@@ -1,6 +1,181 @@
 //! Provides functions to read and write segment registers.
+//!
+//! The [`Segment`] trait provides a uniform interface for all segment registers, which is
+//! implemented by the marker types [`CS`], [`SS`], [`DS`], [`ES`], [`FS`], and [`GS`]. The
+//! free functions of this module are equivalent to the corresponding trait methods.
 
-use crate::structures::gdt::SegmentSelector;
+use crate::{
+    registers::model_specific::{FsBase, GsBase, Msr},
+    structures::gdt::SegmentSelector,
+    VirtAddr,
+};
+
+/// An x86 segment register.
+///
+/// All methods have both an inline assembly and a precompiled assembly implementation, so
+/// they are available with both the `inline_asm` and the `external_asm` feature.
+pub trait Segment {
+    /// Returns the current value of the segment register.
+    fn get_reg() -> SegmentSelector;
+
+    /// Reloads the segment register.
+    ///
+    /// ## Safety
+    ///
+    /// This function is unsafe because the caller must ensure that `sel` is a valid segment
+    /// descriptor for this segment register, and that reloading the register doesn't break
+    /// memory safety.
+    unsafe fn set_reg(sel: SegmentSelector);
+}
+
+/// An x86 segment register whose base address is used in 64-bit mode, i.e. FS and GS.
+///
+/// The base address is accessed through the corresponding model specific register, so this
+/// doesn't require `CR4.FSGSBASE` (see [`rdfsbase`] and [`wrfsbase`] for the faster
+/// instructions).
+pub trait Segment64: Segment {
+    /// The model specific register that holds the base address of the segment.
+    const BASE: Msr;
+
+    /// Reads the base address of the segment.
+    fn read_base() -> VirtAddr;
+
+    /// Writes the base address of the segment.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must ensure that this write operation has no unsafe side effects, as the
+    /// base address is often used for thread local storage or per-CPU data.
+    unsafe fn write_base(base: VirtAddr);
+}
+
+/// Code Segment
+///
+/// The segment base and limit are unused in 64-bit mode. Only the L (long), D (default
+/// operation size), and DPL (descriptor privilege level) fields of the descriptor are
+/// recognized, so changing this register changes the CPU mode and privilege level.
+#[derive(Debug)]
+pub struct CS;
+
+/// Stack Segment
+///
+/// Entirely unused in 64-bit mode, setting the segment register does nothing. However, in
+/// ring 3, the SS register still has to point to a valid descriptor (it can't be zero), so
+/// the register has to be updated when transitioning to ring 3.
+#[derive(Debug)]
+pub struct SS;
+
+/// Data Segment
+///
+/// Entirely unused in 64-bit mode, setting the segment register does nothing.
+#[derive(Debug)]
+pub struct DS;
+
+/// ES Segment
+///
+/// Entirely unused in 64-bit mode, setting the segment register does nothing.
+#[derive(Debug)]
+pub struct ES;
+
+/// FS Segment
+///
+/// Only the base is used in 64-bit mode, see [`Segment64`]. It is often used for thread
+/// local storage.
+#[derive(Debug)]
+pub struct FS;
+
+/// GS Segment
+///
+/// Only the base is used in 64-bit mode, see [`Segment64`]. In kernel mode, it is often used
+/// for per-CPU data, together with [`swap_gs`].
+#[derive(Debug)]
+pub struct GS;
+
+impl Segment for CS {
+    #[inline]
+    fn get_reg() -> SegmentSelector {
+        cs()
+    }
+
+    #[inline]
+    unsafe fn set_reg(sel: SegmentSelector) {
+        set_cs(sel)
+    }
+}
+
+impl Segment for SS {
+    #[inline]
+    fn get_reg() -> SegmentSelector {
+        ss()
+    }
+
+    #[inline]
+    unsafe fn set_reg(sel: SegmentSelector) {
+        load_ss(sel)
+    }
+}
+
+macro_rules! data_segment_impl {
+    ($type:ty, $name:literal, $asm_get:ident, $load:ident) => {
+        impl Segment for $type {
+            #[inline]
+            fn get_reg() -> SegmentSelector {
+                #[cfg(feature = "inline_asm")]
+                {
+                    let segment: u16;
+                    unsafe {
+                        asm!(concat!("mov {0:x}, ", $name), out(reg) segment, options(nostack, nomem))
+                    };
+                    SegmentSelector(segment)
+                }
+
+                #[cfg(not(feature = "inline_asm"))]
+                {
+                    let segment: u16 = unsafe { crate::asm::$asm_get() };
+                    SegmentSelector(segment)
+                }
+            }
+
+            #[inline]
+            unsafe fn set_reg(sel: SegmentSelector) {
+                $load(sel)
+            }
+        }
+    };
+}
+
+data_segment_impl!(DS, "ds", x86_64_asm_get_ds, load_ds);
+data_segment_impl!(ES, "es", x86_64_asm_get_es, load_es);
+data_segment_impl!(FS, "fs", x86_64_asm_get_fs, load_fs);
+data_segment_impl!(GS, "gs", x86_64_asm_get_gs, load_gs);
+
+impl Segment64 for FS {
+    const BASE: Msr = FsBase::MSR;
+
+    #[inline]
+    fn read_base() -> VirtAddr {
+        FsBase::read()
+    }
+
+    #[inline]
+    unsafe fn write_base(base: VirtAddr) {
+        FsBase::write(base)
+    }
+}
+
+impl Segment64 for GS {
+    const BASE: Msr = GsBase::MSR;
+
+    #[inline]
+    fn read_base() -> VirtAddr {
+        GsBase::read()
+    }
+
+    #[inline]
+    unsafe fn write_base(base: VirtAddr) {
+        GsBase::write(base)
+    }
+}
 
 /// Reload code segment register.
 ///
@@ -145,6 +320,26 @@ pub fn cs() -> SegmentSelector {
     }
 }
 
+/// Returns the current value of the stack segment register.
+///
+/// Together with [`cs`], this can be used to save the segment state of the running code, e.g.
+/// to build the stack frame for an `iretq`.
+#[inline]
+pub fn ss() -> SegmentSelector {
+    #[cfg(feature = "inline_asm")]
+    {
+        let segment: u16;
+        unsafe { asm!("mov {0:x}, ss", out(reg) segment, options(nostack, nomem)) };
+        SegmentSelector(segment)
+    }
+
+    #[cfg(not(feature = "inline_asm"))]
+    {
+        let segment: u16 = unsafe { crate::asm::x86_64_asm_get_ss() };
+        SegmentSelector(segment)
+    }
+}
+
 /// Writes the FS segment base address
 ///
 /// ## Safety
@@ -1,6 +1,7 @@
 //! Functions to load GDT, IDT, and TSS structures.
 
 use crate::structures::gdt::SegmentSelector;
+use crate::VirtAddr;
 
 pub use crate::structures::DescriptorTablePointer;
 
@@ -44,6 +45,50 @@ pub unsafe fn lidt(idt: &DescriptorTablePointer) {
     crate::asm::x86_64_asm_lidt(idt as *const _);
 }
 
+/// Get the address of the current GDT using the `sgdt` instruction.
+///
+/// Reading the pointer is safe, but the returned base address is not guaranteed to point
+/// to a valid GDT, so dereferencing it requires care.
+#[inline]
+pub fn sgdt() -> DescriptorTablePointer {
+    let mut gdt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    #[cfg(feature = "inline_asm")]
+    unsafe {
+        asm!("sgdt [{}]", in(reg) &mut gdt, options(nostack, preserves_flags));
+    }
+
+    #[cfg(not(feature = "inline_asm"))]
+    unsafe {
+        crate::asm::x86_64_asm_sgdt(&mut gdt as *mut _);
+    }
+    gdt
+}
+
+/// Get the address of the current IDT using the `sidt` instruction.
+///
+/// Reading the pointer is safe, but the returned base address is not guaranteed to point
+/// to a valid IDT, so dereferencing it requires care.
+#[inline]
+pub fn sidt() -> DescriptorTablePointer {
+    let mut idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+    #[cfg(feature = "inline_asm")]
+    unsafe {
+        asm!("sidt [{}]", in(reg) &mut idt, options(nostack, preserves_flags));
+    }
+
+    #[cfg(not(feature = "inline_asm"))]
+    unsafe {
+        crate::asm::x86_64_asm_sidt(&mut idt as *mut _);
+    }
+    idt
+}
+
 /// Load the task state register using the `ltr` instruction.
 ///
 /// ## Safety
@@ -108,3 +108,21 @@ pub unsafe fn flush_pcid(command: InvPicdCommand) {
         crate::asm::x86_64_asm_invpcid(kind, &desc as *const InvpcidDescriptor as u64)
     };
 }
+
+/// Invalidate the TLB mapping of the given virtual address for the given address space
+/// identifier (ASID) using the `invlpga` instruction.
+///
+/// This is used by hypervisors that use AMD-V (SVM) to flush TLB entries of a guest.
+///
+/// ## Safety
+///
+/// This function is unsafe because it requires SVM to be enabled in the `EFER` register,
+/// otherwise it causes an invalid opcode exception. It must be executed in ring 0.
+#[inline]
+pub unsafe fn invlpga(addr: VirtAddr, asid: u32) {
+    #[cfg(feature = "inline_asm")]
+    asm!("invlpga rax, ecx", in("rax") addr.as_u64(), in("ecx") asid, options(nostack, preserves_flags));
+
+    #[cfg(not(feature = "inline_asm"))]
+    crate::asm::x86_64_asm_invlpga(addr.as_u64(), asid);
+}
@@ -0,0 +1,96 @@
+//! Access to the time stamp counter (TSC).
+
+use core::arch::x86_64::{__rdtscp, _mm_lfence, _rdtsc};
+
+/// Reads the current value of the time stamp counter using the `rdtsc` instruction.
+///
+/// Note that `rdtsc` is not a serializing instruction, so it might be executed before
+/// preceding instructions have completed. Use [`Stopwatch`] if you need properly
+/// fenced measurements.
+#[inline]
+pub fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Reads the current value of the time stamp counter and the `IA32_TSC_AUX` MSR using the
+/// `rdtscp` instruction. Returns a `(tsc, aux)` tuple.
+///
+/// `rdtscp` waits until all previous instructions have executed before reading the counter,
+/// but later instructions might still begin execution before the read is performed.
+///
+/// The instruction is only available if CPUID leaf 0x80000001 reports support for it
+/// (bit 27 of `edx`).
+#[inline]
+pub fn rdtscp() -> (u64, u32) {
+    let mut aux = 0;
+    let tsc = unsafe { __rdtscp(&mut aux) };
+    (tsc, aux)
+}
+
+/// Measures elapsed time stamp counter ticks using the fenced instruction sequences
+/// recommended by Intel for benchmarking.
+///
+/// The start is read with `lfence; rdtsc` and the end with `rdtscp; lfence`, which keeps the
+/// measured code from being reordered out of the measured interval.
+///
+/// The result is only meaningful as a measure of time if the TSC is invariant, i.e. it runs at
+/// a constant rate regardless of processor frequency changes and power states. This is
+/// reported by CPUID leaf 0x80000007 (bit 8 of `edx`). Since [`Stopwatch::elapsed`] uses
+/// `rdtscp`, the processor must also support that instruction (see [`rdtscp`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stopwatch {
+    start: u64,
+}
+
+impl Stopwatch {
+    /// Starts a new measurement by reading the time stamp counter using `lfence; rdtsc`.
+    #[inline]
+    pub fn start() -> Self {
+        let start = unsafe {
+            _mm_lfence();
+            _rdtsc()
+        };
+        Stopwatch { start }
+    }
+
+    /// Returns the time stamp counter value at which the measurement was started.
+    #[inline]
+    pub fn start_value(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the number of ticks that elapsed since [`Stopwatch::start`], reading the
+    /// time stamp counter using `rdtscp; lfence`.
+    ///
+    /// A wrap of the 64-bit counter between the two reads is handled correctly.
+    #[inline]
+    pub fn elapsed(&self) -> u64 {
+        let end = unsafe {
+            let mut aux = 0;
+            let end = __rdtscp(&mut aux);
+            _mm_lfence();
+            end
+        };
+        end.wrapping_sub(self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_stopwatch() {
+        // RDTSCP support indicated by CPUID page 80000001h, edx bit 27
+        let max_extended_leaf = unsafe { core::arch::x86_64::__cpuid(0x8000_0000) }.eax;
+        if max_extended_leaf >= 0x8000_0001
+            && unsafe { core::arch::x86_64::__cpuid(0x8000_0001) }.edx & (1 << 27) != 0
+        {
+            let stopwatch = Stopwatch::start();
+            let first = stopwatch.elapsed();
+            let second = stopwatch.elapsed();
+            // the TSC might not be monotonic on every host, so only check that it runs
+            assert!(first != 0 || second != 0);
+        }
+    }
+}
@@ -8,10 +8,26 @@
 #![cfg_attr(feature = "const_fn", feature(const_fn_fn_ptr_basics))]
 #![cfg_attr(feature = "inline_asm", feature(asm))]
 #![cfg_attr(feature = "abi_x86_interrupt", feature(abi_x86_interrupt))]
+#![cfg_attr(feature = "step_trait", feature(step_trait))]
 #![warn(missing_docs)]
 #![deny(missing_debug_implementations)]
 
-pub use crate::addr::{align_down, align_up, PhysAddr, VirtAddr};
+// The mock backends are built on `std`, which the kernel targets don't have.
+#[cfg(all(
+    not(test),
+    not(target_os = "none"),
+    any(feature = "mock_msr", feature = "mock_port")
+))]
+extern crate std;
+
+#[cfg(all(feature = "mock_msr", target_os = "none"))]
+compile_error!("the `mock_msr` feature requires `std` and must not be enabled for kernels");
+#[cfg(all(feature = "mock_port", target_os = "none"))]
+compile_error!("the `mock_port` feature requires `std` and must not be enabled for kernels");
+
+pub use crate::addr::{
+    align_down, align_up, checked_align_down, checked_align_up, PhysAddr, VirtAddr,
+};
 
 /// Makes a function const only when `feature = "const_fn"` is enabled.
 ///
@@ -44,6 +60,37 @@ macro_rules! const_fn {
     };
 }
 
+/// Implements `Serialize` and `Deserialize` for a type through its integer representation.
+///
+/// The conversion from the integer returns an `Option`, so that invalid values, e.g.
+/// non-canonical virtual addresses, are rejected with a deserialization error instead of
+/// causing a panic.
+#[cfg(feature = "serde")]
+macro_rules! serde_via_int {
+    ($ty:ty, $int:ty, $expected:literal, |$this:ident| $into:expr, |$value:ident| $from:expr) => {
+        impl serde::Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let $this = *self;
+                let value: $int = $into;
+                serde::Serialize::serialize(&value, serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let $value = <$int as serde::Deserialize>::deserialize(deserializer)?;
+                match $from {
+                    Some(value) => Ok(value),
+                    None => Err(serde::de::Error::invalid_value(
+                        serde::de::Unexpected::Unsigned($value.into()),
+                        &$expected,
+                    )),
+                }
+            }
+        }
+    };
+}
+
 #[cfg(all(feature = "instructions", feature = "external_asm"))]
 pub(crate) mod asm;
 
@@ -85,15 +132,123 @@ pub enum PrivilegeLevel {
 impl PrivilegeLevel {
     /// Creates a `PrivilegeLevel` from a numeric value. The value must be in the range 0..4.
     ///
-    /// This function panics if the passed value is >3.
+    /// This function panics if the passed value is >3. Use [`try_from_u16`](Self::try_from_u16)
+    /// or the [`TryFrom`](core::convert::TryFrom) implementations for values that might be
+    /// invalid, e.g. ones read from a possibly corrupted interrupt stack frame.
     #[inline]
     pub fn from_u16(value: u16) -> PrivilegeLevel {
+        match Self::try_from_u16(value) {
+            Ok(level) => level,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a `PrivilegeLevel` from a numeric value, or returns an error if the value is >3.
+    #[inline]
+    pub const fn try_from_u16(value: u16) -> Result<PrivilegeLevel, InvalidPrivilegeLevel> {
         match value {
-            0 => PrivilegeLevel::Ring0,
-            1 => PrivilegeLevel::Ring1,
-            2 => PrivilegeLevel::Ring2,
-            3 => PrivilegeLevel::Ring3,
-            i => panic!("{} is not a valid privilege level", i),
+            0 => Ok(PrivilegeLevel::Ring0),
+            1 => Ok(PrivilegeLevel::Ring1),
+            2 => Ok(PrivilegeLevel::Ring2),
+            3 => Ok(PrivilegeLevel::Ring3),
+            i => Err(InvalidPrivilegeLevel(i)),
         }
     }
 }
+
+impl core::convert::TryFrom<u16> for PrivilegeLevel {
+    type Error = InvalidPrivilegeLevel;
+
+    #[inline]
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Self::try_from_u16(value)
+    }
+}
+
+impl core::convert::TryFrom<u8> for PrivilegeLevel {
+    type Error = InvalidPrivilegeLevel;
+
+    #[inline]
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::try_from_u16(value.into())
+    }
+}
+
+impl From<PrivilegeLevel> for u8 {
+    #[inline]
+    fn from(level: PrivilegeLevel) -> u8 {
+        level as u8
+    }
+}
+
+impl From<PrivilegeLevel> for u16 {
+    #[inline]
+    fn from(level: PrivilegeLevel) -> u16 {
+        level as u16
+    }
+}
+
+impl From<PrivilegeLevel> for u64 {
+    #[inline]
+    fn from(level: PrivilegeLevel) -> u64 {
+        level as u64
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_int!(
+    PrivilegeLevel,
+    u8,
+    "a privilege level between 0 and 3",
+    |level| level as u8,
+    |level| PrivilegeLevel::try_from_u16(level.into()).ok()
+);
+
+/// A value that is not a valid privilege level, i.e. greater than 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPrivilegeLevel(u16);
+
+impl InvalidPrivilegeLevel {
+    /// Returns the invalid value.
+    #[inline]
+    pub const fn value(self) -> u16 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for InvalidPrivilegeLevel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} is not a valid privilege level", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn test_privilege_level_conversions() {
+        assert_eq!(PrivilegeLevel::try_from(3u16), Ok(PrivilegeLevel::Ring3));
+        assert_eq!(PrivilegeLevel::try_from(1u8), Ok(PrivilegeLevel::Ring1));
+        assert_eq!(
+            PrivilegeLevel::try_from(4u16).map_err(InvalidPrivilegeLevel::value),
+            Err(4)
+        );
+        assert!(PrivilegeLevel::try_from(0xffu8).is_err());
+        assert_eq!(u64::from(PrivilegeLevel::Ring2), 2);
+        assert_eq!(u8::from(PrivilegeLevel::Ring3), 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_privilege_level_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        assert_tokens(&PrivilegeLevel::Ring3, &[Token::U8(3)]);
+        assert_de_tokens_error::<PrivilegeLevel>(
+            &[Token::U8(4)],
+            "invalid value: integer `4`, expected a privilege level between 0 and 3",
+        );
+    }
+}
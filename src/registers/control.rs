@@ -2,7 +2,9 @@
 
 pub use super::model_specific::{Efer, EferFlags};
 
+use crate::{structures::paging::PhysFrame, PhysAddr};
 use bitflags::bitflags;
+use core::fmt;
 
 /// Various control flags modifying the basic operation of the CPU.
 #[derive(Debug)]
@@ -41,6 +43,15 @@ bitflags! {
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    Cr0Flags,
+    u64,
+    "valid CR0 flags",
+    |flags| flags.bits(),
+    |bits| Cr0Flags::from_bits(bits)
+);
+
 /// Contains the Page Fault Linear Address (PFLA).
 ///
 /// When page fault occurs, the CPU sets this register to the accessed address.
@@ -61,6 +72,57 @@ bitflags! {
     }
 }
 
+/// A raw value of the CR3 register that is decoded when it is printed.
+///
+/// The `Debug` implementation shows the P4 table frame and both interpretations of the low
+/// 12 bits: the [`Cr3Flags`] and the PCID. Which of them is used by the CPU depends on
+/// [`Cr4Flags::PCID`]. This is useful for dumping the CPU state, e.g. in a panic handler.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Cr3Value(u64);
+
+impl Cr3Value {
+    /// Creates a new value from the raw register contents.
+    #[inline]
+    pub const fn new(value: u64) -> Self {
+        Cr3Value(value)
+    }
+
+    /// Returns the raw register contents.
+    #[inline]
+    pub const fn raw(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the frame of the P4 table.
+    #[inline]
+    pub fn frame(self) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(self.0 & 0x_000f_ffff_ffff_f000))
+    }
+
+    /// Returns the low 12 bits interpreted as [`Cr3Flags`], which is only valid if PCIDs
+    /// are disabled.
+    #[inline]
+    pub const fn flags(self) -> Cr3Flags {
+        Cr3Flags::from_bits_truncate(self.0)
+    }
+
+    /// Returns the low 12 bits interpreted as PCID, which is only valid if PCIDs are enabled.
+    #[inline]
+    pub const fn pcid(self) -> u16 {
+        (self.0 & 0xfff) as u16
+    }
+}
+
+impl fmt::Debug for Cr3Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cr3Value")
+            .field("frame", &self.frame())
+            .field("flags", &self.flags())
+            .field("pcid", &self.pcid())
+            .finish()
+    }
+}
+
 /// Various control flags modifying the basic operation of the CPU while in protected mode.
 ///
 /// Note: The documention for the individual fields is taken from the AMD64 and Intel x86_64
@@ -122,13 +184,28 @@ bitflags! {
         const SUPERVISOR_MODE_ACCESS_PREVENTION = 1 << 21;
         /// Enables 4-level paging to associate each linear address with a protection key.
         const PROTECTION_KEY = 1 << 22;
+        /// Enables control-flow enforcement technology (CET), i.e. shadow stacks and indirect
+        /// branch tracking. Requires `WRITE_PROTECT` to be set in CR0.
+        const CONTROL_FLOW_ENFORCEMENT = 1 << 23;
+        /// Enables protection keys for supervisor-mode pages, configured through the
+        /// `IA32_PKRS` MSR.
+        const PROTECTION_KEY_SUPERVISOR = 1 << 24;
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    Cr4Flags,
+    u64,
+    "valid CR4 flags",
+    |flags| flags.bits(),
+    |bits| Cr4Flags::from_bits(bits)
+);
+
 #[cfg(feature = "instructions")]
 mod x86_64 {
     use super::*;
-    use crate::{instructions::tlb::Pcid, structures::paging::PhysFrame, PhysAddr, VirtAddr};
+    use crate::{instructions::tlb::Pcid, VirtAddr};
 
     impl Cr0 {
         /// Read the current set of CR0 flags.
@@ -237,7 +314,12 @@ mod x86_64 {
             (frame, flags)
         }
 
-        /// Read the current P4 table address from the CR3 register
+        /// Read the current P4 table address from the CR3 register together with the raw
+        /// value of the low 12 bits, which contain either the [`Cr3Flags`] or the PCID.
+        ///
+        /// Unlike [`Cr3::read`] and [`Cr3::read_pcid`], this doesn't interpret the low bits.
+        /// It never panics. See [`Cr3::parse_raw`] for splitting a CR3 value that was not read
+        /// from the register, e.g. the saved CR3 of a guest.
         #[inline]
         pub fn read_raw() -> (PhysFrame, u16) {
             let value: u64;
@@ -252,11 +334,30 @@ mod x86_64 {
                 value = crate::asm::x86_64_asm_read_cr3();
             }
 
+            Cr3::parse_raw(value)
+        }
+
+        /// Splits a raw CR3 value into the P4 table frame and the raw value of the low
+        /// 12 bits, without interpreting the low bits.
+        ///
+        /// Reserved bits above the physical address field are ignored, so this never panics.
+        /// This is useful for inspecting CR3 values of virtual machines, whose low bits might
+        /// be a PCID even if the host doesn't use PCIDs.
+        #[inline]
+        pub fn parse_raw(value: u64) -> (PhysFrame, u16) {
             let addr = PhysAddr::new(value & 0x_000f_ffff_ffff_f000);
             let frame = PhysFrame::containing_address(addr);
             (frame, (value & 0xFFF) as u16)
         }
 
+        /// Read the complete CR3 register as a [`Cr3Value`], which decodes the register when
+        /// it is printed.
+        #[inline]
+        pub fn read_value() -> Cr3Value {
+            let (frame, low_bits) = Cr3::read_raw();
+            Cr3Value::new(frame.start_address().as_u64() | u64::from(low_bits))
+        }
+
         /// Read the current P4 table address from the CR3 register along with PCID.
         /// The correct functioning of this requires CR4.PCIDE = 1.
         /// See [`Cr4Flags::PCID`]
@@ -268,25 +369,56 @@ mod x86_64 {
 
         /// Write a new P4 table address into the CR3 register.
         ///
+        /// The flags are only valid if PCIDs are disabled, since the same bits are part of the
+        /// PCID otherwise. Use [`Cr3::write_pcid`] if [`Cr4Flags::PCID`] is set. In debug
+        /// builds, this function panics if non-empty flags are written while PCIDs are enabled.
+        ///
         /// ## Safety
         /// Changing the level 4 page table is unsafe, because it's possible to violate memory safety by
         /// changing the page mapping.
         #[inline]
         pub unsafe fn write(frame: PhysFrame, flags: Cr3Flags) {
+            debug_assert!(
+                flags.is_empty() || !Cr4::read().contains(Cr4Flags::PCID),
+                "CR3 flags can't be used while PCIDs are enabled"
+            );
             Cr3::write_raw(frame, flags.bits() as u16);
         }
 
         /// Write a new P4 table address into the CR3 register.
         ///
+        /// In debug builds, this function panics if a non-zero PCID is written while PCIDs are
+        /// disabled, since the CPU would interpret the PCID as [`Cr3Flags`] instead.
+        ///
         /// ## Safety
         /// Changing the level 4 page table is unsafe, because it's possible to violate memory safety by
         /// changing the page mapping.
         /// [`Cr4Flags::PCID`] must be set before calling this method.
         #[inline]
         pub unsafe fn write_pcid(frame: PhysFrame, pcid: Pcid) {
+            debug_assert!(
+                pcid.value() == 0 || Cr4::read().contains(Cr4Flags::PCID),
+                "a PCID can't be used while PCIDs are disabled"
+            );
             Cr3::write_raw(frame, pcid.value());
         }
 
+        /// Write a complete CR3 value, e.g. one that was saved through [`Cr3::read_value`],
+        /// without interpreting its low 12 bits.
+        ///
+        /// This is useful for restoring a saved CR3 without knowing whether it was saved while
+        /// PCIDs were enabled. Like [`Cr3::parse_raw`], reserved bits above the physical address
+        /// field are ignored.
+        ///
+        /// ## Safety
+        /// Changing the level 4 page table is unsafe, because it's possible to violate memory safety by
+        /// changing the page mapping. The low 12 bits must be valid for the current value of
+        /// [`Cr4Flags::PCID`].
+        #[inline]
+        pub unsafe fn write_value(value: Cr3Value) {
+            Cr3::write_raw(value.frame(), value.pcid());
+        }
+
         /// Write a new P4 table address into the CR3 register.
         ///
         /// ## Safety
@@ -383,5 +515,43 @@ mod x86_64 {
             f(&mut flags);
             Self::write(flags);
         }
+
+        /// Enables the given CR4 flags, but only if `supported` is true.
+        ///
+        /// Setting a CR4 bit for a feature the CPU doesn't support causes a general protection
+        /// fault, so `supported` should be the result of the corresponding CPUID check, e.g.
+        /// `Cr4::enable_feature(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION, has_smep)`.
+        ///
+        /// Returns whether the flags were enabled.
+        ///
+        /// ## Safety
+        ///
+        /// This function is unsafe because enabling some features changes how the CPU treats
+        /// memory accesses, e.g. enabling SMAP makes supervisor accesses to user pages fault.
+        #[inline]
+        pub unsafe fn enable_feature(flags: Cr4Flags, supported: bool) -> bool {
+            if supported {
+                Self::update(|f| f.insert(flags));
+            }
+            supported
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn cr3_value_debug() {
+        let value = Cr3Value::new(0x1234_5000 | (1 << 3));
+        assert_eq!(value.frame().start_address(), PhysAddr::new(0x1234_5000));
+        assert_eq!(value.flags(), Cr3Flags::PAGE_LEVEL_WRITETHROUGH);
+        assert_eq!(value.pcid(), 8);
+        assert_eq!(
+            format!("{:?}", value),
+            "Cr3Value { frame: PhysFrame[4KiB](0x12345000), \
+             flags: PAGE_LEVEL_WRITETHROUGH, pcid: 8 }"
+        );
     }
 }
@@ -8,5 +8,5 @@ pub mod xcontrol;
 #[cfg(feature = "instructions")]
 pub use crate::instructions::segmentation::{rdfsbase, rdgsbase, wrfsbase, wrgsbase};
 
-#[cfg(all(feature = "instructions", feature = "inline_asm"))]
-pub use crate::instructions::read_rip;
+#[cfg(feature = "instructions")]
+pub use crate::instructions::{read_rip, read_rsp};
@@ -42,11 +42,67 @@ pub struct LStar;
 #[derive(Debug)]
 pub struct SFMask;
 
+/// Maximum Performance Frequency Clock Count Register: IA32_MPERF
+///
+/// Counts at a fixed frequency while the processor is in the C0 state.
+#[derive(Debug)]
+pub struct Mperf;
+
+/// Actual Performance Frequency Clock Count Register: IA32_APERF
+///
+/// Counts at the actual frequency of the processor while it is in the C0 state.
+#[derive(Debug)]
+pub struct Aperf;
+
+/// Time Stamp Counter Register: IA32_TIME_STAMP_COUNTER
+///
+/// Holds the same value that is returned by the `rdtsc` instruction.
+#[derive(Debug)]
+pub struct Tsc;
+
+/// Time Stamp Counter Adjust Register: IA32_TSC_ADJUST
+///
+/// Holds the offset that was applied to the time stamp counter of the current core.
+#[derive(Debug)]
+pub struct TscAdjust;
+
+/// Feature Control Register: IA32_FEATURE_CONTROL
+///
+/// Controls whether VMX, SGX and local machine check exceptions can be enabled. Once the
+/// lock bit is set, the register can't be modified until the next reset.
+#[derive(Debug)]
+pub struct FeatureControl;
+
 impl Efer {
     /// The underlying model specific register.
     pub const MSR: Msr = Msr(0xC000_0080);
 }
 
+impl FeatureControl {
+    /// The underlying model specific register.
+    pub const MSR: Msr = Msr(0x3A);
+}
+
+impl Tsc {
+    /// The underlying model specific register.
+    pub const MSR: Msr = Msr(0x10);
+}
+
+impl TscAdjust {
+    /// The underlying model specific register.
+    pub const MSR: Msr = Msr(0x3B);
+}
+
+impl Mperf {
+    /// The underlying model specific register.
+    pub const MSR: Msr = Msr(0xE7);
+}
+
+impl Aperf {
+    /// The underlying model specific register.
+    pub const MSR: Msr = Msr(0xE8);
+}
+
 impl FsBase {
     /// The underlying model specific register.
     pub const MSR: Msr = Msr(0xC000_0100);
@@ -99,6 +155,102 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Flags of the Feature Control Register.
+    pub struct FeatureControlFlags: u64 {
+        /// Locks the register, writing it afterwards causes a general protection fault.
+        const LOCK = 1;
+        /// Allows `vmxon` inside of SMX operation.
+        const VMX_INSIDE_SMX = 1 << 1;
+        /// Allows `vmxon` outside of SMX operation.
+        const VMX_OUTSIDE_SMX = 1 << 2;
+        /// Enables the local functions of the `getsec[senter]` instruction selected by bits
+        /// 8 to 14.
+        const SENTER_LOCAL_FUNCTIONS = 0x7F << 8;
+        /// Enables the `getsec[senter]` instruction.
+        const SENTER_GLOBAL_ENABLE = 1 << 15;
+        /// Allows the OS to write the SGX launch enclave public key hash MSRs.
+        const SGX_LAUNCH_CONTROL_ENABLE = 1 << 17;
+        /// Enables the SGX instructions.
+        const SGX_GLOBAL_ENABLE = 1 << 18;
+        /// Allows the OS to enable local machine check exceptions.
+        const LMCE_ON = 1 << 20;
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_via_int!(
+    EferFlags,
+    u64,
+    "valid EFER flags",
+    |flags| flags.bits(),
+    |bits| EferFlags::from_bits(bits)
+);
+
+#[cfg(feature = "instructions")]
+pub use self::x86_64::effective_frequency_ratio;
+
+/// An in-memory backend for [`Msr::read`] and [`Msr::write`], enabled by the `mock_msr`
+/// feature.
+///
+/// With the feature enabled, `rdmsr` and `wrmsr` are never executed. Instead, all MSR
+/// accesses, including the ones of the typed registers like [`Efer`], go to a map that is
+/// local to the current thread, so tests running in parallel don't interfere. MSRs that were
+/// never written read as 0.
+///
+/// This makes it possible to unit test code that configures MSRs on a development host,
+/// where `rdmsr` would fault. The feature requires `std` and must not be enabled for kernels.
+///
+/// ```
+/// # #[cfg(all(feature = "instructions", feature = "mock_msr"))]
+/// # {
+/// use x86_64::registers::model_specific::{mock, Efer, EferFlags};
+///
+/// mock::set(Efer::MSR, EferFlags::LONG_MODE_ACTIVE.bits());
+/// unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+/// assert_eq!(
+///     mock::get(Efer::MSR),
+///     Some((EferFlags::LONG_MODE_ACTIVE | EferFlags::NO_EXECUTE_ENABLE).bits())
+/// );
+/// # }
+/// ```
+#[cfg(feature = "mock_msr")]
+pub mod mock {
+    use super::Msr;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    std::thread_local! {
+        static MSRS: RefCell<HashMap<u32, u64>> = RefCell::new(HashMap::new());
+    }
+
+    /// Sets the value of the given MSR, e.g. to emulate the value set by the firmware.
+    pub fn set(msr: Msr, value: u64) {
+        write(msr.0, value);
+    }
+
+    /// Returns the value of the given MSR or `None` if it was never written.
+    pub fn get(msr: Msr) -> Option<u64> {
+        MSRS.with(|msrs| msrs.borrow().get(&msr.0).copied())
+    }
+
+    /// Removes all MSR values of the current thread.
+    pub fn reset() {
+        MSRS.with(|msrs| msrs.borrow_mut().clear());
+    }
+
+    #[cfg_attr(not(feature = "instructions"), allow(dead_code))]
+    pub(super) fn read(reg: u32) -> u64 {
+        MSRS.with(|msrs| msrs.borrow().get(&reg).copied().unwrap_or(0))
+    }
+
+    pub(super) fn write(reg: u32, value: u64) {
+        MSRS.with(|msrs| {
+            msrs.borrow_mut().insert(reg, value);
+        });
+    }
+}
+
 #[cfg(feature = "instructions")]
 mod x86_64 {
     use super::*;
@@ -118,14 +270,19 @@ mod x86_64 {
         /// effects.
         #[inline]
         pub unsafe fn read(&self) -> u64 {
-            #[cfg(feature = "inline_asm")]
+            #[cfg(feature = "mock_msr")]
+            {
+                super::mock::read(self.0)
+            }
+
+            #[cfg(all(feature = "inline_asm", not(feature = "mock_msr")))]
             {
                 let (high, low): (u32, u32);
                 asm!("rdmsr", out("eax") low, out("edx") high, in("ecx") self.0, options(nostack));
                 ((high as u64) << 32) | (low as u64)
             }
 
-            #[cfg(not(feature = "inline_asm"))]
+            #[cfg(not(any(feature = "inline_asm", feature = "mock_msr")))]
             crate::asm::x86_64_asm_rdmsr(self.0)
         }
 
@@ -137,14 +294,17 @@ mod x86_64 {
         /// effects.
         #[inline]
         pub unsafe fn write(&mut self, value: u64) {
-            #[cfg(feature = "inline_asm")]
+            #[cfg(feature = "mock_msr")]
+            super::mock::write(self.0, value);
+
+            #[cfg(all(feature = "inline_asm", not(feature = "mock_msr")))]
             {
                 let low = value as u32;
                 let high = (value >> 32) as u32;
                 asm!("wrmsr", in("ecx") self.0, in("eax") low, in("edx") high, options(nostack))
             }
 
-            #[cfg(not(feature = "inline_asm"))]
+            #[cfg(not(any(feature = "inline_asm", feature = "mock_msr")))]
             crate::asm::x86_64_asm_wrmsr(self.0, value);
         }
     }
@@ -376,6 +536,170 @@ mod x86_64 {
         }
     }
 
+    impl Mperf {
+        /// Read the current value of the IA32_MPERF counter.
+        #[inline]
+        pub fn read() -> u64 {
+            unsafe { Self::MSR.read() }
+        }
+
+        /// Write the given value to the IA32_MPERF counter, e.g. to reset it to 0.
+        #[inline]
+        pub fn write(value: u64) {
+            let mut msr = Self::MSR;
+            unsafe { msr.write(value) };
+        }
+    }
+
+    impl Aperf {
+        /// Read the current value of the IA32_APERF counter.
+        #[inline]
+        pub fn read() -> u64 {
+            unsafe { Self::MSR.read() }
+        }
+
+        /// Write the given value to the IA32_APERF counter, e.g. to reset it to 0.
+        #[inline]
+        pub fn write(value: u64) {
+            let mut msr = Self::MSR;
+            unsafe { msr.write(value) };
+        }
+    }
+
+    impl Tsc {
+        /// Read the current value of the time stamp counter.
+        ///
+        /// Prefer [`rdtsc`](crate::instructions::tsc::rdtsc), which does not require
+        /// privileges.
+        #[inline]
+        pub fn read() -> u64 {
+            unsafe { Self::MSR.read() }
+        }
+
+        /// Write the given value to the time stamp counter of the current core.
+        ///
+        /// This is rarely the right way to synchronize the counters of multiple cores: writing
+        /// the counter also changes IA32_TSC_ADJUST by the difference to the old value, but the
+        /// time between reading a reference value and writing it is lost. Use
+        /// [`TscAdjust::write`] instead.
+        ///
+        /// ## Safety
+        ///
+        /// Unsafe because code that relies on a monotonic time stamp counter (e.g. a clock
+        /// source of the kernel) might behave unexpectedly if the counter jumps.
+        #[inline]
+        pub unsafe fn write(value: u64) {
+            let mut msr = Self::MSR;
+            msr.write(value);
+        }
+    }
+
+    impl TscAdjust {
+        /// Read the offset that was applied to the time stamp counter of the current core.
+        ///
+        /// The register is only available if CPUID leaf 0x7 reports support for it (bit 1
+        /// of `ebx`).
+        #[inline]
+        pub fn read() -> i64 {
+            unsafe { Self::MSR.read() as i64 }
+        }
+
+        /// Write the offset that is applied to the time stamp counter of the current core.
+        ///
+        /// The time stamp counter changes by the difference between the old and the new offset.
+        /// This is the recommended mechanism for synchronizing the time stamp counters of
+        /// multiple cores, because it keeps the counters running at the same invariant rate
+        /// and firmware or other software can still detect the applied offset. Writing the
+        /// same value on all cores, e.g. `0`, undoes any offsets applied by firmware.
+        ///
+        /// ## Safety
+        ///
+        /// Unsafe because code that relies on a monotonic time stamp counter (e.g. a clock
+        /// source of the kernel) might behave unexpectedly if the counter jumps.
+        #[inline]
+        pub unsafe fn write(offset: i64) {
+            let mut msr = Self::MSR;
+            msr.write(offset as u64);
+        }
+    }
+
+    impl FeatureControl {
+        /// Read the current feature control flags.
+        ///
+        /// The register is only available if CPUID leaf 0x1 reports support for VMX (bit 5
+        /// of `ecx`) or SMX (bit 6 of `ecx`).
+        #[inline]
+        pub fn read() -> FeatureControlFlags {
+            FeatureControlFlags::from_bits_truncate(Self::read_raw())
+        }
+
+        /// Read the current raw feature control flags.
+        #[inline]
+        pub fn read_raw() -> u64 {
+            unsafe { Self::MSR.read() }
+        }
+
+        /// Write the feature control flags, preserving reserved values.
+        ///
+        /// Returns an error without writing the register if it is already locked, since the
+        /// write would cause a general protection fault. Set [`FeatureControlFlags::LOCK`]
+        /// together with the enabled features, the CPU refuses to enable e.g. VMX operation
+        /// while the register is unlocked.
+        ///
+        /// ## Safety
+        ///
+        /// Unsafe because enabling features that the CPU doesn't support causes a general
+        /// protection fault.
+        #[inline]
+        pub unsafe fn write(flags: FeatureControlFlags) -> Result<(), &'static str> {
+            let old_value = Self::read_raw();
+            if FeatureControlFlags::from_bits_truncate(old_value)
+                .contains(FeatureControlFlags::LOCK)
+            {
+                return Err("IA32_FEATURE_CONTROL is locked.");
+            }
+            let reserved = old_value & !(FeatureControlFlags::all().bits());
+            let mut msr = Self::MSR;
+            msr.write(reserved | flags.bits());
+            Ok(())
+        }
+
+        /// Update the feature control flags.
+        ///
+        /// Preserves the value of reserved fields. Like [`FeatureControl::write`], this
+        /// returns an error if the register is already locked.
+        ///
+        /// ## Safety
+        ///
+        /// Unsafe because enabling features that the CPU doesn't support causes a general
+        /// protection fault.
+        #[inline]
+        pub unsafe fn update<F>(f: F) -> Result<(), &'static str>
+        where
+            F: FnOnce(&mut FeatureControlFlags),
+        {
+            let mut flags = Self::read();
+            f(&mut flags);
+            Self::write(flags)
+        }
+    }
+
+    /// Reads IA32_APERF and IA32_MPERF and returns them as an `(aperf, mperf)` fraction.
+    ///
+    /// The ratio `aperf / mperf` multiplied with the base frequency of the processor gives
+    /// the average effective frequency since both counters were last reset. To measure a
+    /// specific interval, reset both counters to 0 with [`Aperf::write`] and
+    /// [`Mperf::write`] at its start.
+    ///
+    /// The counters are only available if CPUID leaf 0x6 reports support for them (bit 0
+    /// of `ecx`).
+    #[inline]
+    pub fn effective_frequency_ratio() -> (u64, u64) {
+        let mperf = Mperf::read();
+        let aperf = Aperf::read();
+        (aperf, mperf)
+    }
+
     impl SFMask {
         /// Read to the SFMask register.
         /// The SFMASK register is used to specify which RFLAGS bits
@@ -403,3 +727,18 @@ mod x86_64 {
         }
     }
 }
+
+#[cfg(all(test, feature = "instructions", feature = "mock_msr"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_control_lock() {
+        mock::reset();
+        let flags = FeatureControlFlags::VMX_OUTSIDE_SMX | FeatureControlFlags::LOCK;
+        unsafe { FeatureControl::write(flags) }.unwrap();
+        assert_eq!(FeatureControl::read(), flags);
+        assert!(unsafe { FeatureControl::write(FeatureControlFlags::empty()) }.is_err());
+        assert_eq!(mock::get(FeatureControl::MSR), Some(flags.bits()));
+    }
+}
@@ -62,6 +62,15 @@ bitflags! {
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    RFlags,
+    u64,
+    "valid RFLAGS flags",
+    |flags| flags.bits(),
+    |bits| RFlags::from_bits(bits)
+);
+
 #[cfg(feature = "instructions")]
 mod x86_64 {
     use super::*;
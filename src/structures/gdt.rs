@@ -2,9 +2,13 @@
 
 use crate::structures::tss::TaskStateSegment;
 use crate::PrivilegeLevel;
+use crate::VirtAddr;
 use bit_field::BitField;
 use bitflags::bitflags;
+use core::cell::UnsafeCell;
 use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr;
 
 /// Specifies which element to load into a segment from
 /// descriptor tables (i.e., is a index to LDT or GDT table
@@ -35,6 +39,7 @@ impl SegmentSelector {
     /// Returns the requested privilege level.
     #[inline]
     pub fn rpl(self) -> PrivilegeLevel {
+        // a two bit value is always a valid privilege level, so this can't panic
         PrivilegeLevel::from_u16(self.0.get_bits(0..2))
     }
 
@@ -45,6 +50,15 @@ impl SegmentSelector {
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    SegmentSelector,
+    u16,
+    "a segment selector",
+    |selector| selector.0,
+    |selector| Some(SegmentSelector(selector))
+);
+
 impl fmt::Debug for SegmentSelector {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut s = f.debug_struct("SegmentSelector");
@@ -88,6 +102,21 @@ impl fmt::Debug for SegmentSelector {
 ///
 /// // Add entry for TSS, call gdt.load() then update segment registers
 /// ```
+///
+/// With the `const_fn` feature, `new` and `add_entry` can be used in a `const` context, so a
+/// GDT with code and data segments can be built directly in a `static` initializer, together
+/// with the returned selectors:
+///
+/// ```ignore
+/// static GDT: (GlobalDescriptorTable, SegmentSelector) = {
+///     let mut gdt = GlobalDescriptorTable::new();
+///     let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+///     (gdt, code_selector)
+/// };
+/// ```
+///
+/// TSS descriptors can't be created in a `const` context because they contain the address of
+/// the TSS. Use [`GlobalDescriptorTable::from_raw_slice`] or runtime initialization for them.
 
 #[derive(Debug, Clone)]
 pub struct GlobalDescriptorTable {
@@ -156,11 +185,10 @@ impl GlobalDescriptorTable {
 
             let rpl = match entry {
                 Descriptor::UserSegment(value) => {
-                    if DescriptorFlags::from_bits_truncate(value).contains(DescriptorFlags::DPL_RING_3)
-                    {
-                        PrivilegeLevel::Ring3
-                    } else {
-                        PrivilegeLevel::Ring0
+                    // a two bit value is always a valid privilege level
+                    match PrivilegeLevel::try_from_u16(((value >> 45) & 0b11) as u16) {
+                        Ok(dpl) => dpl,
+                        Err(_) => PrivilegeLevel::Ring0,
                     }
                 }
                 Descriptor::SystemSegment(_, _) => PrivilegeLevel::Ring0,
@@ -219,11 +247,7 @@ impl GlobalDescriptorTable {
     /// safely used if the table is never modified or destroyed while in use.
     #[cfg(feature = "instructions")]
     fn pointer(&self) -> super::DescriptorTablePointer {
-        use core::mem::size_of;
-        super::DescriptorTablePointer {
-            base: crate::VirtAddr::new(self.table.as_ptr() as u64),
-            limit: (self.next_free * size_of::<u64>() - 1) as u16,
-        }
+        super::DescriptorTablePointer::from_gdt(self)
     }
 }
 
@@ -355,20 +379,118 @@ impl Descriptor {
         Descriptor::UserSegment(DescriptorFlags::USER_CODE64.bits())
     }
 
+    const_fn! {
+        /// Creates a 32-bit code segment descriptor, e.g. for running 32-bit processes in
+        /// compatibility mode.
+        ///
+        /// The `limit` is the inclusive offset of the last byte of the segment. If
+        /// `page_granularity` is set, it is stored in units of 4KiB, so its lowest 12 bits must
+        /// all be set. Otherwise it must be below 1MiB. Use
+        /// [`UserSegmentBuilder`] for more control over the descriptor.
+        ///
+        /// Panics if the limit can't be represented.
+        #[inline]
+        pub fn code_segment_32(
+            base: u32,
+            limit: u32,
+            page_granularity: bool,
+            dpl: PrivilegeLevel,
+        ) -> Descriptor {
+            UserSegmentBuilder::code32()
+                .base(base)
+                .byte_limit_with_granularity(limit, page_granularity)
+                .dpl(dpl)
+                .build()
+        }
+    }
+
+    const_fn! {
+        /// Creates a 32-bit data segment descriptor with the given base and inclusive byte
+        /// limit. The limit is validated like for [`code_segment_32`](Self::code_segment_32).
+        ///
+        /// Panics if the limit can't be represented.
+        #[inline]
+        pub fn data_segment_32(
+            base: u32,
+            limit: u32,
+            page_granularity: bool,
+            dpl: PrivilegeLevel,
+        ) -> Descriptor {
+            UserSegmentBuilder::data32()
+                .base(base)
+                .byte_limit_with_granularity(limit, page_granularity)
+                .dpl(dpl)
+                .build()
+        }
+    }
+
+    const_fn! {
+        /// Creates a byte-granular 16-bit code segment descriptor, e.g. for a trampoline
+        /// that switches to real mode.
+        #[inline]
+        pub fn code_segment_16(base: u32, limit: u16, dpl: PrivilegeLevel) -> Descriptor {
+            UserSegmentBuilder::code16()
+                .base(base)
+                .limit(limit as u32)
+                .dpl(dpl)
+                .build()
+        }
+    }
+
+    const_fn! {
+        /// Creates a byte-granular 16-bit data segment descriptor.
+        #[inline]
+        pub fn data_segment_16(base: u32, limit: u16, dpl: PrivilegeLevel) -> Descriptor {
+            UserSegmentBuilder::data16()
+                .base(base)
+                .limit(limit as u32)
+                .dpl(dpl)
+                .build()
+        }
+    }
+
     /// Creates a TSS system descriptor for the given TSS.
     #[inline]
     pub fn tss_segment(tss: &'static TaskStateSegment) -> Descriptor {
-        use self::DescriptorFlags as Flags;
         use core::mem::size_of;
 
-        let ptr = tss as *const _ as u64;
+        // limit (the `-1` in needed since the bound is inclusive)
+        let limit = (size_of::<TaskStateSegment>() - 1) as u32;
+        // SAFETY: the TSS is valid and lives forever
+        unsafe { Self::tss_segment_raw(VirtAddr::from_ptr(tss), limit) }
+    }
+
+    /// Creates a TSS system descriptor for a TSS at the given base address.
+    ///
+    /// The `limit` is the inclusive offset of the last byte of the TSS, i.e. its size minus
+    /// one. It can be larger than the size of [`TaskStateSegment`] if the TSS is followed by
+    /// an I/O permission bitmap.
+    ///
+    /// This is useful for TSSs that are not `'static`, e.g. per-CPU TSSs allocated at runtime.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `base` points to a valid TSS of at least `limit + 1` bytes
+    /// and that the TSS stays valid for as long as the descriptor is in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` does not fit into 20 bits.
+    #[inline]
+    pub unsafe fn tss_segment_raw(base: VirtAddr, limit: u32) -> Descriptor {
+        use self::DescriptorFlags as Flags;
+
+        assert!(limit < (1 << 20), "TSS limit must fit into 20 bits");
+        let ptr = base.as_u64();
+        let limit = u64::from(limit);
 
         let mut low = Flags::PRESENT.bits();
         // base
         low.set_bits(16..40, ptr.get_bits(0..24));
         low.set_bits(56..64, ptr.get_bits(24..32));
-        // limit (the `-1` in needed since the bound is inclusive)
-        low.set_bits(0..16, (size_of::<TaskStateSegment>() - 1) as u64);
+        // limit
+        low.set_bits(0..16, limit.get_bits(0..16));
+        low.set_bits(48..52, limit.get_bits(16..20));
         // type (0b1001 = available 64-bit tss)
         low.set_bits(40..44, 0b1001);
 
@@ -379,9 +501,595 @@ impl Descriptor {
     }
 }
 
+/// A builder for code and data segment descriptors with an explicit base, limit and size.
+///
+/// The presets of [`Descriptor`] only cover flat 64-bit and 32-bit segments. This builder can
+/// additionally create 16-bit segments (e.g. for virtual-8086 or real mode emulation) and
+/// expand-down data segments, and takes care of splitting the base and limit across the
+/// descriptor fields. Like the presets, the built descriptors have the
+/// [`ACCESSED`][DescriptorFlags::ACCESSED] flag set.
+///
+/// ```
+/// use x86_64::structures::gdt::UserSegmentBuilder;
+/// use x86_64::PrivilegeLevel;
+///
+/// let code = UserSegmentBuilder::code16()
+///     .base(0xf0000)
+///     .dpl(PrivilegeLevel::Ring3)
+///     .build();
+/// let stack = UserSegmentBuilder::data32().expand_down().byte_limit(0xffff).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserSegmentBuilder {
+    flags: DescriptorFlags,
+    base: u32,
+    limit: u32,
+}
+
+impl UserSegmentBuilder {
+    /// The largest value of the 20-bit limit field.
+    const MAX_LIMIT: u32 = 0xF_FFFF;
+
+    const fn new(flags: DescriptorFlags, limit: u32) -> Self {
+        UserSegmentBuilder {
+            flags: DescriptorFlags::from_bits_truncate(
+                flags.bits()
+                    | DescriptorFlags::USER_SEGMENT.bits()
+                    | DescriptorFlags::PRESENT.bits()
+                    | DescriptorFlags::WRITABLE.bits()
+                    | DescriptorFlags::ACCESSED.bits(),
+            ),
+            base: 0,
+            limit,
+        }
+    }
+
+    /// A readable 16-bit code segment with base 0 and a limit of 64KiB.
+    #[inline]
+    pub const fn code16() -> Self {
+        Self::new(DescriptorFlags::EXECUTABLE, 0xFFFF)
+    }
+
+    /// A writable 16-bit data segment with base 0 and a limit of 64KiB.
+    #[inline]
+    pub const fn data16() -> Self {
+        Self::new(DescriptorFlags::empty(), 0xFFFF)
+    }
+
+    /// A readable 32-bit code segment that spans the whole 4GiB address space.
+    #[inline]
+    pub const fn code32() -> Self {
+        Self::new(
+            DescriptorFlags::from_bits_truncate(
+                DescriptorFlags::EXECUTABLE.bits()
+                    | DescriptorFlags::DEFAULT_SIZE.bits()
+                    | DescriptorFlags::GRANULARITY.bits(),
+            ),
+            Self::MAX_LIMIT,
+        )
+    }
+
+    /// A writable 32-bit data segment that spans the whole 4GiB address space.
+    #[inline]
+    pub const fn data32() -> Self {
+        Self::new(
+            DescriptorFlags::from_bits_truncate(
+                DescriptorFlags::DEFAULT_SIZE.bits() | DescriptorFlags::GRANULARITY.bits(),
+            ),
+            Self::MAX_LIMIT,
+        )
+    }
+
+    /// Sets the base address of the segment.
+    #[inline]
+    pub const fn base(mut self, base: u32) -> Self {
+        self.base = base;
+        self
+    }
+
+    const_fn! {
+        /// Sets the raw 20-bit limit field, which is interpreted in units of 4KiB if
+        /// [`page_granularity`](Self::page_granularity) is set and in bytes otherwise.
+        ///
+        /// Panics if `limit` does not fit into 20 bits.
+        #[inline]
+        pub fn limit(mut self, limit: u32) -> Self {
+            assert!(limit <= Self::MAX_LIMIT, "segment limit must fit into 20 bits");
+            self.limit = limit;
+            self
+        }
+    }
+
+    /// Sets whether the limit field is scaled by 4KiB.
+    #[inline]
+    pub const fn page_granularity(mut self, enabled: bool) -> Self {
+        self.flags = self.with(DescriptorFlags::GRANULARITY, enabled);
+        self
+    }
+
+    const_fn! {
+        /// Sets the limit to the given inclusive byte offset and chooses the granularity.
+        ///
+        /// Limits below 1MiB are stored with byte granularity. Larger limits are stored in
+        /// units of 4KiB, so their lowest 12 bits must all be set.
+        ///
+        /// Panics if the limit can't be represented.
+        #[inline]
+        pub fn byte_limit(self, limit: u32) -> Self {
+            self.byte_limit_with_granularity(limit, limit > Self::MAX_LIMIT)
+        }
+    }
+
+    const_fn! {
+        /// Sets the limit to the given inclusive byte offset, stored in units of 4KiB if
+        /// `page_granularity` is set and in bytes otherwise.
+        ///
+        /// Panics if the limit can't be represented with the given granularity.
+        #[inline]
+        fn byte_limit_with_granularity(self, limit: u32, page_granularity: bool) -> Self {
+            if page_granularity {
+                assert!(limit & 0xFFF == 0xFFF, "segment limit is not representable");
+                self.page_granularity(true).limit(limit >> 12)
+            } else {
+                assert!(
+                    limit <= Self::MAX_LIMIT,
+                    "segment limits above 1MiB require page granularity"
+                );
+                self.page_granularity(false).limit(limit)
+            }
+        }
+    }
+
+    const_fn! {
+        /// Makes this data segment expand-down, so that the valid offsets are the ones above
+        /// the limit instead of the ones up to it. This is typically used for stack segments.
+        ///
+        /// Panics if this is a code segment, since the bit means "conforming" for them.
+        #[inline]
+        pub fn expand_down(mut self) -> Self {
+            assert!(
+                !self.flags.contains(DescriptorFlags::EXECUTABLE),
+                "code segments can't be expand-down"
+            );
+            self.flags = self.with(DescriptorFlags::CONFORMING, true);
+            self
+        }
+    }
+
+    const_fn! {
+        /// Makes this code segment conforming.
+        ///
+        /// Panics if this is a data segment, since the bit means "expand-down" for them.
+        #[inline]
+        pub fn conforming(mut self) -> Self {
+            assert!(
+                self.flags.contains(DescriptorFlags::EXECUTABLE),
+                "data segments can't be conforming"
+            );
+            self.flags = self.with(DescriptorFlags::CONFORMING, true);
+            self
+        }
+    }
+
+    /// Sets the descriptor privilege level of the segment.
+    #[inline]
+    pub const fn dpl(mut self, dpl: PrivilegeLevel) -> Self {
+        self.flags = DescriptorFlags::from_bits_truncate(
+            (self.flags.bits() & !DescriptorFlags::DPL_RING_3.bits()) | ((dpl as u64) << 45),
+        );
+        self
+    }
+
+    /// Creates the segment descriptor.
+    #[inline]
+    pub const fn build(self) -> Descriptor {
+        let base = self.base as u64;
+        let limit = self.limit as u64;
+        Descriptor::UserSegment(
+            self.flags.bits()
+                | (limit & 0xFFFF)
+                | ((limit >> 16) << 48)
+                | ((base & 0xFF_FFFF) << 16)
+                | ((base >> 24) << 56),
+        )
+    }
+
+    const fn with(self, flag: DescriptorFlags, enabled: bool) -> DescriptorFlags {
+        if enabled {
+            DescriptorFlags::from_bits_truncate(self.flags.bits() | flag.bits())
+        } else {
+            DescriptorFlags::from_bits_truncate(self.flags.bits() & !flag.bits())
+        }
+    }
+}
+
+/// The configuration of a [`CpuDescriptorSet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuDescriptorConfig {
+    /// Whether to add ring 3 segments, laid out as required by `sysret`.
+    pub user_segments: bool,
+    /// The stack that the CPU switches to when an interrupt occurs in ring 3, stored as the
+    /// first entry of the privilege stack table of the TSS.
+    pub privilege_stack_top: VirtAddr,
+    /// The tops of the interrupt stacks, stored in the interrupt stack table of the TSS.
+    ///
+    /// Zero addresses mark unused entries.
+    pub interrupt_stack_tops: [VirtAddr; 7],
+}
+
+impl CpuDescriptorConfig {
+    /// Creates a configuration with user segments and without any stacks.
+    #[inline]
+    pub const fn new() -> Self {
+        CpuDescriptorConfig {
+            user_segments: true,
+            privilege_stack_top: VirtAddr::zero(),
+            interrupt_stack_tops: [VirtAddr::zero(); 7],
+        }
+    }
+}
+
+impl Default for CpuDescriptorConfig {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The segment selectors of a [`CpuDescriptorSet`].
+///
+/// The user selectors are `None` if the set has no user segments. Otherwise the selectors
+/// can be passed to [`Star::write`](crate::registers::model_specific::Star::write) as
+/// `(user_code, user_data, kernel_code, kernel_data)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuSelectors {
+    /// The 64-bit kernel code segment.
+    pub kernel_code: SegmentSelector,
+    /// The kernel data segment, which is also used as stack segment.
+    pub kernel_data: SegmentSelector,
+    /// The 32-bit user code segment for compatibility mode, selected by `sysret` without
+    /// a REX.W prefix.
+    pub user_code32: Option<SegmentSelector>,
+    /// The user data segment, which is also used as stack segment.
+    pub user_data: Option<SegmentSelector>,
+    /// The 64-bit user code segment.
+    pub user_code: Option<SegmentSelector>,
+    /// The TSS segment.
+    pub tss: SegmentSelector,
+}
+
+/// The GDT and the TSS of a CPU.
+///
+/// This combines the individual steps of setting up segmentation on a CPU: the TSS is
+/// initialized with the stacks of the [`CpuDescriptorConfig`], the GDT contains the kernel
+/// segments, the optional user segments and the TSS descriptor, and
+/// [`load`](CpuDescriptorSet::load) loads them in the required order.
+///
+/// The set is created in place by [`new_in`](CpuDescriptorSet::new_in), since the TSS
+/// descriptor contains the final address of the TSS. After loading, the set stays accessible
+/// through the returned shared reference, e.g. for updating the privilege stack on context
+/// switches.
+///
+/// ```no_run
+/// use core::mem::MaybeUninit;
+/// use x86_64::structures::gdt::{CpuDescriptorConfig, CpuDescriptorSet};
+/// use x86_64::VirtAddr;
+///
+/// # fn allocate_stack() -> VirtAddr { unimplemented!() }
+/// let mut config = CpuDescriptorConfig::new();
+/// config.privilege_stack_top = allocate_stack();
+/// config.interrupt_stack_tops[0] = allocate_stack();
+/// // e.g. allocated per CPU at runtime
+/// let set = CpuDescriptorSet::new_in(Box::leak(Box::new(MaybeUninit::uninit())), config);
+/// # #[cfg(feature = "instructions")]
+/// # {
+/// let selectors = set.load();
+/// # }
+///
+/// // on a context switch
+/// unsafe { set.set_privilege_stack_top(allocate_stack()) };
+/// ```
+pub struct CpuDescriptorSet {
+    gdt: GlobalDescriptorTable,
+    // the CPU reads the TSS on privilege changes while the set is shared
+    tss: UnsafeCell<TaskStateSegment>,
+    selectors: CpuSelectors,
+}
+
+impl CpuDescriptorSet {
+    /// Creates the GDT and the TSS for the given configuration in the given place.
+    ///
+    /// The TSS descriptor of the GDT points to the TSS in `place`, so the set is ready for
+    /// [`load`](CpuDescriptorSet::load).
+    pub fn new_in(
+        place: &'static mut MaybeUninit<Self>,
+        config: CpuDescriptorConfig,
+    ) -> &'static Self {
+        *place = MaybeUninit::new(Self::unplaced(config));
+        // SAFETY: `place` was initialized above
+        let set = unsafe { &mut *place.as_mut_ptr() };
+        set.write_tss_descriptor();
+        set
+    }
+
+    /// Creates the set with a placeholder for the TSS descriptor, which depends on the
+    /// final address of the TSS.
+    fn unplaced(config: CpuDescriptorConfig) -> Self {
+        use core::mem::size_of;
+
+        let mut tss = TaskStateSegment::new();
+        tss.privilege_stack_table[0] = config.privilege_stack_top;
+        tss.interrupt_stack_table = config.interrupt_stack_tops;
+        // place the I/O permission bitmap outside of the TSS limit, to deny access to all
+        // ports in ring 3
+        tss.iomap_base = size_of::<TaskStateSegment>() as u16;
+
+        let mut gdt = GlobalDescriptorTable::new();
+        let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let (user_code32, user_data, user_code) = if config.user_segments {
+            // `sysret` expects the 32-bit code segment, the data segment and the 64-bit code
+            // segment in this order
+            let user_code32 =
+                gdt.add_entry(Descriptor::UserSegment(DescriptorFlags::USER_CODE32.bits()));
+            let user_data = gdt.add_entry(Descriptor::user_data_segment());
+            let user_code = gdt.add_entry(Descriptor::user_code_segment());
+            (Some(user_code32), Some(user_data), Some(user_code))
+        } else {
+            (None, None, None)
+        };
+        let tss_selector = gdt.add_entry(Descriptor::SystemSegment(0, 0));
+
+        CpuDescriptorSet {
+            gdt,
+            tss: UnsafeCell::new(tss),
+            selectors: CpuSelectors {
+                kernel_code,
+                kernel_data,
+                user_code32,
+                user_data,
+                user_code,
+                tss: tss_selector,
+            },
+        }
+    }
+
+    /// Returns the GDT.
+    #[inline]
+    pub fn gdt(&self) -> &GlobalDescriptorTable {
+        &self.gdt
+    }
+
+    /// Returns the TSS.
+    #[inline]
+    pub fn tss(&self) -> &TaskStateSegment {
+        // SAFETY: the TSS is only modified by `set_privilege_stack_top`, whose caller ensures
+        // that the returned references aren't used across the modification
+        unsafe { &*self.tss.get() }
+    }
+
+    /// Returns the segment selectors.
+    #[inline]
+    pub fn selectors(&self) -> CpuSelectors {
+        self.selectors
+    }
+
+    /// Sets the stack that the CPU switches to when an interrupt occurs in ring 3, e.g. to
+    /// the kernel stack of the next thread on a context switch.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must ensure that `stack_top` is the top of a valid stack and that no
+    /// reference returned by [`tss`](CpuDescriptorSet::tss) is used across this call.
+    /// The set must not be accessed concurrently, e.g. by another CPU.
+    #[inline]
+    pub unsafe fn set_privilege_stack_top(&self, stack_top: VirtAddr) {
+        let stack_table = ptr::addr_of_mut!((*self.tss.get()).privilege_stack_table);
+        // the TSS is packed, so the entry might be unaligned
+        (stack_table as *mut VirtAddr).write_unaligned(stack_top);
+    }
+
+    /// Loads the GDT, reloads `cs`, `ss`, `ds` and `es` with the kernel segments and loads the
+    /// TSS, in this order. `fs` and `gs` are not changed, since loading them would reset
+    /// their base addresses.
+    #[cfg(feature = "instructions")]
+    pub fn load(&'static self) -> CpuSelectors {
+        use crate::instructions::segmentation::{load_ds, load_es, load_ss, set_cs};
+        use crate::instructions::tables::load_tss;
+
+        self.gdt.load();
+        // SAFETY: the selectors point to valid segments of the loaded GDT
+        unsafe {
+            set_cs(self.selectors.kernel_code);
+            load_ss(self.selectors.kernel_data);
+            load_ds(self.selectors.kernel_data);
+            load_es(self.selectors.kernel_data);
+            load_tss(self.selectors.tss);
+        }
+        self.selectors
+    }
+
+    fn write_tss_descriptor(&mut self) {
+        // SAFETY: the TSS lives as long as the GDT, which contains the descriptor
+        let descriptor = unsafe {
+            Descriptor::tss_segment_raw(
+                VirtAddr::from_ptr(self.tss.get()),
+                (core::mem::size_of::<TaskStateSegment>() - 1) as u32,
+            )
+        };
+        if let Descriptor::SystemSegment(low, high) = descriptor {
+            let index = usize::from(self.selectors.tss.index());
+            self.gdt.table[index] = low;
+            self.gdt.table[index + 1] = high;
+        }
+    }
+}
+
+impl fmt::Debug for CpuDescriptorSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CpuDescriptorSet")
+            .field("gdt", &self.gdt)
+            .field("tss", self.tss())
+            .field("selectors", &self.selectors)
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::DescriptorFlags as Flags;
+    #[cfg(feature = "const_fn")]
+    use super::*;
+
+    #[cfg(feature = "const_fn")]
+    #[test]
+    pub fn const_gdt() {
+        static GDT: (GlobalDescriptorTable, SegmentSelector, SegmentSelector) = {
+            let mut gdt = GlobalDescriptorTable::new();
+            let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+            let user_selector = gdt.add_entry(Descriptor::user_code_segment());
+            (gdt, code_selector, user_selector)
+        };
+
+        assert_eq!(
+            GDT.0.as_raw_slice(),
+            [0, Flags::KERNEL_CODE64.bits(), Flags::USER_CODE64.bits()]
+        );
+        assert_eq!(GDT.1, SegmentSelector::new(1, PrivilegeLevel::Ring0));
+        assert_eq!(GDT.2, SegmentSelector::new(2, PrivilegeLevel::Ring3));
+    }
+
+    #[test]
+    pub fn descriptor_table_pointer() {
+        use crate::structures::DescriptorTablePointer;
+
+        let mut gdt = super::GlobalDescriptorTable::new();
+        gdt.add_entry(super::Descriptor::kernel_code_segment());
+        let pointer = DescriptorTablePointer::from_gdt(&gdt);
+        assert_eq!({ pointer.limit }, 15);
+        assert_eq!(pointer.entries_len(8), 2);
+        assert_eq!(pointer, DescriptorTablePointer::from_gdt(&gdt));
+    }
+
+    #[test]
+    pub fn user_segment_builder() {
+        use super::{Descriptor, UserSegmentBuilder};
+
+        let raw = |descriptor| match descriptor {
+            Descriptor::UserSegment(value) => value,
+            Descriptor::SystemSegment(_, _) => unreachable!(),
+        };
+        // the builder agrees with the flat presets
+        assert_eq!(
+            raw(UserSegmentBuilder::code32().build()),
+            Flags::KERNEL_CODE32.bits()
+        );
+        assert_eq!(
+            raw(UserSegmentBuilder::data32()
+                .dpl(crate::PrivilegeLevel::Ring3)
+                .build()),
+            Flags::USER_DATA.bits()
+        );
+
+        assert_eq!(
+            raw(UserSegmentBuilder::code16().base(0x1234_5678).build()),
+            0x1200_9B34_5678_FFFF
+        );
+        let stack = raw(UserSegmentBuilder::data16()
+            .expand_down()
+            .byte_limit(0x10_0FFF)
+            .build());
+        assert_eq!(stack, 0x0080_9700_0000_0100);
+
+        let mut gdt = super::GlobalDescriptorTable::new();
+        let selector = gdt.add_entry(
+            UserSegmentBuilder::data16()
+                .dpl(crate::PrivilegeLevel::Ring1)
+                .build(),
+        );
+        assert_eq!(selector.rpl(), crate::PrivilegeLevel::Ring1);
+    }
+
+    #[test]
+    pub fn cpu_descriptor_set() {
+        use super::{CpuDescriptorConfig, CpuDescriptorSet};
+        use crate::{PrivilegeLevel, VirtAddr};
+        use std::boxed::Box;
+        use std::mem::MaybeUninit;
+
+        let mut config = CpuDescriptorConfig::new();
+        config.interrupt_stack_tops[0] = VirtAddr::new(0x1000);
+        let set = CpuDescriptorSet::new_in(Box::leak(Box::new(MaybeUninit::uninit())), config);
+        let selectors = set.selectors();
+        assert_eq!(selectors.kernel_code.0, 0x08);
+        assert_eq!(selectors.kernel_data.0, 0x10);
+        // `Star::write` requires these offsets
+        let (user_code, user_data) = (selectors.user_code.unwrap(), selectors.user_data.unwrap());
+        assert_eq!(user_code.0 - 16, user_data.0 - 8);
+        assert_eq!(selectors.user_code32.unwrap().0, user_data.0 - 8);
+        assert_eq!(user_data.rpl(), PrivilegeLevel::Ring3);
+        assert_eq!(selectors.tss.0, 6 << 3);
+        assert_eq!(
+            { set.tss().interrupt_stack_table }[0],
+            VirtAddr::new(0x1000)
+        );
+
+        // the TSS descriptor points to the placed TSS
+        let tss_addr = VirtAddr::from_ptr(set.tss()).as_u64();
+        let raw = set.gdt().as_raw_slice();
+        assert_eq!(raw.len(), 8);
+        assert_eq!(raw[6] & 0xFFFF, 0x67);
+        assert_eq!(raw[6] >> 16 & 0xFF_FFFF, tss_addr & 0xFF_FFFF);
+        assert_eq!(raw[7], tss_addr >> 32);
+
+        unsafe { set.set_privilege_stack_top(VirtAddr::new(0x2000)) };
+        assert_eq!(
+            { set.tss().privilege_stack_table }[0],
+            VirtAddr::new(0x2000)
+        );
+
+        config.user_segments = false;
+        let set = CpuDescriptorSet::new_in(Box::leak(Box::new(MaybeUninit::uninit())), config);
+        assert_eq!(set.selectors().user_code, None);
+        assert_eq!(set.selectors().tss.0, 3 << 3);
+        assert_eq!(set.gdt().as_raw_slice().len(), 5);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    pub fn legacy_segments() {
+        use super::Descriptor;
+        use crate::PrivilegeLevel::{Ring0, Ring3};
+
+        let raw = |descriptor| match descriptor {
+            Descriptor::UserSegment(value) => value,
+            Descriptor::SystemSegment(_, _) => unreachable!(),
+        };
+        // The PnP BIOS segments of the Linux kernel (arch/x86/kernel/cpu/common.c), with
+        // the accessed bit set
+        assert_eq!(raw(Descriptor::code_segment_32(0, 0xffff, false, Ring0)), 0x00409b000000ffff);
+        assert_eq!(raw(Descriptor::code_segment_16(0, 0xffff, Ring0)),        0x00009b000000ffff);
+        assert_eq!(raw(Descriptor::data_segment_16(0, 0xffff, Ring0)),        0x000093000000ffff);
+        // The flat segments match the presets
+        assert_eq!(
+            raw(Descriptor::code_segment_32(0, u32::MAX, true, Ring3)),
+            Flags::USER_CODE32.bits()
+        );
+        assert_eq!(
+            raw(Descriptor::data_segment_32(0, u32::MAX, true, Ring0)),
+            Flags::KERNEL_DATA.bits()
+        );
+        assert_eq!(
+            raw(Descriptor::data_segment_32(0x1234_5678, 0x1f_ffff, true, Ring0)),
+            0x12c0_9334_5678_01ff
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn legacy_segment_limit_requires_granularity() {
+        super::Descriptor::code_segment_32(0, 0x10_0000, false, crate::PrivilegeLevel::Ring0);
+    }
 
     #[test]
     #[rustfmt::skip]
@@ -9,9 +9,12 @@
 
 //! Provides types for the Interrupt Descriptor Table and its entries.
 
+use crate::registers::rflags::RFlags;
+use crate::structures::gdt::SegmentSelector;
 use crate::{PrivilegeLevel, VirtAddr};
 use bit_field::BitField;
 use bitflags::bitflags;
+use core::convert::TryFrom;
 use core::fmt;
 use core::marker::PhantomData;
 use core::ops::Bound::{Excluded, Included, Unbounded};
@@ -370,9 +373,29 @@ pub struct InterruptDescriptorTable {
     interrupts: [Entry<HandlerFunc>; 256 - 32],
 }
 
+impl Default for InterruptDescriptorTable {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InterruptDescriptorTable {
     const_fn! {
         /// Creates a new IDT filled with non-present entries.
+        ///
+        /// With the `const_fn` feature, this function can be used in a `const` context, e.g. to
+        /// initialize a `static mut` or a `static` cell type without lazy initialization:
+        ///
+        /// ```ignore
+        /// static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+        /// ```
+        ///
+        /// The handler functions can only be set at runtime because their addresses are not
+        /// known in a `const` context. They must be installed before the table is loaded
+        /// through [`load`](InterruptDescriptorTable::load), since the CPU would otherwise
+        /// raise a general protection fault (and likely a double fault) for any interrupt
+        /// that arrives in between.
         #[inline]
         pub fn new() -> InterruptDescriptorTable {
             InterruptDescriptorTable {
@@ -405,12 +428,33 @@ impl InterruptDescriptorTable {
         }
     }
 
-    /// Resets all entries of this IDT in place.
+    /// Resets all entries of this IDT in place, i.e. marks them as not present.
+    ///
+    /// This allows reusing the same table for a different set of handlers instead of
+    /// creating a second IDT, e.g. when switching from early boot handlers to the final ones.
+    ///
+    /// The CPU reads the entries from memory when an interrupt occurs, so changes to the
+    /// currently loaded IDT take effect immediately. Interrupts should thus be disabled while
+    /// the table is rebuilt. If the table is not the active one, it must be loaded through
+    /// [`load`](InterruptDescriptorTable::load) (i.e. `lidt`) after the reconfiguration.
     #[inline]
     pub fn reset(&mut self) {
         *self = Self::new();
     }
 
+    /// Resets the entries of the user interrupts 32 to 255 in place and keeps the exception
+    /// handlers.
+    ///
+    /// This is useful for replacing the handlers of external interrupts, e.g. when switching
+    /// from the legacy PIC to the APIC. See [`reset`](InterruptDescriptorTable::reset) for
+    /// when the table needs to be reloaded.
+    #[inline]
+    pub fn reset_interrupts(&mut self) {
+        for entry in self.interrupts.iter_mut() {
+            *entry = Entry::missing();
+        }
+    }
+
     /// Loads the IDT in the CPU using the `lidt` command.
     #[cfg(feature = "instructions")]
     #[inline]
@@ -439,11 +483,68 @@ impl InterruptDescriptorTable {
     /// safely used if the table is never modified or destroyed while in use.
     #[cfg(feature = "instructions")]
     fn pointer(&self) -> crate::structures::DescriptorTablePointer {
-        use core::mem::size_of;
-        crate::structures::DescriptorTablePointer {
-            base: VirtAddr::new(self as *const _ as u64),
-            limit: (size_of::<Self>() - 1) as u16,
+        crate::structures::DescriptorTablePointer::from_idt(self)
+    }
+
+    /// Sets the handler function for the given interrupt vector and sets the present bit.
+    ///
+    /// This allows configuring exceptions and interrupts in a loop, e.g. from a table. The
+    /// handler type must match the vector: exceptions that push an error code need a
+    /// `WithErrCode` handler, the page fault a `PageFault` handler, and the double fault and
+    /// machine check exceptions a diverging handler. All other vectors, including the user
+    /// interrupts 32 to 255, need a `Normal` handler.
+    ///
+    /// Returns an error if the vector is reserved or if the handler type doesn't match.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # #![cfg_attr(feature = "abi_x86_interrupt", feature(abi_x86_interrupt))]
+    /// # #[cfg(feature = "abi_x86_interrupt")]
+    /// # fn main() {
+    /// use x86_64::structures::idt::{InterruptDescriptorTable, InterruptHandler, InterruptStackFrame};
+    ///
+    /// extern "x86-interrupt" fn handler(_frame: InterruptStackFrame) {}
+    ///
+    /// let mut idt = InterruptDescriptorTable::new();
+    /// for vector in 32..=255 {
+    ///     idt.set_handler(vector, InterruptHandler::Normal(handler)).unwrap();
+    /// }
+    /// # }
+    /// # #[cfg(not(feature = "abi_x86_interrupt"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "instructions")]
+    pub fn set_handler(
+        &mut self,
+        vector: u8,
+        handler: InterruptHandler,
+    ) -> Result<&mut EntryOptions, SetHandlerError> {
+        use InterruptHandler::*;
+
+        let expected = match ExceptionVector::try_from(vector) {
+            Ok(exception) => exception.handler_kind(),
+            Err(InvalidExceptionVector(32..=255)) => HandlerKind::Normal,
+            Err(_) => return Err(SetHandlerError::Reserved(vector)),
+        };
+        if handler.kind() != expected {
+            return Err(SetHandlerError::HandlerTypeMismatch(vector));
         }
+
+        let options = match (vector, handler) {
+            (8, DivergingWithErrCode(handler)) => self.double_fault.set_handler_fn(handler),
+            (10, WithErrCode(handler)) => self.invalid_tss.set_handler_fn(handler),
+            (11, WithErrCode(handler)) => self.segment_not_present.set_handler_fn(handler),
+            (12, WithErrCode(handler)) => self.stack_segment_fault.set_handler_fn(handler),
+            (13, WithErrCode(handler)) => self.general_protection_fault.set_handler_fn(handler),
+            (14, PageFault(handler)) => self.page_fault.set_handler_fn(handler),
+            (17, WithErrCode(handler)) => self.alignment_check.set_handler_fn(handler),
+            (18, Diverging(handler)) => self.machine_check.set_handler_fn(handler),
+            (30, WithErrCode(handler)) => self.security_exception.set_handler_fn(handler),
+            (vector, Normal(handler)) => self[usize::from(vector)].set_handler_fn(handler),
+            _ => unreachable!("handler kind was checked above"),
+        };
+        Ok(options)
     }
 
     /// Returns a normalized and ranged check slice range from a RangeBounds trait object
@@ -556,6 +657,295 @@ impl IndexMut<usize> for InterruptDescriptorTable {
     }
 }
 
+/// The architecturally defined exceptions, i.e. the IDT vectors 0 to 31 that are not reserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum ExceptionVector {
+    /// Divide error (`#DE`), see [`InterruptDescriptorTable::divide_error`].
+    DivideError = 0,
+    /// Debug exception (`#DB`), see [`InterruptDescriptorTable::debug`].
+    Debug = 1,
+    /// Non-maskable interrupt, see [`InterruptDescriptorTable::non_maskable_interrupt`].
+    NonMaskableInterrupt = 2,
+    /// Breakpoint (`#BP`), see [`InterruptDescriptorTable::breakpoint`].
+    Breakpoint = 3,
+    /// Overflow (`#OF`), see [`InterruptDescriptorTable::overflow`].
+    Overflow = 4,
+    /// Bound range exceeded (`#BR`), see [`InterruptDescriptorTable::bound_range_exceeded`].
+    BoundRangeExceeded = 5,
+    /// Invalid opcode (`#UD`), see [`InterruptDescriptorTable::invalid_opcode`].
+    InvalidOpcode = 6,
+    /// Device not available (`#NM`), see [`InterruptDescriptorTable::device_not_available`].
+    DeviceNotAvailable = 7,
+    /// Double fault (`#DF`), see [`InterruptDescriptorTable::double_fault`].
+    DoubleFault = 8,
+    /// Coprocessor segment overrun, a reserved vector that current processors don't raise.
+    CoprocessorSegmentOverrun = 9,
+    /// Invalid TSS (`#TS`), see [`InterruptDescriptorTable::invalid_tss`].
+    InvalidTss = 10,
+    /// Segment not present (`#NP`), see [`InterruptDescriptorTable::segment_not_present`].
+    SegmentNotPresent = 11,
+    /// Stack fault (`#SS`), see [`InterruptDescriptorTable::stack_segment_fault`].
+    StackSegmentFault = 12,
+    /// General protection fault (`#GP`), see
+    /// [`InterruptDescriptorTable::general_protection_fault`].
+    GeneralProtectionFault = 13,
+    /// Page fault (`#PF`), see [`InterruptDescriptorTable::page_fault`].
+    PageFault = 14,
+    /// x87 floating-point exception (`#MF`), see
+    /// [`InterruptDescriptorTable::x87_floating_point`].
+    X87FloatingPoint = 16,
+    /// Alignment check (`#AC`), see [`InterruptDescriptorTable::alignment_check`].
+    AlignmentCheck = 17,
+    /// Machine check (`#MC`), see [`InterruptDescriptorTable::machine_check`].
+    MachineCheck = 18,
+    /// SIMD floating-point exception (`#XM`), see
+    /// [`InterruptDescriptorTable::simd_floating_point`].
+    SimdFloatingPoint = 19,
+    /// Virtualization exception (`#VE`), see [`InterruptDescriptorTable::virtualization`].
+    Virtualization = 20,
+    /// Security exception (`#SX`), see [`InterruptDescriptorTable::security_exception`].
+    SecurityException = 30,
+}
+
+impl ExceptionVector {
+    /// Returns whether the CPU pushes an error code for this exception.
+    #[inline]
+    pub fn has_error_code(self) -> bool {
+        matches!(
+            self,
+            ExceptionVector::DoubleFault
+                | ExceptionVector::InvalidTss
+                | ExceptionVector::SegmentNotPresent
+                | ExceptionVector::StackSegmentFault
+                | ExceptionVector::GeneralProtectionFault
+                | ExceptionVector::PageFault
+                | ExceptionVector::AlignmentCheck
+                | ExceptionVector::SecurityException
+        )
+    }
+
+    /// Returns whether the handler for this exception must not return.
+    #[inline]
+    pub fn is_diverging(self) -> bool {
+        matches!(
+            self,
+            ExceptionVector::DoubleFault | ExceptionVector::MachineCheck
+        )
+    }
+
+    #[cfg(feature = "instructions")]
+    fn handler_kind(self) -> HandlerKind {
+        match self {
+            ExceptionVector::PageFault => HandlerKind::PageFault,
+            _ => match (self.has_error_code(), self.is_diverging()) {
+                (false, false) => HandlerKind::Normal,
+                (true, false) => HandlerKind::WithErrCode,
+                (false, true) => HandlerKind::Diverging,
+                (true, true) => HandlerKind::DivergingWithErrCode,
+            },
+        }
+    }
+}
+
+impl From<ExceptionVector> for u8 {
+    #[inline]
+    fn from(vector: ExceptionVector) -> Self {
+        vector as u8
+    }
+}
+
+impl TryFrom<u8> for ExceptionVector {
+    type Error = InvalidExceptionVector;
+
+    #[inline]
+    fn try_from(vector: u8) -> Result<Self, Self::Error> {
+        use ExceptionVector::*;
+
+        Ok(match vector {
+            0 => DivideError,
+            1 => Debug,
+            2 => NonMaskableInterrupt,
+            3 => Breakpoint,
+            4 => Overflow,
+            5 => BoundRangeExceeded,
+            6 => InvalidOpcode,
+            7 => DeviceNotAvailable,
+            8 => DoubleFault,
+            9 => CoprocessorSegmentOverrun,
+            10 => InvalidTss,
+            11 => SegmentNotPresent,
+            12 => StackSegmentFault,
+            13 => GeneralProtectionFault,
+            14 => PageFault,
+            16 => X87FloatingPoint,
+            17 => AlignmentCheck,
+            18 => MachineCheck,
+            19 => SimdFloatingPoint,
+            20 => Virtualization,
+            30 => SecurityException,
+            other => return Err(InvalidExceptionVector(other)),
+        })
+    }
+}
+
+/// The given vector is not an architecturally defined exception, i.e. it is reserved or a
+/// user interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidExceptionVector(pub u8);
+
+/// A handler function of any type, for [`InterruptDescriptorTable::set_handler`].
+#[derive(Debug, Clone, Copy)]
+pub enum InterruptHandler {
+    /// A handler for an interrupt or an exception without error code.
+    Normal(HandlerFunc),
+    /// A handler for an exception that pushes an error code.
+    WithErrCode(HandlerFuncWithErrCode),
+    /// A handler for the page fault exception.
+    PageFault(PageFaultHandlerFunc),
+    /// A handler that must not return, for the machine check exception.
+    Diverging(DivergingHandlerFunc),
+    /// A handler with an error code that must not return, for the double fault exception.
+    DivergingWithErrCode(DivergingHandlerFuncWithErrCode),
+}
+
+#[cfg(feature = "instructions")]
+impl InterruptHandler {
+    fn kind(self) -> HandlerKind {
+        match self {
+            InterruptHandler::Normal(_) => HandlerKind::Normal,
+            InterruptHandler::WithErrCode(_) => HandlerKind::WithErrCode,
+            InterruptHandler::PageFault(_) => HandlerKind::PageFault,
+            InterruptHandler::Diverging(_) => HandlerKind::Diverging,
+            InterruptHandler::DivergingWithErrCode(_) => HandlerKind::DivergingWithErrCode,
+        }
+    }
+}
+
+/// The type of handler the CPU expects for a vector.
+#[cfg(feature = "instructions")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandlerKind {
+    Normal,
+    WithErrCode,
+    PageFault,
+    Diverging,
+    DivergingWithErrCode,
+}
+
+/// This error is returned from [`InterruptDescriptorTable::set_handler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetHandlerError {
+    /// The vector is reserved by the architecture.
+    Reserved(u8),
+    /// The handler type doesn't match the vector, e.g. a handler without error code was
+    /// passed for an exception that pushes an error code.
+    HandlerTypeMismatch(u8),
+}
+
+/// Tracks which of the user-defined interrupt vectors 32 to 255 are in use.
+///
+/// This is pure bookkeeping for handing out vectors, e.g. for MSI-X interrupts and IPIs, and
+/// doesn't modify any IDT. The exception vectors 0 to 31 are always reported as used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorAllocator {
+    used: [u64; 4],
+}
+
+impl VectorAllocator {
+    /// Creates an allocator with all user-defined vectors free.
+    #[inline]
+    pub const fn new() -> Self {
+        VectorAllocator {
+            used: [u32::MAX as u64, 0, 0, 0],
+        }
+    }
+
+    /// Creates an allocator that marks the vectors of all present entries of the given IDT
+    /// as used.
+    pub fn from_idt(idt: &InterruptDescriptorTable) -> Self {
+        let mut allocator = Self::new();
+        for (i, entry) in idt.interrupts.iter().enumerate() {
+            if entry.options.0.get_bit(15) {
+                allocator.set_used(i as u8 + 32, true);
+            }
+        }
+        allocator
+    }
+
+    /// Returns whether the given vector is in use.
+    #[inline]
+    pub fn is_used(&self, vector: u8) -> bool {
+        self.used[usize::from(vector / 64)].get_bit(usize::from(vector % 64))
+    }
+
+    /// Allocates the lowest free vector.
+    pub fn allocate(&mut self) -> Option<u8> {
+        self.allocate_aligned(1, 1)
+    }
+
+    /// Allocates a block of `count` consecutive free vectors whose first vector is a multiple
+    /// of `align` and returns the first vector.
+    ///
+    /// Multi-message MSI requires blocks whose size is a power of two and that are aligned to
+    /// their size, i.e. `count == align`. Returns `None` if `count` is 0 or larger than the
+    /// number of free vectors, or if there is no such free block.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn allocate_aligned(&mut self, count: usize, align: usize) -> Option<u8> {
+        assert!(align.is_power_of_two(), "`align` must be a power of two");
+        let free: u32 = self.used.iter().map(|word| word.count_zeros()).sum();
+        if count == 0 || count > free as usize {
+            return None;
+        }
+
+        let mut start = 32 / align * align;
+        loop {
+            let end = start.checked_add(count).filter(|&end| end <= 256)?;
+            if (start..end).all(|vector| !self.is_used(vector as u8)) {
+                for vector in start..end {
+                    self.set_used(vector as u8, true);
+                }
+                return Some(start as u8);
+            }
+            start = start.checked_add(align)?;
+        }
+    }
+
+    /// Marks the given vector as used, e.g. for a vector that is hardwired to a device.
+    ///
+    /// Returns `false` if the vector was already in use.
+    pub fn reserve(&mut self, vector: u8) -> bool {
+        let was_free = !self.is_used(vector);
+        self.set_used(vector, true);
+        was_free
+    }
+
+    /// Marks the given vector as free again.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the vector is an exception vector or not in use.
+    pub fn free(&mut self, vector: u8) {
+        assert!(vector >= 32, "exception vectors can't be freed");
+        assert!(self.is_used(vector), "vector {} is not in use", vector);
+        self.set_used(vector, false);
+    }
+
+    fn set_used(&mut self, vector: u8, used: bool) {
+        self.used[usize::from(vector / 64)].set_bit(usize::from(vector % 64), used);
+    }
+}
+
+impl Default for VectorAllocator {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// An Interrupt Descriptor Table entry.
 ///
 /// The generic parameter can either be `HandlerFunc` or `HandlerFuncWithErrCode`, depending
@@ -677,8 +1067,6 @@ impl EntryOptions {
 
     /// Set the required privilege level (DPL) for invoking the handler. The DPL can be 0, 1, 2,
     /// or 3, the default is 0. If CPL < DPL, a general protection fault occurs.
-    ///
-    /// This function panics for a DPL > 3.
     #[inline]
     pub fn set_privilege_level(&mut self, dpl: PrivilegeLevel) -> &mut Self {
         self.0.set_bits(13..15, dpl as u16);
@@ -776,6 +1164,55 @@ pub struct InterruptStackFrameValue {
     pub stack_segment: u64,
 }
 
+impl InterruptStackFrameValue {
+    /// Creates an interrupt stack frame, e.g. as the initial frame of a new thread that is
+    /// started by `iretq`.
+    ///
+    /// The reserved bit 1 of the flags register is always set, so only the meaningful flags
+    /// need to be passed. A new thread typically starts with just
+    /// [`INTERRUPT_FLAG`](RFlags::INTERRUPT_FLAG) set, so that it can be preempted.
+    #[inline]
+    pub const fn new(
+        instruction_pointer: VirtAddr,
+        code_segment: SegmentSelector,
+        cpu_flags: RFlags,
+        stack_pointer: VirtAddr,
+        stack_segment: SegmentSelector,
+    ) -> Self {
+        InterruptStackFrameValue {
+            instruction_pointer,
+            code_segment: code_segment.0 as u64,
+            cpu_flags: cpu_flags.bits() | (1 << 1),
+            stack_pointer,
+            stack_segment: stack_segment.0 as u64,
+        }
+    }
+
+    /// Writes the frame directly below `stack_top` and returns the address of the frame.
+    ///
+    /// The returned address is the value that the stack pointer must have when executing
+    /// `iretq` to return to the frame.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must ensure that the `size_of::<InterruptStackFrameValue>()` bytes below
+    /// `stack_top` are valid for writes.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `stack_top` is not 8-byte aligned.
+    #[inline]
+    pub unsafe fn write_to_stack(&self, stack_top: VirtAddr) -> VirtAddr {
+        assert!(
+            stack_top.is_aligned(8u64),
+            "stack top must be 8-byte aligned"
+        );
+        let frame = stack_top - core::mem::size_of::<Self>() as u64;
+        core::ptr::write(frame.as_mut_ptr::<Self>(), self.clone());
+        frame
+    }
+}
+
 impl fmt::Debug for InterruptStackFrameValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         struct Hex(u64);
@@ -823,9 +1260,19 @@ bitflags! {
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    PageFaultErrorCode,
+    u64,
+    "a valid page fault error code",
+    |flags| flags.bits(),
+    |bits| PageFaultErrorCode::from_bits(bits)
+);
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::structures::DescriptorTablePointer;
 
     #[test]
     fn size_test() {
@@ -833,4 +1280,111 @@ mod test {
         assert_eq!(size_of::<Entry<HandlerFunc>>(), 16);
         assert_eq!(size_of::<InterruptDescriptorTable>(), 256 * 16);
     }
+
+    #[cfg(feature = "const_fn")]
+    #[test]
+    fn const_new_test() {
+        static IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+
+        assert_eq!(IDT.page_fault, Entry::missing());
+        assert_eq!(IDT[255], Entry::missing());
+    }
+
+    #[test]
+    fn vector_allocator() {
+        let mut allocator = VectorAllocator::new();
+        assert!(allocator.is_used(31));
+        assert!(!allocator.reserve(3));
+        assert_eq!(allocator.allocate(), Some(32));
+        assert!(allocator.reserve(34));
+        assert!(!allocator.reserve(34));
+        assert_eq!(allocator.allocate(), Some(33));
+        assert_eq!(allocator.allocate(), Some(35));
+
+        // the first free block of 4 vectors that is aligned to 4
+        assert_eq!(allocator.allocate_aligned(4, 4), Some(36));
+        assert_eq!(allocator.allocate_aligned(64, 64), Some(64));
+        assert_eq!(allocator.allocate_aligned(128, 128), Some(128));
+        assert_eq!(allocator.allocate_aligned(32, 32), None);
+        assert_eq!(allocator.allocate_aligned(0, 1), None);
+        assert_eq!(allocator.allocate_aligned(usize::MAX, 1), None);
+        assert_eq!(allocator.allocate_aligned(1, usize::MAX / 2 + 1), None);
+
+        allocator.free(33);
+        assert_eq!(allocator.allocate(), Some(33));
+
+        let mut idt = InterruptDescriptorTable::new();
+        idt[40].options.set_present(true);
+        let allocator = VectorAllocator::from_idt(&idt);
+        assert!(allocator.is_used(40));
+        assert!(!allocator.is_used(41));
+    }
+
+    #[test]
+    fn descriptor_table_pointer() {
+        let idt = InterruptDescriptorTable::new();
+        let pointer = DescriptorTablePointer::from_idt(&idt);
+        assert_eq!({ pointer.limit }, 4095);
+        assert_eq!(pointer.entries_len(16), 256);
+        assert_eq!(
+            DescriptorTablePointer::try_new_idt(pointer.base, 4095),
+            Ok(pointer)
+        );
+        assert!(DescriptorTablePointer::try_new_idt(pointer.base, 4096).is_err());
+        assert!(DescriptorTablePointer::try_new_idt(pointer.base, 0).is_err());
+    }
+
+    #[test]
+    fn reset_test() {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.breakpoint.options.set_present(true);
+        idt[32].options.set_present(true);
+
+        idt.reset_interrupts();
+        assert_ne!(idt.breakpoint, Entry::missing());
+        assert_eq!(idt[32], Entry::missing());
+
+        idt.reset();
+        assert_eq!(idt.breakpoint, Entry::missing());
+    }
+
+    #[test]
+    fn exception_vector_test() {
+        for vector in 0..=255u8 {
+            if let Ok(exception) = ExceptionVector::try_from(vector) {
+                assert_eq!(u8::from(exception), vector);
+            }
+        }
+        assert_eq!(
+            ExceptionVector::try_from(14),
+            Ok(ExceptionVector::PageFault)
+        );
+        assert_eq!(
+            ExceptionVector::try_from(15),
+            Err(InvalidExceptionVector(15))
+        );
+        assert_eq!(
+            ExceptionVector::try_from(32),
+            Err(InvalidExceptionVector(32))
+        );
+        assert!(ExceptionVector::DoubleFault.has_error_code());
+        assert!(ExceptionVector::DoubleFault.is_diverging());
+        assert!(!ExceptionVector::Breakpoint.has_error_code());
+    }
+
+    #[test]
+    fn initial_stack_frame() {
+        let mut stack = [0u64; 8];
+        let stack_top = VirtAddr::from_ptr(stack.as_mut_ptr()) + 8 * 8u64;
+        let frame = InterruptStackFrameValue::new(
+            VirtAddr::new(0x1000),
+            SegmentSelector::new(4, PrivilegeLevel::Ring3),
+            RFlags::INTERRUPT_FLAG,
+            VirtAddr::new(0x8000),
+            SegmentSelector::new(3, PrivilegeLevel::Ring3),
+        );
+        let rsp = unsafe { frame.write_to_stack(stack_top) };
+        assert_eq!(rsp, stack_top - 40u64);
+        assert_eq!(stack[3..], [0x1000, 0x23, 0x202, 0x8000, 0x1b]);
+    }
 }
@@ -4,8 +4,13 @@ use crate::VirtAddr;
 
 pub mod gdt;
 
-// idt needs `feature(abi_x86_interrupt)`, which is not available on stable rust
-#[cfg(feature = "abi_x86_interrupt")]
+// idt needs `feature(abi_x86_interrupt)`, which is not available on stable rust. The
+// `x86-interrupt` calling convention is also only supported on x86 targets, so the other
+// structures can still be used (and tested) on other hosts.
+#[cfg(all(
+    feature = "abi_x86_interrupt",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
 pub mod idt;
 
 pub mod paging;
@@ -14,7 +19,7 @@ pub mod tss;
 
 /// A struct describing a pointer to a descriptor table (GDT / IDT).
 /// This is in a format suitable for giving to 'lgdt' or 'lidt'.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct DescriptorTablePointer {
     /// Size of the DT.
@@ -22,3 +27,72 @@ pub struct DescriptorTablePointer {
     /// Pointer to the memory region containing the DT.
     pub base: VirtAddr,
 }
+
+impl DescriptorTablePointer {
+    /// The size of an IDT entry in bytes.
+    const IDT_ENTRY_SIZE: usize = 16;
+
+    /// Creates a pointer to the given GDT.
+    ///
+    /// The limit only covers the entries that were added to the table, so the CPU rejects
+    /// selectors that point to unused entries.
+    #[inline]
+    pub fn from_gdt(gdt: &gdt::GlobalDescriptorTable) -> Self {
+        let entries = gdt.as_raw_slice();
+        DescriptorTablePointer {
+            base: VirtAddr::from_ptr(entries.as_ptr()),
+            limit: (core::mem::size_of_val(entries) - 1) as u16,
+        }
+    }
+
+    /// Creates a pointer to the given IDT, covering all of its 256 entries.
+    #[cfg(all(
+        feature = "abi_x86_interrupt",
+        any(target_arch = "x86", target_arch = "x86_64")
+    ))]
+    #[inline]
+    pub fn from_idt(idt: &idt::InterruptDescriptorTable) -> Self {
+        DescriptorTablePointer {
+            base: VirtAddr::from_ptr(idt),
+            limit: (core::mem::size_of::<idt::InterruptDescriptorTable>() - 1) as u16,
+        }
+    }
+
+    /// Creates a pointer to an IDT with the given base address and limit.
+    ///
+    /// This is useful for loading an IDT that isn't represented by an
+    /// [`InterruptDescriptorTable`](idt::InterruptDescriptorTable), e.g. one at a fixed
+    /// location during early boot. Returns an error if the limit isn't `16 * n - 1`, i.e. if
+    /// it doesn't cover a whole number of 16 byte IDT entries.
+    #[inline]
+    pub fn try_new_idt(base: VirtAddr, limit: u16) -> Result<Self, InvalidIdtLimit> {
+        if (usize::from(limit) + 1) % Self::IDT_ENTRY_SIZE != 0 {
+            return Err(InvalidIdtLimit(limit));
+        }
+        Ok(DescriptorTablePointer { limit, base })
+    }
+
+    /// Returns the number of entries of size `entry_size` that are covered by the limit.
+    ///
+    /// The entry size is 16 bytes for the IDT and 8 bytes for the GDT. Note that system
+    /// segment descriptors in the GDT (e.g. for the TSS) occupy two entries.
+    #[inline]
+    pub fn entries_len(&self, entry_size: usize) -> usize {
+        (usize::from(self.limit) + 1) / entry_size
+    }
+}
+
+/// The limit passed to [`DescriptorTablePointer::try_new_idt`] was not a multiple of the IDT
+/// entry size minus one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidIdtLimit(pub u16);
+
+impl core::fmt::Display for InvalidIdtLimit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the IDT limit {:#x} does not cover a whole number of entries",
+            self.0
+        )
+    }
+}
@@ -1,9 +1,13 @@
 //! Abstractions for default-sized and huge physical memory frames.
 
-use super::page::AddressNotAligned;
-use crate::structures::paging::page::{PageSize, Size4KiB};
-use crate::PhysAddr;
+use super::page::{AddressNotAligned, PageSizeKind, PageSizeMismatch};
+use crate::structures::paging::page::{PageSize, Size1GiB, Size2MiB, Size4KiB};
+use crate::{align_down, PhysAddr};
+use core::convert::TryFrom;
 use core::fmt;
+use core::iter::FusedIterator;
+#[cfg(feature = "step_trait")]
+use core::iter::Step;
 use core::marker::PhantomData;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
@@ -16,15 +20,18 @@ pub struct PhysFrame<S: PageSize = Size4KiB> {
 }
 
 impl<S: PageSize> PhysFrame<S> {
-    /// Returns the frame that starts at the given virtual address.
-    ///
-    /// Returns an error if the address is not correctly aligned (i.e. is not a valid frame start).
-    #[inline]
-    pub fn from_start_address(address: PhysAddr) -> Result<Self, AddressNotAligned> {
-        if !address.is_aligned(S::SIZE) {
-            return Err(AddressNotAligned);
+    const_fn! {
+        /// Returns the frame that starts at the given physical address.
+        ///
+        /// Returns an error if the address is not correctly aligned (i.e. is not a valid frame start).
+        /// The error contains the address and the frame size.
+        #[inline]
+        pub fn from_start_address(address: PhysAddr) -> Result<Self, AddressNotAligned> {
+            if address.as_u64() & (S::SIZE - 1) != 0 {
+                return Err(AddressNotAligned::new(address.as_u64(), S::SIZE));
+            }
+            Ok(PhysFrame::containing_address(address))
         }
-        Ok(PhysFrame::containing_address(address))
     }
 
     const_fn! {
@@ -42,12 +49,14 @@ impl<S: PageSize> PhysFrame<S> {
         }
     }
 
-    /// Returns the frame that contains the given physical address.
-    #[inline]
-    pub fn containing_address(address: PhysAddr) -> Self {
-        PhysFrame {
-            start_address: address.align_down(S::SIZE),
-            size: PhantomData,
+    const_fn! {
+        /// Returns the frame that contains the given physical address.
+        #[inline]
+        pub fn containing_address(address: PhysAddr) -> Self {
+            PhysFrame {
+                start_address: unsafe { PhysAddr::new_unsafe(align_down(address.as_u64(), S::SIZE)) },
+                size: PhantomData,
+            }
         }
     }
 
@@ -69,6 +78,8 @@ impl<S: PageSize> PhysFrame<S> {
 
     const_fn! {
         /// Returns a range of frames, exclusive `end`.
+        ///
+        /// With the `step_trait` feature, the native `start..end` syntax can be used instead.
         #[inline]
         pub fn range(start: PhysFrame<S>, end: PhysFrame<S>) -> PhysFrameRange<S> {
             PhysFrameRange { start, end }
@@ -77,6 +88,8 @@ impl<S: PageSize> PhysFrame<S> {
 
     const_fn! {
         /// Returns a range of frames, inclusive `end`.
+        ///
+        /// With the `step_trait` feature, the native `start..=end` syntax can be used instead.
         #[inline]
         pub fn range_inclusive(start: PhysFrame<S>, end: PhysFrame<S>) -> PhysFrameRangeInclusive<S> {
             PhysFrameRangeInclusive { start, end }
@@ -84,6 +97,37 @@ impl<S: PageSize> PhysFrame<S> {
     }
 }
 
+impl<S: PageSize> PhysFrame<S> {
+    /// Returns the next frame, or `None` if this is the last frame below the 52-bit limit.
+    #[inline]
+    fn checked_next(self) -> Option<Self> {
+        let start_address = self.start_address.checked_add(S::SIZE)?;
+        Some(PhysFrame {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    /// Returns the previous frame, or `None` if this is the first frame.
+    #[inline]
+    fn checked_prev(self) -> Option<Self> {
+        let start_address = self.start_address.checked_sub(S::SIZE)?;
+        Some(PhysFrame {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    /// Returns the number of frames from `start` to `end`, or zero if `end` is before `start`.
+    #[inline]
+    fn frames_between(start: Self, end: Self) -> u64 {
+        end.start_address
+            .as_u64()
+            .saturating_sub(start.start_address.as_u64())
+            / S::SIZE
+    }
+}
+
 impl<S: PageSize> fmt::Debug for PhysFrame<S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_fmt(format_args!(
@@ -102,6 +146,38 @@ impl<S: PageSize> Add<u64> for PhysFrame<S> {
     }
 }
 
+/// Stepping advances by the frame size and stops at the 52-bit physical address limit.
+#[cfg(feature = "step_trait")]
+impl<S: PageSize> Step for PhysFrame<S> {
+    #[inline]
+    fn steps_between(start: &Self, end: &Self) -> Option<usize> {
+        let bytes = end.start_address.checked_offset_from(start.start_address)?;
+        usize::try_from(bytes)
+            .ok()
+            .map(|bytes| bytes / S::SIZE as usize)
+    }
+
+    #[inline]
+    fn forward_checked(start: Self, count: usize) -> Option<Self> {
+        let bytes = u64::try_from(count).ok()?.checked_mul(S::SIZE)?;
+        let start_address = start.start_address.checked_add(bytes)?;
+        Some(PhysFrame {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn backward_checked(start: Self, count: usize) -> Option<Self> {
+        let bytes = u64::try_from(count).ok()?.checked_mul(S::SIZE)?;
+        let start_address = start.start_address.checked_sub(bytes)?;
+        Some(PhysFrame {
+            start_address,
+            size: PhantomData,
+        })
+    }
+}
+
 impl<S: PageSize> AddAssign<u64> for PhysFrame<S> {
     #[inline]
     fn add_assign(&mut self, rhs: u64) {
@@ -132,6 +208,105 @@ impl<S: PageSize> Sub<PhysFrame<S>> for PhysFrame<S> {
     }
 }
 
+/// A physical memory frame whose size is only known at runtime.
+///
+/// This is the interchange representation for frames of mixed sizes. It can be converted from
+/// a [`PhysFrame`] through `From` and back through `TryFrom`, which fails if the sizes don't
+/// match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AnyFrame {
+    start_address: PhysAddr,
+    size: PageSizeKind,
+}
+
+impl AnyFrame {
+    /// Returns the frame of the given size that starts at the given physical address.
+    ///
+    /// Returns an error if the address is not correctly aligned (i.e. is not a valid frame start).
+    #[inline]
+    pub fn from_start_address(
+        address: PhysAddr,
+        size: PageSizeKind,
+    ) -> Result<Self, AddressNotAligned> {
+        if !address.is_aligned(size.size()) {
+            return Err(AddressNotAligned::new(address.as_u64(), size.size()));
+        }
+        Ok(AnyFrame::containing_address(address, size))
+    }
+
+    /// Returns the frame of the given size that contains the given physical address.
+    #[inline]
+    pub fn containing_address(address: PhysAddr, size: PageSizeKind) -> Self {
+        AnyFrame {
+            start_address: address.align_down(size.size()),
+            size,
+        }
+    }
+
+    /// Returns the start address of the frame.
+    #[inline]
+    pub fn start_address(self) -> PhysAddr {
+        self.start_address
+    }
+
+    /// Returns the size of the frame.
+    #[inline]
+    pub fn size_kind(self) -> PageSizeKind {
+        self.size
+    }
+
+    /// Returns the size of the frame in bytes.
+    #[inline]
+    pub fn size(self) -> u64 {
+        self.size.size()
+    }
+}
+
+impl From<PhysFrame<Size4KiB>> for AnyFrame {
+    #[inline]
+    fn from(frame: PhysFrame<Size4KiB>) -> Self {
+        AnyFrame {
+            start_address: frame.start_address(),
+            size: PageSizeKind::Size4KiB,
+        }
+    }
+}
+
+impl From<PhysFrame<Size2MiB>> for AnyFrame {
+    #[inline]
+    fn from(frame: PhysFrame<Size2MiB>) -> Self {
+        AnyFrame {
+            start_address: frame.start_address(),
+            size: PageSizeKind::Size2MiB,
+        }
+    }
+}
+
+impl From<PhysFrame<Size1GiB>> for AnyFrame {
+    #[inline]
+    fn from(frame: PhysFrame<Size1GiB>) -> Self {
+        AnyFrame {
+            start_address: frame.start_address(),
+            size: PageSizeKind::Size1GiB,
+        }
+    }
+}
+
+impl<S: PageSize> TryFrom<AnyFrame> for PhysFrame<S> {
+    type Error = PageSizeMismatch;
+
+    #[inline]
+    fn try_from(frame: AnyFrame) -> Result<Self, Self::Error> {
+        if frame.size() != S::SIZE {
+            return Err(PageSizeMismatch);
+        }
+        Ok(PhysFrame {
+            start_address: frame.start_address,
+            size: PhantomData,
+        })
+    }
+}
+
 /// An range of physical memory frames, exclusive the upper bound.
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
@@ -148,6 +323,39 @@ impl<S: PageSize> PhysFrameRange<S> {
     pub fn is_empty(&self) -> bool {
         self.start >= self.end
     }
+
+    /// Returns the number of frames in the range.
+    #[inline]
+    pub(crate) fn len_u64(&self) -> u64 {
+        PhysFrame::frames_between(self.start, self.end)
+    }
+
+    /// Returns whether the given frame is part of the range.
+    #[inline]
+    pub fn contains(&self, frame: PhysFrame<S>) -> bool {
+        self.start <= frame && frame < self.end
+    }
+
+    /// Returns whether this range and the given range have at least one frame in common.
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Returns the range of frames that are part of both this range and the given range, or
+    /// `None` if the ranges don't overlap.
+    #[inline]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let intersection = PhysFrameRange {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+        if intersection.is_empty() {
+            None
+        } else {
+            Some(intersection)
+        }
+    }
 }
 
 impl<S: PageSize> Iterator for PhysFrameRange<S> {
@@ -163,8 +371,43 @@ impl<S: PageSize> Iterator for PhysFrameRange<S> {
             None
         }
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.len_u64()) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        usize::try_from(self.len_u64()).unwrap()
+    }
+
+    #[inline]
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl<S: PageSize> DoubleEndedIterator for PhysFrameRange<S> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start < self.end {
+            self.end = self.end.checked_prev().unwrap();
+            Some(self.end)
+        } else {
+            None
+        }
+    }
 }
 
+#[cfg(target_pointer_width = "64")]
+impl<S: PageSize> ExactSizeIterator for PhysFrameRange<S> {}
+
+impl<S: PageSize> FusedIterator for PhysFrameRange<S> {}
+
 impl<S: PageSize> fmt::Debug for PhysFrameRange<S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("PhysFrameRange")
@@ -190,6 +433,43 @@ impl<S: PageSize> PhysFrameRangeInclusive<S> {
     pub fn is_empty(&self) -> bool {
         self.start > self.end
     }
+
+    /// Returns the number of frames in the range.
+    #[inline]
+    fn len_u64(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            PhysFrame::frames_between(self.start, self.end) + 1
+        }
+    }
+
+    /// Returns whether the given frame is part of the range.
+    #[inline]
+    pub fn contains(&self, frame: PhysFrame<S>) -> bool {
+        self.start <= frame && frame <= self.end
+    }
+
+    /// Returns whether this range and the given range have at least one frame in common.
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Returns the range of frames that are part of both this range and the given range, or
+    /// `None` if the ranges don't overlap.
+    #[inline]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let intersection = PhysFrameRangeInclusive {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+        if intersection.is_empty() {
+            None
+        } else {
+            Some(intersection)
+        }
+    }
 }
 
 impl<S: PageSize> Iterator for PhysFrameRangeInclusive<S> {
@@ -197,16 +477,68 @@ impl<S: PageSize> Iterator for PhysFrameRangeInclusive<S> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.start <= self.end {
+        if self.start < self.end {
             let frame = self.start;
-            self.start += 1;
+            self.start = frame.checked_next().unwrap();
+            Some(frame)
+        } else if self.start == self.end {
+            // make the range empty without stepping past the ends of the address space
+            let frame = self.start;
+            match frame.checked_next() {
+                Some(next) => self.start = next,
+                None => self.end = frame.checked_prev().unwrap(),
+            }
             Some(frame)
         } else {
             None
         }
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.len_u64()) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        usize::try_from(self.len_u64()).unwrap()
+    }
+
+    #[inline]
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
 }
 
+impl<S: PageSize> DoubleEndedIterator for PhysFrameRangeInclusive<S> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start < self.end {
+            let frame = self.end;
+            self.end = frame.checked_prev().unwrap();
+            Some(frame)
+        } else if self.start == self.end {
+            // make the range empty without stepping past the ends of the address space
+            let frame = self.end;
+            match frame.checked_prev() {
+                Some(prev) => self.end = prev,
+                None => self.start = frame.checked_next().unwrap(),
+            }
+            Some(frame)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl<S: PageSize> ExactSizeIterator for PhysFrameRangeInclusive<S> {}
+
+impl<S: PageSize> FusedIterator for PhysFrameRangeInclusive<S> {}
+
 impl<S: PageSize> fmt::Debug for PhysFrameRangeInclusive<S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("PhysFrameRangeInclusive")
@@ -215,3 +547,76 @@ impl<S: PageSize> fmt::Debug for PhysFrameRangeInclusive<S> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_inclusive_range_at_top<S: PageSize>() {
+        let last: PhysFrame<S> =
+            PhysFrame::containing_address(PhysAddr::new(0x000f_ffff_ffff_ffff));
+        let range = PhysFrame::range_inclusive(last - 2, last);
+        assert_eq!(range.collect::<Vec<_>>(), [last - 2, last - 1, last]);
+        assert_eq!(range.rev().collect::<Vec<_>>(), [last, last - 1, last - 2]);
+        assert_eq!(range.len(), 3);
+
+        let mut range = PhysFrame::range_inclusive(last, last);
+        assert_eq!(range.next(), Some(last));
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+
+        let first: PhysFrame<S> = PhysFrame::containing_address(PhysAddr::zero());
+        let mut range = PhysFrame::range_inclusive(first, first);
+        assert_eq!(range.next_back(), Some(first));
+        assert_eq!(range.next_back(), None);
+        assert_eq!(range.next(), None);
+
+        let mut empty = PhysFrame::range_inclusive(last, last - 1);
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.next(), None);
+    }
+
+    #[test]
+    pub fn test_inclusive_range_at_top() {
+        check_inclusive_range_at_top::<Size4KiB>();
+        check_inclusive_range_at_top::<Size2MiB>();
+        check_inclusive_range_at_top::<Size1GiB>();
+    }
+
+    #[test]
+    pub fn test_frame_range_helpers() {
+        let frame = |addr| PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(addr));
+
+        let range = PhysFrame::range(frame(0x1000), frame(0x5000));
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.last(), Some(frame(0x4000)));
+        assert!(range.contains(frame(0x1000)));
+        assert!(!range.contains(frame(0x5000)));
+        assert_eq!(
+            range.intersection(&PhysFrame::range(frame(0x3000), frame(0x8000))),
+            Some(PhysFrame::range(frame(0x3000), frame(0x5000)))
+        );
+        assert!(!range.overlaps(&PhysFrame::range(frame(0x5000), frame(0x8000))));
+
+        let last = frame(0x000f_ffff_ffff_f000);
+        let mut range = PhysFrame::range_inclusive(last, last);
+        assert_eq!(range.len(), 1);
+        assert_eq!(range.next(), Some(last));
+        assert_eq!(range.next(), None);
+    }
+
+    #[cfg(feature = "step_trait")]
+    #[test]
+    pub fn test_frame_step() {
+        let start: PhysFrame<Size2MiB> = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+        let end = PhysFrame::containing_address(PhysAddr::new(0x4100_0000));
+        assert_eq!((start..end).count(), 8);
+        assert_eq!((end..start).count(), 0);
+        assert_eq!((start..end).last(), Some(end - 1));
+
+        // stepping stops at the 52-bit physical address limit
+        let last: PhysFrame = PhysFrame::containing_address(PhysAddr::new(0x000f_ffff_ffff_f000));
+        assert_eq!(Step::forward_checked(last, 1), None);
+        assert_eq!((last..=last).count(), 1);
+    }
+}
@@ -1,14 +1,31 @@
 //! Traits for abstracting away frame allocation and deallocation.
 
-use crate::structures::paging::{PageSize, PhysFrame};
+use crate::structures::paging::{frame::PhysFrameRange, PageSize, PhysFrame, Size4KiB};
+use crate::PhysAddr;
+use core::fmt;
 
 /// A trait for types that can allocate a frame of memory.
 ///
 /// This trait is unsafe to implement because the implementer must guarantee that
-/// the `allocate_frame` method returns only unique unused frames.
+/// the `allocate_frame` and `allocate_contiguous` methods return only unique unused frames.
 pub unsafe trait FrameAllocator<S: PageSize> {
     /// Allocate a frame of the appropriate size and return it if possible.
     fn allocate_frame(&mut self) -> Option<PhysFrame<S>>;
+
+    /// Allocate `count` physically contiguous frames and return them as a range if possible.
+    ///
+    /// The `align` parameter is given in frames, not bytes: the start frame number of the
+    /// returned range is a multiple of `align`. For example, a 16 KiB aligned run of four 4 KiB
+    /// frames is requested with `allocate_contiguous(4, 4)`. `align` must be a power of two;
+    /// an `align` of `0` or `1` imposes no extra alignment.
+    ///
+    /// The default implementation always returns `None`, so allocators that cannot guarantee
+    /// contiguity only need to implement [`allocate_frame`](FrameAllocator::allocate_frame).
+    #[inline]
+    fn allocate_contiguous(&mut self, count: usize, align: u64) -> Option<PhysFrameRange<S>> {
+        let _ = (count, align);
+        None
+    }
 }
 
 /// A trait for types that can deallocate a frame of memory.
@@ -20,3 +37,301 @@ pub trait FrameDeallocator<S: PageSize> {
     /// The caller must ensure that the passed frame is unused.
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame<S>);
 }
+
+/// A frame allocator that hands out 4KiB frames from a set of physical memory regions.
+///
+/// The allocator keeps one bit per frame in a caller-provided bitmap, so it needs no heap
+/// allocation and works with any bootloader: the usable memory regions only need to be
+/// converted to [`PhysFrameRange`]s. The bitmap covers all frames from the lowest to the
+/// highest frame of the given regions; frames in gaps between regions are never handed out.
+///
+/// Frames are allocated from low to high addresses. Deallocated frames can be allocated again,
+/// and [`allocate_contiguous`](FrameAllocator::allocate_contiguous) is supported.
+#[derive(Debug)]
+pub struct RegionFrameAllocator<'a> {
+    /// One bit per frame, a set bit means the frame is free.
+    bitmap: &'a mut [u64],
+    /// The frame tracked by bit zero of the bitmap.
+    base: PhysFrame<Size4KiB>,
+    /// The number of frames tracked by the bitmap.
+    frames: u64,
+    /// The index of the first bitmap word that might contain a free frame.
+    next_word: usize,
+}
+
+impl<'a> RegionFrameAllocator<'a> {
+    /// Creates a new allocator that hands out the frames of the given regions.
+    ///
+    /// The `bitmap` must hold at least one bit per frame between the lowest and the highest
+    /// frame of all regions, otherwise a [`BitmapTooSmall`] error containing the required length
+    /// is returned. [`required_bitmap_len`](Self::required_bitmap_len) can be used to size the
+    /// buffer up front. Regions may overlap and do not need to be sorted.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must guarantee that all frames of the given regions are unused and not handed
+    /// out by any other allocator.
+    pub unsafe fn new<I>(regions: I, bitmap: &'a mut [u64]) -> Result<Self, BitmapTooSmall>
+    where
+        I: IntoIterator<Item = PhysFrameRange<Size4KiB>>,
+        I::IntoIter: Clone,
+    {
+        let regions = regions.into_iter();
+        let (base, frames) = Self::span(regions.clone());
+        let required = Self::words_for(frames);
+        if bitmap.len() < required {
+            return Err(BitmapTooSmall { required });
+        }
+
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+        let mut allocator = RegionFrameAllocator {
+            bitmap,
+            base,
+            frames,
+            next_word: 0,
+        };
+        for region in regions.filter(|r| !r.is_empty()) {
+            let start = allocator.index_of(region.start);
+            let end = allocator.index_of(region.end - 1) + 1;
+            for index in start..end {
+                allocator.set_free(index, true);
+            }
+        }
+        Ok(allocator)
+    }
+
+    /// Returns the number of `u64` bitmap words needed to manage the given regions.
+    pub fn required_bitmap_len<I>(regions: I) -> usize
+    where
+        I: IntoIterator<Item = PhysFrameRange<Size4KiB>>,
+    {
+        Self::words_for(Self::span(regions.into_iter()).1)
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> u64 {
+        self.bitmap
+            .iter()
+            .map(|word| u64::from(word.count_ones()))
+            .sum()
+    }
+
+    /// Returns the lowest frame and the number of frames spanned by the given regions.
+    fn span<I>(regions: I) -> (PhysFrame<Size4KiB>, u64)
+    where
+        I: Iterator<Item = PhysFrameRange<Size4KiB>>,
+    {
+        let mut bounds: Option<(PhysFrame<Size4KiB>, PhysFrame<Size4KiB>)> = None;
+        for region in regions.filter(|r| !r.is_empty()) {
+            bounds = Some(match bounds {
+                Some((start, end)) => (start.min(region.start), end.max(region.end)),
+                None => (region.start, region.end),
+            });
+        }
+        match bounds {
+            Some((start, end)) => (start, frame_number(end) - frame_number(start)),
+            None => (PhysFrame::containing_address(PhysAddr::zero()), 0),
+        }
+    }
+
+    fn words_for(frames: u64) -> usize {
+        (frames.saturating_add(63) / 64) as usize
+    }
+
+    fn index_of(&self, frame: PhysFrame<Size4KiB>) -> u64 {
+        frame_number(frame) - frame_number(self.base)
+    }
+
+    fn frame_at(&self, index: u64) -> PhysFrame<Size4KiB> {
+        self.base + index
+    }
+
+    fn is_free(&self, index: u64) -> bool {
+        self.bitmap[(index / 64) as usize] & (1 << (index % 64)) != 0
+    }
+
+    fn set_free(&mut self, index: u64, free: bool) {
+        let word = &mut self.bitmap[(index / 64) as usize];
+        if free {
+            *word |= 1 << (index % 64);
+        } else {
+            *word &= !(1 << (index % 64));
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for RegionFrameAllocator<'_> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let words = Self::words_for(self.frames);
+        let word = (self.next_word..words).find(|&i| self.bitmap[i] != 0)?;
+        self.next_word = word;
+        let index = word as u64 * 64 + u64::from(self.bitmap[word].trailing_zeros());
+        self.set_free(index, false);
+        Some(self.frame_at(index))
+    }
+
+    fn allocate_contiguous(
+        &mut self,
+        count: usize,
+        align: u64,
+    ) -> Option<PhysFrameRange<Size4KiB>> {
+        let count = count as u64;
+        let align = align.max(1);
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        if count == 0 {
+            return None;
+        }
+
+        // the alignment applies to the frame number, not to the bitmap index
+        let base = frame_number(self.base);
+        let align_up =
+            |index: u64| Some(((base + index).checked_add(align - 1)? & !(align - 1)) - base);
+        let mut start = align_up(self.next_word as u64 * 64)?;
+        loop {
+            let end = start.checked_add(count).filter(|&end| end <= self.frames)?;
+            match (start..end).find(|&index| !self.is_free(index)) {
+                Some(used) => start = align_up(used + 1)?,
+                None => {
+                    for index in start..end {
+                        self.set_free(index, false);
+                    }
+                    return Some(PhysFrameRange {
+                        start: self.frame_at(start),
+                        end: self.frame_at(end),
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for RegionFrameAllocator<'_> {
+    /// Returns the given frame to the allocator.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the frame is outside of the regions managed by this allocator. In debug mode,
+    /// it also panics if the frame is already free.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        assert!(
+            frame >= self.base && self.index_of(frame) < self.frames,
+            "frame is not managed by this allocator"
+        );
+        let index = self.index_of(frame);
+        debug_assert!(!self.is_free(index), "frame was deallocated twice");
+        self.set_free(index, true);
+        self.next_word = self.next_word.min((index / 64) as usize);
+    }
+}
+
+/// The bitmap passed to [`RegionFrameAllocator::new`] was too small for the given regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitmapTooSmall {
+    required: usize,
+}
+
+impl BitmapTooSmall {
+    /// Returns the number of `u64` bitmap words that are required.
+    #[inline]
+    pub fn required(&self) -> usize {
+        self.required
+    }
+}
+
+impl fmt::Display for BitmapTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the frame bitmap is too small, {} words are required",
+            self.required
+        )
+    }
+}
+
+fn frame_number(frame: PhysFrame<Size4KiB>) -> u64 {
+    frame.start_address().as_u64() / Size4KiB::SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(number: u64) -> PhysFrame<Size4KiB> {
+        PhysFrame::containing_address(PhysAddr::new(number * Size4KiB::SIZE))
+    }
+
+    fn range(start: u64, end: u64) -> PhysFrameRange<Size4KiB> {
+        PhysFrame::range(frame(start), frame(end))
+    }
+
+    #[test]
+    fn test_region_allocator() {
+        let regions = [
+            range(0x102, 0x104),
+            range(0x100, 0x101),
+            range(0x180, 0x190),
+        ];
+        assert_eq!(RegionFrameAllocator::required_bitmap_len(regions), 3);
+
+        let mut small = [0; 2];
+        let err = unsafe { RegionFrameAllocator::new(regions, &mut small) }.unwrap_err();
+        assert_eq!(err.required(), 3);
+
+        let mut bitmap = [0; 3];
+        let mut allocator = unsafe { RegionFrameAllocator::new(regions, &mut bitmap) }.unwrap();
+        assert_eq!(allocator.free_frames(), 19);
+        assert_eq!(allocator.allocate_frame(), Some(frame(0x100)));
+        assert_eq!(allocator.allocate_frame(), Some(frame(0x102)));
+        assert_eq!(allocator.allocate_frame(), Some(frame(0x103)));
+        unsafe { allocator.deallocate_frame(frame(0x100)) };
+        assert_eq!(allocator.allocate_frame(), Some(frame(0x100)));
+        assert_eq!(allocator.allocate_frame(), Some(frame(0x180)));
+
+        // 0x181..0x184 is free but not aligned to four frames
+        assert_eq!(
+            allocator.allocate_contiguous(4, 4),
+            Some(range(0x184, 0x188))
+        );
+        assert_eq!(
+            allocator.allocate_contiguous(3, 1),
+            Some(range(0x181, 0x184))
+        );
+        assert_eq!(allocator.allocate_contiguous(16, 1), None);
+        assert_eq!(allocator.allocate_contiguous(usize::MAX, 1), None);
+        assert_eq!(allocator.allocate_contiguous(1, 1 << 63), None);
+        assert_eq!(allocator.free_frames(), 8);
+
+        for _ in 0..8 {
+            assert!(allocator.allocate_frame().is_some());
+        }
+        assert_eq!(allocator.allocate_frame(), None);
+    }
+
+    #[test]
+    fn test_default_allocate_contiguous() {
+        struct SingleFrames(u64);
+
+        unsafe impl FrameAllocator<Size4KiB> for SingleFrames {
+            fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+                self.0 += 1;
+                Some(frame(self.0))
+            }
+        }
+
+        // generic code can ask any allocator for contiguous frames
+        fn dma_buffer<A: FrameAllocator<Size4KiB>>(
+            allocator: &mut A,
+        ) -> Option<PhysFrameRange<Size4KiB>> {
+            allocator.allocate_contiguous(2, 2)
+        }
+
+        assert_eq!(dma_buffer(&mut SingleFrames(0)), None);
+
+        let mut bitmap = [0; 1];
+        let mut allocator =
+            unsafe { RegionFrameAllocator::new([range(0x11, 0x20)], &mut bitmap) }.unwrap();
+        assert_eq!(dma_buffer(&mut allocator), Some(range(0x12, 0x14)));
+    }
+}
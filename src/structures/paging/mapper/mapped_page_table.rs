@@ -1,18 +1,25 @@
+use core::ops::RangeInclusive;
+
+use super::mapped_regions::MappedRegions;
 use crate::structures::paging::{
     frame::PhysFrame,
-    frame_alloc::FrameAllocator,
+    frame_alloc::{FrameAllocator, FrameDeallocator},
     mapper::*,
-    page::{AddressNotAligned, Page, Size1GiB, Size2MiB, Size4KiB},
-    page_table::{FrameError, PageTable, PageTableEntry, PageTableFlags},
+    page::{Page, PageRangeInclusive, Size1GiB, Size2MiB, Size4KiB},
+    page_table::{FrameError, PageTable, PageTableEntry, PageTableFlags, PageTableLevel},
+    PageTableIndex,
 };
 
 /// A Mapper implementation that relies on a PhysAddr to VirtAddr conversion function.
 ///
 /// This type requires that the all physical page table frames are mapped to some virtual
 /// address. Normally, this is done by mapping the complete physical address space into
-/// the virtual address space at some offset. Other mappings between physical and virtual
-/// memory are possible too, as long as they can be calculated as an `PhysAddr` to
-/// `VirtAddr` closure.
+/// the virtual address space at some offset, which is implemented by [`PhysOffset`]. Other
+/// mappings between physical and virtual memory are possible too, as long as they can be
+/// described by a [`PageTableFrameMapping`] implementation or a
+/// `Fn(PhysFrame) -> *mut PageTable` closure.
+///
+/// [`PhysOffset`]: super::PhysOffset
 #[derive(Debug)]
 pub struct MappedPageTable<'a, P: PageTableFrameMapping> {
     page_table_walker: PageTableWalker<P>,
@@ -20,26 +27,115 @@ pub struct MappedPageTable<'a, P: PageTableFrameMapping> {
 }
 
 impl<'a, P: PageTableFrameMapping> MappedPageTable<'a, P> {
-    /// Creates a new `MappedPageTable` that uses the passed closure for converting virtual
+    /// Creates a new `MappedPageTable` that uses the passed mapping for converting virtual
     /// to physical addresses.
     ///
     /// ## Safety
     ///
     /// This function is unsafe because the caller must guarantee that the passed `page_table_frame_mapping`
-    /// closure is correct. Also, the passed `level_4_table` must point to the level 4 page table
+    /// is correct, which includes mappings passed as a closure. Also, the passed `level_4_table` must point to the level 4 page table
     /// of a valid page table hierarchy. Otherwise this function might break memory safety, e.g.
     /// by writing to an illegal memory location.
     #[inline]
     pub unsafe fn new(level_4_table: &'a mut PageTable, page_table_frame_mapping: P) -> Self {
+        Self::with_encryption_mask(level_4_table, page_table_frame_mapping, 0)
+    }
+
+    /// Creates a new `MappedPageTable` for a page table hierarchy that uses memory encryption,
+    /// e.g. inside an AMD SEV guest.
+    ///
+    /// The bits in `encryption_mask` (e.g. the "C-bit" reported by CPUID leaf 0x8000001F) are
+    /// stripped from the addresses of all entries when walking the page tables or translating
+    /// addresses. They are set for all newly created entries, i.e. both page tables and mapped
+    /// pages are mapped as encrypted. The `P` mapping receives the frames with the bits
+    /// already stripped.
+    ///
+    /// ## Safety
+    ///
+    /// Same as for [`MappedPageTable::new`]. Additionally, the caller must guarantee that
+    /// `encryption_mask` matches the encryption bit of the CPU.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `encryption_mask` has bits set outside of the address field of page table
+    /// entries (bits 12 to 51).
+    #[inline]
+    pub unsafe fn with_encryption_mask(
+        level_4_table: &'a mut PageTable,
+        page_table_frame_mapping: P,
+        encryption_mask: u64,
+    ) -> Self {
+        assert_eq!(
+            encryption_mask & !0x000f_ffff_ffff_f000,
+            0,
+            "encryption mask must be part of the address field"
+        );
         Self {
             level_4_table,
-            page_table_walker: PageTableWalker::new(page_table_frame_mapping),
+            page_table_walker: PageTableWalker::new(page_table_frame_mapping, encryption_mask),
         }
     }
 
+    /// Returns an immutable reference to the wrapped level 4 `PageTable` instance.
+    #[inline]
+    pub fn level_4_table(&self) -> &PageTable {
+        self.level_4_table
+    }
+
     /// Returns a mutable reference to the wrapped level 4 `PageTable` instance.
-    pub fn level_4_table(&mut self) -> &mut PageTable {
-        &mut self.level_4_table
+    #[inline]
+    pub fn level_4_table_mut(&mut self) -> &mut PageTable {
+        self.level_4_table
+    }
+
+    /// Returns the mapping that is used for accessing the page table frames.
+    #[inline]
+    pub fn page_table_frame_mapping(&self) -> &P {
+        &self.page_table_walker.page_table_frame_mapping
+    }
+
+    /// Returns an iterator over all mapped regions of the page table.
+    ///
+    /// The page table hierarchy is walked depth-first, so the regions are returned in the order
+    /// of their virtual addresses. Adjacent pages are coalesced into a single [`MappedRegion`]
+    /// if they are mapped with the same page size and flags to contiguous physical memory.
+    /// Huge pages are supported at all levels, level 4 entries with the `HUGE_PAGE` flag set
+    /// are skipped. The iterator doesn't allocate, regions are yielded while walking.
+    pub fn mapped_regions(&self) -> impl Iterator<Item = MappedRegion> + '_ {
+        let walker = &self.page_table_walker;
+        MappedRegions::new(
+            self.level_4_table,
+            None,
+            walker.encryption_mask,
+            move |_, _, entry| {
+                let frame = entry.frame_with_mask(walker.encryption_mask).ok()?;
+                let page_table = walker.page_table_frame_mapping.frame_to_pointer(frame);
+                Some(unsafe { &*page_table })
+            },
+        )
+    }
+
+    /// Initializes `new_table` as a level 4 table that shares the entries in the given index
+    /// range with the wrapped level 4 table.
+    ///
+    /// All entries of `new_table` outside of `range` are cleared. The entries in `range` are
+    /// copied, which means that the lower level page tables they point to are shared by both
+    /// hierarchies, not deep-copied. This is the usual way to map the kernel into a new address
+    /// space, e.g. with the higher-half entries `256..=511`. Changes to the shared lower level
+    /// tables are visible in both address spaces, while changes to the shared level 4 entries
+    /// themselves are not.
+    ///
+    /// If `range` is empty, `new_table` is only cleared.
+    ///
+    /// Since the wrapped table is borrowed immutably, `new_table` can't be the same table.
+    #[inline]
+    pub fn clone_level_4_into(
+        &self,
+        new_table: &mut PageTable,
+        range: RangeInclusive<PageTableIndex>,
+    ) {
+        new_table.zero();
+        new_table.clone_range_from(self.level_4_table, range);
     }
 
     /// Helper function for implementing Mapper. Safe to limit the scope of unsafe, see
@@ -65,7 +161,11 @@ impl<'a, P: PageTableFrameMapping> MappedPageTable<'a, P> {
         if !p3[page.p3_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(frame));
         }
-        p3[page.p3_index()].set_addr(frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
+        p3[page.p3_index()].set_addr_encrypted(
+            frame.start_address(),
+            flags | PageTableFlags::HUGE_PAGE,
+            self.page_table_walker.encryption_mask,
+        );
 
         Ok(MapperFlush::new(page))
     }
@@ -98,7 +198,11 @@ impl<'a, P: PageTableFrameMapping> MappedPageTable<'a, P> {
         if !p2[page.p2_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(frame));
         }
-        p2[page.p2_index()].set_addr(frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
+        p2[page.p2_index()].set_addr_encrypted(
+            frame.start_address(),
+            flags | PageTableFlags::HUGE_PAGE,
+            self.page_table_walker.encryption_mask,
+        );
 
         Ok(MapperFlush::new(page))
     }
@@ -136,7 +240,12 @@ impl<'a, P: PageTableFrameMapping> MappedPageTable<'a, P> {
         if !p1[page.p1_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(frame));
         }
-        p1[page.p1_index()].set_frame(frame, flags);
+        assert!(!flags.contains(PageTableFlags::HUGE_PAGE));
+        p1[page.p1_index()].set_addr_encrypted(
+            frame.start_address(),
+            flags,
+            self.page_table_walker.encryption_mask,
+        );
 
         Ok(MapperFlush::new(page))
     }
@@ -177,13 +286,47 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size1GiB> for MappedPageTable<'a, P> {
             return Err(UnmapError::ParentEntryHugePage);
         }
 
-        let frame = PhysFrame::from_start_address(p3_entry.addr())
-            .map_err(|AddressNotAligned| UnmapError::InvalidFrameAddress(p3_entry.addr()))?;
+        let addr = p3_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        let frame = PhysFrame::from_start_address(addr)
+            .map_err(|_| UnmapError::InvalidFrameAddress(addr))?;
 
         p3_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size1GiB>,
+        new_frame: PhysFrame<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size1GiB>, MapperFlush<Size1GiB>), RemapError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+
+        let p3_entry = &mut p3[page.p3_index()];
+        let old_flags = p3_entry.flags();
+
+        if !old_flags.contains(PageTableFlags::PRESENT) {
+            return Err(RemapError::PageNotMapped);
+        }
+        if !old_flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(RemapError::ParentEntryHugePage);
+        }
+
+        let addr = p3_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        let frame = PhysFrame::from_start_address(addr)
+            .map_err(|_| RemapError::InvalidFrameAddress(addr))?;
+
+        p3_entry.set_addr_encrypted(
+            new_frame.start_address(),
+            flags | PageTableFlags::HUGE_PAGE,
+            self.page_table_walker.encryption_mask,
+        );
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     unsafe fn update_flags(
         &mut self,
         page: Page<Size1GiB>,
@@ -197,11 +340,29 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size1GiB> for MappedPageTable<'a, P> {
         if p3[page.p3_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
-        p3[page.p3_index()].set_flags(flags | PageTableFlags::HUGE_PAGE);
+        p3[page.p3_index()].set_flags_keep_os_bits(flags | PageTableFlags::HUGE_PAGE);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size1GiB>), FlagUpdateError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+
+        if p3[page.p3_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p3[page.p3_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size1GiB>,
@@ -239,14 +400,27 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size1GiB> for MappedPageTable<'a, P> {
         let p4 = &self.level_4_table;
         let p3 = self.page_table_walker.next_table(&p4[page.p4_index()])?;
 
-        let p3_entry = &p3[page.p3_index()];
+        let p3_entry = p3[page.p3_index()].read_volatile();
+
+        if p3_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        let addr = p3_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        PhysFrame::from_start_address(addr).map_err(|_| TranslateError::InvalidFrameAddress(addr))
+    }
+
+    fn page_flags(&self, page: Page<Size1GiB>) -> Result<PageTableFlags, TranslateError> {
+        let p4 = &self.level_4_table;
+        let p3 = self.page_table_walker.next_table(&p4[page.p4_index()])?;
+
+        let p3_entry = p3[page.p3_index()].read_volatile();
 
         if p3_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
-        PhysFrame::from_start_address(p3_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p3_entry.addr()))
+        Ok(p3_entry.flags())
     }
 }
 
@@ -288,13 +462,50 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size2MiB> for MappedPageTable<'a, P> {
             return Err(UnmapError::ParentEntryHugePage);
         }
 
-        let frame = PhysFrame::from_start_address(p2_entry.addr())
-            .map_err(|AddressNotAligned| UnmapError::InvalidFrameAddress(p2_entry.addr()))?;
+        let addr = p2_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        let frame = PhysFrame::from_start_address(addr)
+            .map_err(|_| UnmapError::InvalidFrameAddress(addr))?;
 
         p2_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size2MiB>,
+        new_frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size2MiB>, MapperFlush<Size2MiB>), RemapError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index()])?;
+
+        let p2_entry = &mut p2[page.p2_index()];
+        let old_flags = p2_entry.flags();
+
+        if !old_flags.contains(PageTableFlags::PRESENT) {
+            return Err(RemapError::PageNotMapped);
+        }
+        if !old_flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(RemapError::ParentEntryHugePage);
+        }
+
+        let addr = p2_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        let frame = PhysFrame::from_start_address(addr)
+            .map_err(|_| RemapError::InvalidFrameAddress(addr))?;
+
+        p2_entry.set_addr_encrypted(
+            new_frame.start_address(),
+            flags | PageTableFlags::HUGE_PAGE,
+            self.page_table_walker.encryption_mask,
+        );
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     unsafe fn update_flags(
         &mut self,
         page: Page<Size2MiB>,
@@ -312,11 +523,32 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size2MiB> for MappedPageTable<'a, P> {
             return Err(FlagUpdateError::PageNotMapped);
         }
 
-        p2[page.p2_index()].set_flags(flags | PageTableFlags::HUGE_PAGE);
+        p2[page.p2_index()].set_flags_keep_os_bits(flags | PageTableFlags::HUGE_PAGE);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size2MiB>), FlagUpdateError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index()])?;
+
+        if p2[page.p2_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p2[page.p2_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size2MiB>,
@@ -348,6 +580,9 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size2MiB> for MappedPageTable<'a, P> {
         if p3_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p3_entry.set_flags(flags);
 
@@ -367,14 +602,28 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size2MiB> for MappedPageTable<'a, P> {
         let p3 = self.page_table_walker.next_table(&p4[page.p4_index()])?;
         let p2 = self.page_table_walker.next_table(&p3[page.p3_index()])?;
 
-        let p2_entry = &p2[page.p2_index()];
+        let p2_entry = p2[page.p2_index()].read_volatile();
 
         if p2_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
-        PhysFrame::from_start_address(p2_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p2_entry.addr()))
+        let addr = p2_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        PhysFrame::from_start_address(addr).map_err(|_| TranslateError::InvalidFrameAddress(addr))
+    }
+
+    fn page_flags(&self, page: Page<Size2MiB>) -> Result<PageTableFlags, TranslateError> {
+        let p4 = &self.level_4_table;
+        let p3 = self.page_table_walker.next_table(&p4[page.p4_index()])?;
+        let p2 = self.page_table_walker.next_table(&p3[page.p3_index()])?;
+
+        let p2_entry = p2[page.p2_index()].read_volatile();
+
+        if p2_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        Ok(p2_entry.flags())
     }
 }
 
@@ -411,15 +660,51 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
 
         let p1_entry = &mut p1[page.p1_index()];
 
-        let frame = p1_entry.frame().map_err(|err| match err {
-            FrameError::FrameNotPresent => UnmapError::PageNotMapped,
-            FrameError::HugeFrame => UnmapError::ParentEntryHugePage,
-        })?;
+        let frame = p1_entry
+            .frame_with_mask(self.page_table_walker.encryption_mask)
+            .map_err(|err| match err {
+                FrameError::FrameNotPresent => UnmapError::PageNotMapped,
+                FrameError::HugeFrame => UnmapError::ParentEntryHugePage,
+            })?;
 
         p1_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size4KiB>,
+        new_frame: PhysFrame<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), RemapError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index()])?;
+        let p1 = self
+            .page_table_walker
+            .next_table_mut(&mut p2[page.p2_index()])?;
+
+        let p1_entry = &mut p1[page.p1_index()];
+
+        let frame = p1_entry
+            .frame_with_mask(self.page_table_walker.encryption_mask)
+            .map_err(|err| match err {
+                FrameError::FrameNotPresent => RemapError::PageNotMapped,
+                FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+            })?;
+
+        p1_entry.set_addr_encrypted(
+            new_frame.start_address(),
+            flags,
+            self.page_table_walker.encryption_mask,
+        );
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     unsafe fn update_flags(
         &mut self,
         page: Page<Size4KiB>,
@@ -440,11 +725,35 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
             return Err(FlagUpdateError::PageNotMapped);
         }
 
-        p1[page.p1_index()].set_flags(flags);
+        p1[page.p1_index()].set_flags_keep_os_bits(flags);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size4KiB>), FlagUpdateError> {
+        let p4 = &mut self.level_4_table;
+        let p3 = self
+            .page_table_walker
+            .next_table_mut(&mut p4[page.p4_index()])?;
+        let p2 = self
+            .page_table_walker
+            .next_table_mut(&mut p3[page.p3_index()])?;
+        let p1 = self
+            .page_table_walker
+            .next_table_mut(&mut p2[page.p2_index()])?;
+
+        if p1[page.p1_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p1[page.p1_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size4KiB>,
@@ -476,6 +785,9 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
         if p3_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p3_entry.set_flags(flags);
 
@@ -499,6 +811,9 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
         if p2_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p2_entry.set_flags(flags);
 
@@ -511,89 +826,143 @@ impl<'a, P: PageTableFrameMapping> Mapper<Size4KiB> for MappedPageTable<'a, P> {
         let p2 = self.page_table_walker.next_table(&p3[page.p3_index()])?;
         let p1 = self.page_table_walker.next_table(&p2[page.p2_index()])?;
 
-        let p1_entry = &p1[page.p1_index()];
+        let p1_entry = p1[page.p1_index()].read_volatile();
 
         if p1_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
-        PhysFrame::from_start_address(p1_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p1_entry.addr()))
+        let addr = p1_entry.addr_with_mask(self.page_table_walker.encryption_mask);
+        PhysFrame::from_start_address(addr).map_err(|_| TranslateError::InvalidFrameAddress(addr))
     }
-}
 
-impl<'a, P: PageTableFrameMapping> Translate for MappedPageTable<'a, P> {
-    #[allow(clippy::inconsistent_digit_grouping)]
-    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+    fn page_flags(&self, page: Page<Size4KiB>) -> Result<PageTableFlags, TranslateError> {
         let p4 = &self.level_4_table;
-        let p3 = match self.page_table_walker.next_table(&p4[addr.p4_index()]) {
-            Ok(page_table) => page_table,
-            Err(PageTableWalkError::NotMapped) => return TranslateResult::NotMapped,
-            Err(PageTableWalkError::MappedToHugePage) => {
-                panic!("level 4 entry has huge page bit set")
-            }
-        };
-        let p2 = match self.page_table_walker.next_table(&p3[addr.p3_index()]) {
-            Ok(page_table) => page_table,
-            Err(PageTableWalkError::NotMapped) => return TranslateResult::NotMapped,
-            Err(PageTableWalkError::MappedToHugePage) => {
-                let entry = &p3[addr.p3_index()];
-                let frame = PhysFrame::containing_address(entry.addr());
-                let offset = addr.as_u64() & 0o_777_777_7777;
-                let flags = entry.flags();
-                return TranslateResult::Mapped {
-                    frame: MappedFrame::Size1GiB(frame),
-                    offset,
-                    flags,
-                };
-            }
-        };
-        let p1 = match self.page_table_walker.next_table(&p2[addr.p2_index()]) {
-            Ok(page_table) => page_table,
-            Err(PageTableWalkError::NotMapped) => return TranslateResult::NotMapped,
-            Err(PageTableWalkError::MappedToHugePage) => {
-                let entry = &p2[addr.p2_index()];
-                let frame = PhysFrame::containing_address(entry.addr());
-                let offset = addr.as_u64() & 0o_777_7777;
-                let flags = entry.flags();
-                return TranslateResult::Mapped {
-                    frame: MappedFrame::Size2MiB(frame),
-                    offset,
-                    flags,
-                };
-            }
-        };
+        let p3 = self.page_table_walker.next_table(&p4[page.p4_index()])?;
+        let p2 = self.page_table_walker.next_table(&p3[page.p3_index()])?;
+        let p1 = self.page_table_walker.next_table(&p2[page.p2_index()])?;
 
-        let p1_entry = &p1[addr.p1_index()];
+        let p1_entry = p1[page.p1_index()].read_volatile();
 
         if p1_entry.is_unused() {
-            return TranslateResult::NotMapped;
+            return Err(TranslateError::PageNotMapped);
         }
 
-        let frame = match PhysFrame::from_start_address(p1_entry.addr()) {
-            Ok(frame) => frame,
-            Err(AddressNotAligned) => return TranslateResult::InvalidFrameAddress(p1_entry.addr()),
-        };
-        let offset = u64::from(addr.page_offset());
-        let flags = p1_entry.flags();
-        TranslateResult::Mapped {
-            frame: MappedFrame::Size4KiB(frame),
-            offset,
-            flags,
+        Ok(p1_entry.flags())
+    }
+}
+
+impl<'a, P: PageTableFrameMapping> Translate for MappedPageTable<'a, P> {
+    #[inline]
+    fn translate_detailed(&self, addr: VirtAddr) -> Result<Translation, TranslateErrorDetailed> {
+        self.page_table_walker
+            .translate_detailed(self.level_4_table, addr)
+    }
+}
+
+impl<'a, P: PageTableFrameMapping> CleanUp for MappedPageTable<'a, P> {
+    #[inline]
+    unsafe fn clean_up<D>(&mut self, frame_deallocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        self.clean_up_addr_range(
+            Page::range_inclusive(
+                Page::containing_address(VirtAddr::new(0)),
+                Page::containing_address(VirtAddr::new(0xffff_ffff_ffff_ffff)),
+            ),
+            frame_deallocator,
+        )
+    }
+
+    unsafe fn clean_up_addr_range<D>(
+        &mut self,
+        range: PageRangeInclusive,
+        frame_deallocator: &mut D,
+    ) where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        /// Frees the empty tables below `page_table` and returns whether `page_table` itself
+        /// is empty afterwards.
+        unsafe fn clean_up<P: PageTableFrameMapping, D: FrameDeallocator<Size4KiB>>(
+            page_table: &mut PageTable,
+            page_table_walker: &PageTableWalker<P>,
+            level: PageTableLevel,
+            range: PageRangeInclusive,
+            frame_deallocator: &mut D,
+        ) -> bool {
+            if range.is_empty() {
+                return false;
+            }
+
+            let table_addr = range
+                .start
+                .start_address()
+                .align_down(level.table_address_space_alignment());
+
+            let start = range.start.page_table_index(level);
+            let end = range.end.page_table_index(level);
+
+            if let Some(next_level) = level.next_lower_level() {
+                let offset_per_entry = level.entry_address_space_alignment();
+                for (i, entry) in page_table
+                    .iter_mut()
+                    .enumerate()
+                    .take(usize::from(end) + 1)
+                    .skip(usize::from(start))
+                {
+                    // fails for unused entries and huge pages, which are skipped
+                    if let Ok(next_table) = page_table_walker.next_table_mut(entry) {
+                        let start = VirtAddr::new_truncate(
+                            table_addr.as_u64() + offset_per_entry * i as u64,
+                        );
+                        let end = start + (offset_per_entry - 1);
+                        let start = Page::containing_address(start).max(range.start);
+                        let end = Page::containing_address(end).min(range.end);
+                        if clean_up(
+                            next_table,
+                            page_table_walker,
+                            next_level,
+                            Page::range_inclusive(start, end),
+                            frame_deallocator,
+                        ) {
+                            let frame = entry
+                                .frame_with_mask(page_table_walker.encryption_mask)
+                                .unwrap();
+                            entry.set_unused();
+                            frame_deallocator.deallocate_frame(frame);
+                        }
+                    }
+                }
+            }
+
+            page_table.iter().all(PageTableEntry::is_unused)
         }
+
+        clean_up(
+            self.level_4_table,
+            &self.page_table_walker,
+            PageTableLevel::Four,
+            range,
+            frame_deallocator,
+        );
     }
 }
 
 #[derive(Debug)]
 struct PageTableWalker<P: PageTableFrameMapping> {
     page_table_frame_mapping: P,
+    /// Bits of the address field that are set for encrypted memory, see
+    /// [`MappedPageTable::with_encryption_mask`].
+    encryption_mask: u64,
 }
 
 impl<P: PageTableFrameMapping> PageTableWalker<P> {
     #[inline]
-    pub unsafe fn new(page_table_frame_mapping: P) -> Self {
+    pub unsafe fn new(page_table_frame_mapping: P, encryption_mask: u64) -> Self {
         Self {
             page_table_frame_mapping,
+            encryption_mask,
         }
     }
 
@@ -607,9 +976,11 @@ impl<P: PageTableFrameMapping> PageTableWalker<P> {
         &self,
         entry: &'b PageTableEntry,
     ) -> Result<&'b PageTable, PageTableWalkError> {
-        let page_table_ptr = self
-            .page_table_frame_mapping
-            .frame_to_pointer(entry.frame()?);
+        let page_table_ptr = self.page_table_frame_mapping.frame_to_pointer(
+            entry
+                .read_volatile()
+                .frame_with_mask(self.encryption_mask)?,
+        );
         let page_table: &PageTable = unsafe { &*page_table_ptr };
 
         Ok(page_table)
@@ -627,12 +998,79 @@ impl<P: PageTableFrameMapping> PageTableWalker<P> {
     ) -> Result<&'b mut PageTable, PageTableWalkError> {
         let page_table_ptr = self
             .page_table_frame_mapping
-            .frame_to_pointer(entry.frame()?);
+            .frame_to_pointer(entry.frame_with_mask(self.encryption_mask)?);
         let page_table: &mut PageTable = unsafe { &mut *page_table_ptr };
 
         Ok(page_table)
     }
 
+    /// Internal helper function that translates the given address in the page table hierarchy
+    /// with the given level 4 table.
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn translate_detailed(
+        &self,
+        p4: &PageTable,
+        addr: VirtAddr,
+    ) -> Result<Translation, TranslateErrorDetailed> {
+        let p4_entry = p4[addr.p4_index()].read_volatile();
+        let p3 = match self.next_table(&p4_entry) {
+            Ok(page_table) => page_table,
+            Err(err) => return Err(err.into_detailed(PageTableLevel::Four, &p4_entry)),
+        };
+        let p3_entry = p3[addr.p3_index()].read_volatile();
+        let p2 = match self.next_table(&p3_entry) {
+            Ok(page_table) => page_table,
+            Err(PageTableWalkError::MappedToHugePage) => {
+                let frame =
+                    PhysFrame::containing_address(p3_entry.addr_with_mask(self.encryption_mask));
+                return Ok(Translation {
+                    frame: MappedFrame::Size1GiB(frame),
+                    offset: addr.as_u64() & 0o_777_777_7777,
+                    flags: p3_entry.flags(),
+                });
+            }
+            Err(err) => return Err(err.into_detailed(PageTableLevel::Three, &p3_entry)),
+        };
+        let p2_entry = p2[addr.p2_index()].read_volatile();
+        let p1 = match self.next_table(&p2_entry) {
+            Ok(page_table) => page_table,
+            Err(PageTableWalkError::MappedToHugePage) => {
+                let frame =
+                    PhysFrame::containing_address(p2_entry.addr_with_mask(self.encryption_mask));
+                return Ok(Translation {
+                    frame: MappedFrame::Size2MiB(frame),
+                    offset: addr.as_u64() & 0o_777_7777,
+                    flags: p2_entry.flags(),
+                });
+            }
+            Err(err) => return Err(err.into_detailed(PageTableLevel::Two, &p2_entry)),
+        };
+
+        let p1_entry = p1[addr.p1_index()].read_volatile();
+        let flags = p1_entry.flags();
+
+        if p1_entry.is_unused() {
+            return Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::One,
+                flags,
+            });
+        }
+
+        let frame_addr = p1_entry.addr_with_mask(self.encryption_mask);
+        let frame = PhysFrame::from_start_address(frame_addr).map_err(|_| {
+            TranslateErrorDetailed::InvalidFrameAddress {
+                level: PageTableLevel::One,
+                flags,
+                addr: frame_addr,
+            }
+        })?;
+        Ok(Translation {
+            frame: MappedFrame::Size4KiB(frame),
+            offset: u64::from(addr.page_offset()),
+            flags,
+        })
+    }
+
     /// Internal helper function to create the page table of the next level if needed.
     ///
     /// If the passed entry is unused, a new frame is allocated from the given allocator, zeroed,
@@ -655,7 +1093,7 @@ impl<P: PageTableFrameMapping> PageTableWalker<P> {
 
         if entry.is_unused() {
             if let Some(frame) = allocator.allocate_frame() {
-                entry.set_frame(frame, insert_flags);
+                entry.set_addr_encrypted(frame.start_address(), insert_flags, self.encryption_mask);
                 created = true;
             } else {
                 return Err(PageTableCreateError::FrameAllocationFailed);
@@ -744,6 +1182,16 @@ impl From<PageTableWalkError> for UnmapError {
     }
 }
 
+impl From<PageTableWalkError> for RemapError {
+    #[inline]
+    fn from(err: PageTableWalkError) -> Self {
+        match err {
+            PageTableWalkError::MappedToHugePage => RemapError::ParentEntryHugePage,
+            PageTableWalkError::NotMapped => RemapError::PageNotMapped,
+        }
+    }
+}
+
 impl From<PageTableWalkError> for FlagUpdateError {
     #[inline]
     fn from(err: PageTableWalkError) -> Self {
@@ -754,6 +1202,23 @@ impl From<PageTableWalkError> for FlagUpdateError {
     }
 }
 
+impl PageTableWalkError {
+    /// Converts the error for the given entry of a table of the given level.
+    fn into_detailed(
+        self,
+        level: PageTableLevel,
+        entry: &PageTableEntry,
+    ) -> TranslateErrorDetailed {
+        let flags = entry.flags();
+        match self {
+            PageTableWalkError::NotMapped => TranslateErrorDetailed::NotMapped { level, flags },
+            PageTableWalkError::MappedToHugePage => {
+                TranslateErrorDetailed::InvalidHugePage { level, flags }
+            }
+        }
+    }
+}
+
 impl From<PageTableWalkError> for TranslateError {
     #[inline]
     fn from(err: PageTableWalkError) -> Self {
@@ -777,3 +1242,848 @@ pub unsafe trait PageTableFrameMapping {
     /// Translate the given physical frame to a virtual page table pointer.
     fn frame_to_pointer(&self, frame: PhysFrame) -> *mut PageTable;
 }
+
+/// Allows passing a closure as mapping, e.g. to [`MappedPageTable::new`].
+///
+/// The closure must fulfill the same requirements as any other `PageTableFrameMapping`.
+/// Since closures can't be marked as `unsafe`, this is part of the safety contract of the
+/// functions that accept a mapping instead.
+unsafe impl<F> PageTableFrameMapping for F
+where
+    F: Fn(PhysFrame) -> *mut PageTable,
+{
+    #[inline]
+    fn frame_to_pointer(&self, frame: PhysFrame) -> *mut PageTable {
+        self(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::paging::{frame::PhysFrameRange, page::PageRange};
+
+    /// Maps the frame with the start address `n * 4096` to the `n`th table of an array.
+    struct TableArray(*mut PageTable);
+
+    unsafe impl PageTableFrameMapping for TableArray {
+        fn frame_to_pointer(&self, frame: PhysFrame) -> *mut PageTable {
+            let index = frame.start_address().as_u64() / Size4KiB::SIZE;
+            unsafe { self.0.add(index as usize) }
+        }
+    }
+
+    /// Allocates the frames `1..limit`, frame 0 is used for the level 4 table.
+    struct TableAllocator {
+        next: u64,
+        limit: u64,
+    }
+
+    impl TableAllocator {
+        /// Hands out all tables except for the level 4 table.
+        fn new(tables: &[PageTable]) -> Self {
+            TableAllocator {
+                next: 1,
+                limit: tables.len() as u64,
+            }
+        }
+    }
+
+    unsafe impl FrameAllocator<Size4KiB> for TableAllocator {
+        fn allocate_frame(&mut self) -> Option<PhysFrame> {
+            if self.next < self.limit {
+                self.next += 1;
+                Some(PhysFrame::containing_address(PhysAddr::new(
+                    (self.next - 1) * Size4KiB::SIZE,
+                )))
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Returns a mapper whose level 4 table is `tables[0]` and an allocator for the other
+    /// tables, which are accessed as the frames `1..tables.len()`.
+    ///
+    /// The tables can be inspected through a raw pointer that was created before.
+    fn test_mapper(tables: &mut [PageTable]) -> (MappedPageTable<'_, TableArray>, TableAllocator) {
+        let allocator = TableAllocator::new(tables);
+        let base = tables.as_mut_ptr();
+        let mapper = unsafe { MappedPageTable::new(&mut *base, TableArray(base)) };
+        (mapper, allocator)
+    }
+
+    /// Records the deallocated frames.
+    struct RecordingDeallocator(Vec<PhysFrame>);
+
+    impl FrameDeallocator<Size4KiB> for RecordingDeallocator {
+        unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+            self.0.push(frame);
+        }
+    }
+
+    fn pages(start: u64, end: u64) -> PageRange {
+        Page::range(
+            Page::containing_address(VirtAddr::new(start * Size4KiB::SIZE)),
+            Page::containing_address(VirtAddr::new(end * Size4KiB::SIZE)),
+        )
+    }
+
+    fn frames(start: u64, end: u64) -> PhysFrameRange {
+        PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(start * Size4KiB::SIZE)),
+            PhysFrame::containing_address(PhysAddr::new(end * Size4KiB::SIZE)),
+        )
+    }
+
+    #[test]
+    pub fn test_translate_detailed() {
+        use crate::structures::paging::page::PageSizeKind;
+
+        let mut tables = vec![PageTable::new(); 4];
+        let base = tables.as_mut_ptr();
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let page: Page = Page::containing_address(VirtAddr::new(0x1220_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x100 * Size4KiB::SIZE));
+        unsafe { mapper.map_to(page, frame, flags, &mut allocator) }
+            .unwrap()
+            .ignore();
+
+        let translation = mapper.translate_detailed(page.start_address() + 8u64);
+        assert_eq!(
+            translation,
+            Ok(Translation {
+                frame: MappedFrame::Size4KiB(frame),
+                offset: 8,
+                flags,
+            })
+        );
+        let err = mapper
+            .translate_detailed(VirtAddr::new(0x80_0000_0000))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::Four,
+                flags: PageTableFlags::empty(),
+            }
+        );
+        let err = mapper.translate_detailed(page.start_address() + 0x1000u64);
+        assert_eq!(err.unwrap_err().level(), PageTableLevel::One);
+
+        let huge_page: Page<Size2MiB> = Page::containing_address(page.start_address());
+        assert_eq!(
+            mapper.translate_page_detailed(huge_page),
+            Err(TranslateErrorDetailed::NotHugePage {
+                level: PageTableLevel::One,
+                flags,
+                size: PageSizeKind::Size4KiB,
+            })
+        );
+        assert_eq!(mapper.translate_page_detailed(page), Ok(frame));
+
+        // replace the level 1 table with a 2MiB page
+        let p2 = unsafe { &mut *base.add(2) };
+        let huge_flags = flags | PageTableFlags::HUGE_PAGE;
+        p2[huge_page.p2_index()].set_addr(PhysAddr::new(0x20_0000), huge_flags);
+        assert_eq!(
+            mapper.translate_page_detailed(page),
+            Err(TranslateErrorDetailed::ParentEntryHugePage {
+                level: PageTableLevel::Two,
+                flags: huge_flags,
+                size: PageSizeKind::Size2MiB,
+            })
+        );
+        assert!(matches!(
+            TranslateError::from(mapper.translate_page_detailed(page).unwrap_err()),
+            TranslateError::ParentEntryHugePage
+        ));
+
+        mapper.level_4_table_mut()[0].set_flags(huge_flags);
+        assert_eq!(
+            mapper.translate_detailed(page.start_address()),
+            Err(TranslateErrorDetailed::InvalidHugePage {
+                level: PageTableLevel::Four,
+                flags: huge_flags,
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_clone_level_4_into() {
+        let mut tables = vec![PageTable::new(); 4];
+        let base = tables.as_mut_ptr();
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let kernel_page: Page = Page::containing_address(VirtAddr::new(0xffff_8000_0000_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x100 * Size4KiB::SIZE));
+        unsafe { mapper.map_to(kernel_page, frame, flags, &mut allocator) }
+            .unwrap()
+            .ignore();
+
+        let mut new_table = PageTable::new();
+        new_table[0].set_addr(PhysAddr::new(0x1000), flags);
+        mapper.clone_level_4_into(
+            &mut new_table,
+            PageTableIndex::new(256)..=PageTableIndex::new(511),
+        );
+        assert!(new_table[0].is_unused());
+        assert_eq!(new_table[256].addr(), mapper.level_4_table()[256].addr());
+        assert_eq!(new_table.count_used(), 1);
+
+        let new_mapper = unsafe { MappedPageTable::new(&mut new_table, TableArray(base)) };
+        assert_eq!(new_mapper.translate_page(kernel_page).ok(), Some(frame));
+
+        let mut empty_table = PageTable::new();
+        new_mapper.clone_level_4_into(
+            &mut empty_table,
+            PageTableIndex::new(511)..=PageTableIndex::new(256),
+        );
+        assert_eq!(empty_table.count_used(), 0);
+    }
+
+    #[test]
+    pub fn test_closure_mapping() {
+        let mut tables = vec![PageTable::new(); 4];
+        let base = tables.as_mut_ptr();
+        let table_array = TableArray(base);
+        let mapping = |frame| table_array.frame_to_pointer(frame);
+        let mut mapper = unsafe { MappedPageTable::new(&mut *base, mapping) };
+        let mut allocator = TableAllocator::new(&tables);
+        let page: Page = Page::containing_address(VirtAddr::new(0x1234_5000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x100 * Size4KiB::SIZE));
+
+        unsafe {
+            mapper
+                .map_to(page, frame, PageTableFlags::PRESENT, &mut allocator)
+                .unwrap()
+                .ignore();
+        }
+        assert_eq!(mapper.translate_page(page).ok(), Some(frame));
+    }
+
+    #[test]
+    pub fn test_map_range_rollback() {
+        let mut tables = vec![PageTable::new(); 4];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let already_mapped = Page::containing_address(VirtAddr::new(3 * Size4KiB::SIZE));
+
+        unsafe {
+            mapper
+                .map_range(pages(1, 1), frames(0x100, 0x100), flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            let (err, untouched) = mapper
+                .map_range(pages(1, 3), frames(0x100, 0x101), flags, &mut allocator)
+                .unwrap_err();
+            assert!(matches!(err, MapRangeError::LengthMismatch));
+            assert!(untouched.is_empty());
+
+            let frame = PhysFrame::containing_address(PhysAddr::new(0x200 * Size4KiB::SIZE));
+            mapper
+                .map_to(already_mapped, frame, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+
+            let (err, rolled_back) = mapper
+                .map_range(pages(1, 5), frames(0x100, 0x104), flags, &mut allocator)
+                .unwrap_err();
+            assert!(matches!(
+                err,
+                MapRangeError::MapTo(MapToError::PageAlreadyMapped(_))
+            ));
+            assert_eq!(rolled_back, pages(1, 3));
+            for page in pages(1, 3) {
+                assert!(mapper.translate_page(page).is_err());
+            }
+            assert_eq!(mapper.translate_page(already_mapped).ok(), Some(frame));
+
+            let flush = mapper
+                .map_range(pages(4, 6), frames(0x104, 0x106), flags, &mut allocator)
+                .unwrap();
+            assert_eq!(flush.pages(), pages(4, 6));
+            flush.ignore();
+        }
+
+        mapper.unmap_range(pages(3, 6)).unwrap().ignore();
+        let err = mapper.unmap_range(pages(3, 6)).unwrap_err();
+        assert_eq!(err.page, already_mapped);
+        assert!(err.flush.pages().is_empty());
+        err.flush.ignore();
+    }
+
+    #[test]
+    pub fn test_translate_huge_page() {
+        let mut tables = vec![PageTable::new(); 3];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+
+        let page: Page<Size2MiB> = Page::containing_address(VirtAddr::new(0x20_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe {
+            mapper
+                .map_to(page, frame, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+        }
+
+        match mapper.translate(VirtAddr::new(0x21_2345)) {
+            TranslateResult::Mapped {
+                frame: mapped,
+                offset,
+                flags,
+            } => {
+                assert_eq!(mapped, MappedFrame::Size2MiB(frame));
+                assert_eq!(AnyFrame::from(mapped).size(), Size2MiB::SIZE);
+                assert_eq!(offset, 0x1_2345);
+                assert!(flags.contains(PageTableFlags::WRITABLE | PageTableFlags::HUGE_PAGE));
+            }
+            other => panic!("unexpected translation result {:?}", other),
+        }
+        assert_eq!(
+            mapper.translate_addr(VirtAddr::new(0x21_2345)),
+            Some(PhysAddr::new(0x4001_2345))
+        );
+        assert!(matches!(
+            mapper.translate(VirtAddr::new(0x40_0000)),
+            TranslateResult::NotMapped
+        ));
+    }
+
+    #[test]
+    pub fn test_remap() {
+        let mut tables = vec![PageTable::new(); 4];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let page = Page::containing_address(VirtAddr::new(0x1000));
+        let old_frame = PhysFrame::containing_address(PhysAddr::new(0x10_0000));
+        let new_frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000));
+
+        unsafe {
+            assert!(matches!(
+                mapper.remap(page, new_frame, PageTableFlags::PRESENT),
+                Err(RemapError::PageNotMapped)
+            ));
+            mapper
+                .map_to(page, old_frame, PageTableFlags::PRESENT, &mut allocator)
+                .unwrap()
+                .ignore();
+
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            let (frame, flush) = mapper.remap(page, new_frame, flags).unwrap();
+            flush.ignore();
+            assert_eq!(frame, old_frame);
+        }
+
+        match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { frame, flags, .. } => {
+                assert_eq!(frame, MappedFrame::Size4KiB(new_frame));
+                assert!(flags.contains(PageTableFlags::WRITABLE));
+            }
+            other => panic!("unexpected translation result {:?}", other),
+        }
+    }
+
+    #[test]
+    pub fn test_remap_huge_pages() {
+        let mut tables = vec![PageTable::new(); 3];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        // 1GiB page
+        let page = Page::<Size1GiB>::containing_address(VirtAddr::new(0x4000_0000));
+        let old_frame = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+        let new_frame = PhysFrame::containing_address(PhysAddr::new(0x8000_0000));
+        unsafe {
+            mapper
+                .map_to(page, old_frame, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            let (frame, flush) = mapper
+                .remap(page, new_frame, PageTableFlags::PRESENT)
+                .unwrap();
+            flush.ignore();
+            assert_eq!(frame, old_frame);
+        }
+        assert_eq!(mapper.translate_page(page).unwrap(), new_frame);
+        assert_eq!(
+            mapper.page_flags(page).unwrap(),
+            PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE
+        );
+        // the 2MiB pages within the 1GiB page can't be remapped individually
+        let small_page = Page::<Size2MiB>::containing_address(page.start_address());
+        assert!(matches!(
+            unsafe {
+                mapper.remap(
+                    small_page,
+                    PhysFrame::containing_address(PhysAddr::zero()),
+                    flags,
+                )
+            },
+            Err(RemapError::ParentEntryHugePage)
+        ));
+
+        // 2MiB page
+        let page = Page::<Size2MiB>::containing_address(VirtAddr::new(0x20_0000));
+        let old_frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000));
+        let new_frame = PhysFrame::containing_address(PhysAddr::new(0x60_0000));
+        unsafe {
+            assert!(matches!(
+                mapper.remap(page, new_frame, flags),
+                Err(RemapError::PageNotMapped)
+            ));
+            mapper
+                .map_to(page, old_frame, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            let (frame, flush) = mapper.remap(page, new_frame, flags).unwrap();
+            flush.ignore();
+            assert_eq!(frame, old_frame);
+        }
+        assert_eq!(mapper.translate_page(page).unwrap(), new_frame);
+        assert_eq!(
+            mapper.translate_addr(page.start_address() + 0x1234u64),
+            Some(new_frame.start_address() + 0x1234u64)
+        );
+        assert!(matches!(
+            unsafe {
+                mapper.remap(
+                    Page::<Size4KiB>::containing_address(page.start_address()),
+                    PhysFrame::containing_address(PhysAddr::zero()),
+                    flags,
+                )
+            },
+            Err(RemapError::ParentEntryHugePage)
+        ));
+    }
+
+    #[test]
+    pub fn test_map_to_with_table_flags() {
+        use PageTableFlags as Flags;
+
+        let mut tables = vec![PageTable::new(); 4];
+        let base = tables.as_mut_ptr();
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        // the flags of the P4, P3, and P2 entries that lead to the pages below
+        let parent_flags = || {
+            let flags = |table| unsafe { (&*base.add(table))[0].flags() };
+            [flags(0), flags(1), flags(2)]
+        };
+        let page = |n: u64| Page::<Size4KiB>::containing_address(VirtAddr::new(n * 0x1000));
+
+        unsafe {
+            // creates the parent tables with exactly the given flags
+            mapper
+                .map_to_with_table_flags(
+                    page(1),
+                    frames(0x100, 0x100).start,
+                    Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE,
+                    Flags::PRESENT | Flags::USER_ACCESSIBLE,
+                    &mut allocator,
+                )
+                .unwrap()
+                .ignore();
+            assert_eq!(parent_flags(), [Flags::PRESENT | Flags::USER_ACCESSIBLE; 3]);
+
+            // adds to the flags of the existing parent entries, but doesn't remove any
+            mapper
+                .map_to_with_table_flags(
+                    page(2),
+                    frames(0x101, 0x101).start,
+                    Flags::PRESENT | Flags::WRITABLE,
+                    Flags::PRESENT | Flags::WRITABLE,
+                    &mut allocator,
+                )
+                .unwrap()
+                .ignore();
+            assert_eq!(
+                parent_flags(),
+                [Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE; 3]
+            );
+
+            // `map_to` doesn't propagate `NO_EXECUTE` to the parents
+            mapper
+                .map_to(
+                    page(3),
+                    frames(0x102, 0x102).start,
+                    Flags::PRESENT | Flags::NO_EXECUTE,
+                    &mut allocator,
+                )
+                .unwrap()
+                .ignore();
+            assert_eq!(
+                parent_flags(),
+                [Flags::PRESENT | Flags::WRITABLE | Flags::USER_ACCESSIBLE; 3]
+            );
+        }
+        assert_eq!(allocator.next, 4);
+    }
+
+    #[test]
+    pub fn test_clear_accessed_range() {
+        let mut tables = vec![PageTable::new(); 4];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        // page 2 stays unmapped
+        for (page, accessed) in [(1, true), (3, false), (4, true)] {
+            let flags = if accessed {
+                flags | PageTableFlags::ACCESSED
+            } else {
+                flags
+            };
+            unsafe {
+                mapper
+                    .map_to_range(
+                        pages(page, page + 1),
+                        frames(page, page).start,
+                        flags,
+                        &mut allocator,
+                    )
+                    .unwrap()
+                    .ignore();
+            }
+        }
+
+        let mut bitmap = [u64::MAX];
+        mapper
+            .clear_accessed_range(pages(1, 5), &mut bitmap)
+            .unwrap()
+            .ignore();
+        assert_eq!(bitmap, [0b1001 | (u64::MAX << 4)]);
+        assert!(!mapper.is_accessed(pages(1, 2).start).unwrap());
+
+        mapper
+            .clear_accessed_range(pages(1, 5), &mut bitmap)
+            .unwrap()
+            .ignore();
+        assert_eq!(bitmap, [u64::MAX << 4]);
+    }
+
+    #[test]
+    pub fn test_clean_up() {
+        let mut tables = vec![PageTable::new(); 8];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(0x1000));
+        let huge_page = Page::<Size2MiB>::containing_address(VirtAddr::new(0x80_0000_0000));
+
+        unsafe {
+            mapper
+                .map_to(page, frames(0x100, 0x100).start, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            let frame = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+            mapper
+                .map_to(huge_page, frame, flags, &mut allocator)
+                .unwrap()
+                .ignore();
+        }
+        mapper.unmap(page).unwrap().1.ignore();
+
+        let mut deallocator = RecordingDeallocator(Vec::new());
+        unsafe { mapper.clean_up(&mut deallocator) };
+        // the P3, P2, and P1 tables of the unmapped page, in bottom-up order
+        assert_eq!(deallocator.0, frames(1, 4).rev().collect::<Vec<_>>());
+        assert!(!mapper.level_4_table[0]
+            .flags()
+            .contains(PageTableFlags::PRESENT));
+        assert_eq!(
+            mapper.translate_page(huge_page).ok(),
+            Some(PhysFrame::containing_address(PhysAddr::new(0x4000_0000)))
+        );
+
+        unsafe { mapper.clean_up(&mut deallocator) };
+        assert_eq!(deallocator.0.len(), 3);
+    }
+
+    #[test]
+    pub fn test_unmap_range_and_deallocate() {
+        let mut tables = vec![PageTable::new(); 8];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        unsafe {
+            mapper
+                .map_range(pages(1, 4), frames(0x100, 0x103), flags, &mut allocator)
+                .unwrap()
+                .ignore();
+        }
+
+        let mut deallocator = RecordingDeallocator(Vec::new());
+        unsafe {
+            mapper
+                .unmap_and_deallocate(pages(1, 2).start, &mut deallocator)
+                .unwrap()
+                .ignore();
+            mapper
+                .unmap_range_and_deallocate(pages(2, 4), &mut deallocator)
+                .unwrap()
+                .ignore();
+        }
+        // the mapped frames, followed by the P1, P2, and P3 tables
+        let mut expected = frames(0x100, 0x103).collect::<Vec<_>>();
+        expected.extend(frames(1, 4).rev());
+        assert_eq!(deallocator.0, expected);
+        assert!(!mapper.level_4_table[0]
+            .flags()
+            .contains(PageTableFlags::PRESENT));
+    }
+
+    #[test]
+    pub fn test_mapped_regions() {
+        let mut tables = vec![PageTable::new(); 8];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let frame = |addr| PhysFrame::containing_address(PhysAddr::new(addr));
+
+        unsafe {
+            mapper
+                .map_range(pages(1, 4), frames(0x100, 0x103), flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            let (page_4, page_5) = (pages(4, 5).start, pages(5, 6).start);
+            mapper
+                .map_to(
+                    page_4,
+                    frame(0x10_3000),
+                    PageTableFlags::PRESENT,
+                    &mut allocator,
+                )
+                .unwrap()
+                .ignore();
+            mapper
+                .map_to(page_5, frame(0x20_0000), flags, &mut allocator)
+                .unwrap()
+                .ignore();
+            for (virt, phys) in [(0x40_0000, 0x20_0000), (0x60_0000, 0x40_0000)] {
+                let page = Page::<Size2MiB>::containing_address(VirtAddr::new(virt));
+                mapper
+                    .map_to(
+                        page,
+                        PhysFrame::containing_address(PhysAddr::new(phys)),
+                        flags,
+                        &mut allocator,
+                    )
+                    .unwrap()
+                    .ignore();
+            }
+            let page = Page::<Size1GiB>::containing_address(VirtAddr::new(0x80_0000_0000));
+            mapper
+                .map_to(
+                    page,
+                    PhysFrame::containing_address(PhysAddr::new(0x4000_0000)),
+                    flags,
+                    &mut allocator,
+                )
+                .unwrap()
+                .ignore();
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(0xffff_8000_0000_0000));
+            mapper
+                .map_to(page, frame(0x30_0000), flags, &mut allocator)
+                .unwrap()
+                .ignore();
+        }
+
+        let region = |start, len, phys, page_size, flags| MappedRegion {
+            start: VirtAddr::new(start),
+            len,
+            phys_start: PhysAddr::new(phys),
+            page_size,
+            flags,
+        };
+        let huge = flags | PageTableFlags::HUGE_PAGE;
+        let expected = [
+            region(0x1000, 0x3000, 0x10_0000, PageSizeKind::Size4KiB, flags),
+            region(
+                0x4000,
+                0x1000,
+                0x10_3000,
+                PageSizeKind::Size4KiB,
+                PageTableFlags::PRESENT,
+            ),
+            region(0x5000, 0x1000, 0x20_0000, PageSizeKind::Size4KiB, flags),
+            region(
+                0x40_0000,
+                0x40_0000,
+                0x20_0000,
+                PageSizeKind::Size2MiB,
+                huge,
+            ),
+            region(
+                0x80_0000_0000,
+                0x4000_0000,
+                0x4000_0000,
+                PageSizeKind::Size1GiB,
+                huge,
+            ),
+            region(
+                0xffff_8000_0000_0000,
+                0x1000,
+                0x30_0000,
+                PageSizeKind::Size4KiB,
+                flags,
+            ),
+        ];
+        assert_eq!(mapper.mapped_regions().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    pub fn test_identity_map_region() {
+        let mut tables = vec![PageTable::new(); 4];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        unsafe {
+            // rounded to the frames 0x10..0x13
+            let flush = mapper
+                .identity_map_region(PhysAddr::new(0x10_800), 0x2_800, flags, &mut allocator)
+                .unwrap();
+            assert_eq!(flush.pages(), pages(0x10, 0x13));
+            flush.ignore();
+
+            let (err, untouched) = Mapper::<Size4KiB>::identity_map_region(
+                &mut mapper,
+                PhysAddr::new(0x7fff_ffff_f000),
+                0x1000,
+                flags,
+                &mut allocator,
+            )
+            .unwrap_err();
+            assert!(matches!(err, MapRangeError::NonCanonical));
+            assert!(untouched.is_empty());
+        }
+        for page in pages(0x10, 0x13) {
+            assert_eq!(
+                mapper.translate_page(page).ok(),
+                Some(PhysFrame::containing_address(PhysAddr::new(
+                    page.start_address().as_u64()
+                )))
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_map_range_auto() {
+        let mut tables = vec![PageTable::new(); 5];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        // the last table is only handed out after the first attempt
+        allocator.limit = 4;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        // a 4KiB page before and after two 2MiB pages
+        let start = 0x1f_f000;
+        let len = 2 * Size4KiB::SIZE + 2 * Size2MiB::SIZE;
+        let huge_page = Page::<Size2MiB>::containing_address(VirtAddr::new(0x20_0000));
+
+        unsafe {
+            let err = mapper
+                .map_range_auto(
+                    VirtAddr::new(start),
+                    PhysAddr::new(start),
+                    0x800,
+                    flags,
+                    &mut allocator,
+                )
+                .unwrap_err();
+            assert!(matches!(err, MapRangeAutoError::NotAligned));
+
+            // the level 1 table of the last page can't be allocated
+            let err = mapper
+                .map_range_auto(
+                    VirtAddr::new(start),
+                    PhysAddr::new(start),
+                    len,
+                    flags,
+                    &mut allocator,
+                )
+                .unwrap_err();
+            assert!(matches!(err, MapRangeAutoError::FrameAllocationFailed));
+            assert!(mapper.translate_page(huge_page).is_err());
+            assert!(mapper.translate_addr(VirtAddr::new(start)).is_none());
+
+            allocator.limit = 5;
+            let (counts, flush) = mapper
+                .map_range_auto(
+                    VirtAddr::new(start),
+                    PhysAddr::new(start),
+                    len,
+                    flags,
+                    &mut allocator,
+                )
+                .unwrap();
+            flush.ignore();
+            assert_eq!(
+                counts,
+                MappedPageCounts {
+                    size_4kib: 2,
+                    size_2mib: 2,
+                    size_1gib: 0,
+                }
+            );
+        }
+        assert_eq!(
+            mapper.translate_page(huge_page).ok(),
+            Some(PhysFrame::containing_address(PhysAddr::new(0x20_0000)))
+        );
+        assert_eq!(
+            mapper.translate_addr(VirtAddr::new(start + len - 1)),
+            Some(PhysAddr::new(start + len - 1))
+        );
+    }
+
+    #[test]
+    pub fn test_update_flags_keeps_os_bits() {
+        let mut tables = vec![PageTable::new(); 4];
+        let base = tables.as_mut_ptr();
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let page: Page = Page::containing_address(VirtAddr::new(0x1220_0000));
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x100 * Size4KiB::SIZE));
+        unsafe { mapper.map_to(page, frame, flags, &mut allocator) }
+            .unwrap()
+            .ignore();
+
+        // the level 1 table is the last allocated frame
+        let p1 = unsafe { &mut *base.add(3) };
+        let entry = &mut p1[page.p1_index()];
+        entry.set_os_bits_low(0b110);
+        entry.set_os_bits_high(0x55);
+
+        unsafe { mapper.update_flags(page, PageTableFlags::PRESENT) }
+            .unwrap()
+            .ignore();
+        let p1 = unsafe { &*base.add(3) };
+        let entry = &p1[page.p1_index()];
+        assert_eq!(entry.os_bits_low(), 0b110);
+        assert_eq!(entry.os_bits_high(), 0x55);
+        assert_eq!(entry.addr(), frame.start_address());
+        assert_eq!(
+            mapper.page_flags(page).unwrap(),
+            (PageTableFlags::PRESENT
+                | PageTableFlags::BIT_10
+                | PageTableFlags::BIT_11
+                | PageTableFlags::from_bits_truncate(0x55 << 52))
+        );
+    }
+
+    #[test]
+    pub fn test_map_to_range_last_frame() {
+        let mut tables = vec![PageTable::new(); 4];
+        let (mut mapper, mut allocator) = test_mapper(&mut tables);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let last_frame = PhysFrame::containing_address(PhysAddr::new(0x000f_ffff_ffff_f000));
+
+        unsafe { mapper.map_to_range(pages(1, 3), last_frame - 1, flags, &mut allocator) }
+            .unwrap()
+            .ignore();
+        assert_eq!(
+            mapper.translate_page(pages(1, 3).start).unwrap(),
+            last_frame - 1
+        );
+        assert_eq!(
+            mapper.translate_page(pages(2, 3).start).unwrap(),
+            last_frame
+        );
+    }
+}
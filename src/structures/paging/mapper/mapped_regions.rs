@@ -0,0 +1,152 @@
+//! Enumerates the mapped regions of a page table hierarchy.
+
+use crate::structures::paging::{
+    page::PageSizeKind,
+    page_table::{PageTable, PageTableEntry, PageTableFlags, PageTableIndex},
+};
+use crate::{PhysAddr, VirtAddr};
+
+/// A virtually and physically contiguous range of pages with identical flags, returned by the
+/// `mapped_regions` methods of the mapper types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRegion {
+    /// The virtual start address of the region.
+    pub start: VirtAddr,
+    /// The size of the region in bytes, a multiple of the page size.
+    pub len: u64,
+    /// The physical address that `start` is mapped to.
+    pub phys_start: PhysAddr,
+    /// The size of the pages that map the region.
+    pub page_size: PageSizeKind,
+    /// The flags of the page table entries that map the region.
+    pub flags: PageTableFlags,
+}
+
+impl MappedRegion {
+    /// Returns whether the given page directly follows this region, both virtually and
+    /// physically, and is mapped with the same page size and flags.
+    fn is_continued_by(&self, page: &MappedRegion) -> bool {
+        self.page_size == page.page_size
+            && self.flags == page.flags
+            && self.start.as_u64().checked_add(self.len) == Some(page.start.as_u64())
+            && self.phys_start.as_u64().checked_add(self.len) == Some(page.phys_start.as_u64())
+    }
+}
+
+/// Walks a 4-level page table hierarchy depth-first and yields the coalesced mapped regions.
+///
+/// The walk doesn't allocate: it only keeps the tables and indices of the current path.
+/// `next_table` is called with the level 4 based depth of an entry that points to a page
+/// table, the virtual address that the entry is responsible for, and the entry itself.
+pub(super) struct MappedRegions<'a, F> {
+    tables: [&'a PageTable; 4],
+    /// The index of the next entry to visit at each depth.
+    indices: [usize; 4],
+    depth: usize,
+    /// A level 4 index that is skipped, e.g. the recursive entry.
+    skip: Option<PageTableIndex>,
+    encryption_mask: u64,
+    next_table: F,
+    pending: Option<MappedRegion>,
+}
+
+impl<'a, F> MappedRegions<'a, F>
+where
+    F: Fn(usize, VirtAddr, &PageTableEntry) -> Option<&'a PageTable>,
+{
+    pub(super) fn new(
+        level_4_table: &'a PageTable,
+        skip: Option<PageTableIndex>,
+        encryption_mask: u64,
+        next_table: F,
+    ) -> Self {
+        MappedRegions {
+            tables: [level_4_table; 4],
+            indices: [0; 4],
+            depth: 0,
+            skip,
+            encryption_mask,
+            next_table,
+            pending: None,
+        }
+    }
+
+    /// Returns the virtual address of the entry with the given index at the current depth.
+    fn addr(&self, index: usize) -> VirtAddr {
+        // the indices above the current depth were already advanced past the current path
+        let addr = self.indices[..self.depth]
+            .iter()
+            .enumerate()
+            .fold(0, |addr, (depth, &i)| {
+                addr | ((i as u64 - 1) << shift(depth))
+            });
+        VirtAddr::new_truncate(addr | ((index as u64) << shift(self.depth)))
+    }
+}
+
+impl<'a, F> Iterator for MappedRegions<'a, F>
+where
+    F: Fn(usize, VirtAddr, &PageTableEntry) -> Option<&'a PageTable>,
+{
+    type Item = MappedRegion;
+
+    fn next(&mut self) -> Option<MappedRegion> {
+        loop {
+            let index = self.indices[self.depth];
+            if index == 512 {
+                if self.depth == 0 {
+                    return self.pending.take();
+                }
+                self.depth -= 1;
+                continue;
+            }
+            self.indices[self.depth] += 1;
+
+            let entry = self.tables[self.depth][index].read_volatile();
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT)
+                || (self.depth == 0 && self.skip.map(usize::from) == Some(index))
+            {
+                continue;
+            }
+
+            let addr = self.addr(index);
+            let page_size = match self.depth {
+                3 => PageSizeKind::Size4KiB,
+                2 if flags.contains(PageTableFlags::HUGE_PAGE) => PageSizeKind::Size2MiB,
+                1 if flags.contains(PageTableFlags::HUGE_PAGE) => PageSizeKind::Size1GiB,
+                // the `HUGE_PAGE` flag is invalid in level 4 entries
+                0 if flags.contains(PageTableFlags::HUGE_PAGE) => continue,
+                depth => {
+                    if let Some(table) = (self.next_table)(depth, addr, &entry) {
+                        self.depth += 1;
+                        self.tables[self.depth] = table;
+                        self.indices[self.depth] = 0;
+                    }
+                    continue;
+                }
+            };
+
+            let page = MappedRegion {
+                start: addr,
+                len: page_size.size(),
+                phys_start: entry.addr_with_mask(self.encryption_mask),
+                page_size,
+                flags,
+            };
+            match &mut self.pending {
+                Some(pending) if pending.is_continued_by(&page) => pending.len += page.len,
+                pending => {
+                    if let Some(region) = pending.replace(page) {
+                        return Some(region);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the number of address bits below the index of the given level 4 based depth.
+fn shift(depth: usize) -> u64 {
+    39 - 9 * depth as u64
+}
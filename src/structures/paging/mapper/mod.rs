@@ -1,28 +1,189 @@
 //! Abstractions for reading and modifying the mapping of pages.
 
 pub use self::mapped_page_table::{MappedPageTable, PageTableFrameMapping};
+pub use self::mapped_regions::MappedRegion;
 #[cfg(target_pointer_width = "64")]
-pub use self::offset_page_table::OffsetPageTable;
+pub use self::offset_page_table::{OffsetPageTable, PhysOffset};
 #[cfg(feature = "instructions")]
 pub use self::recursive_page_table::{InvalidPageTable, RecursivePageTable};
 
 use crate::structures::paging::{
-    frame_alloc::FrameAllocator, page_table::PageTableFlags, Page, PageSize, PhysFrame, Size1GiB,
-    Size2MiB, Size4KiB,
+    frame::{AnyFrame, PhysFrameRange},
+    frame_alloc::{FrameAllocator, FrameDeallocator},
+    page::{PageRange, PageRangeInclusive, PageSizeKind},
+    page_table::{PageTableFlags, PageTableLevel},
+    Page, PageSize, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
 };
 use crate::{PhysAddr, VirtAddr};
 
 mod mapped_page_table;
+mod mapped_regions;
 mod offset_page_table;
 #[cfg(feature = "instructions")]
 mod recursive_page_table;
 
-/// An empty convencience trait that requires the `Mapper` trait for all page sizes.
-pub trait MapperAllSizes: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB> {}
+/// A convencience trait that requires the `Mapper` trait for all page sizes.
+pub trait MapperAllSizes: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB> {
+    /// Maps the `len` bytes starting at `virt` to the physical memory starting at `phys`,
+    /// using the largest possible pages.
+    ///
+    /// For each part of the range, the largest page size is chosen that both addresses are
+    /// aligned to and that fits into the remaining length. So unaligned parts at the start
+    /// and end of the range are mapped with smaller pages. Note that 1GiB pages are only
+    /// supported by the CPU if CPUID leaf 0x80000001 reports it (bit 26 of `edx`).
+    ///
+    /// Returns the number of created pages of each size and a [`MapperFlushAll`] for the
+    /// complete range. Either the complete range is mapped or none of it: if mapping a page
+    /// fails, all pages that were mapped by this call are unmapped again. The rolled back
+    /// pages might still be cached in the TLB, so they should be flushed before the range
+    /// is used again. Page tables created by this call are not freed.
+    ///
+    /// ## Safety
+    ///
+    /// This is a convencience function that invokes [`Mapper::map_to`] internally, so
+    /// all safety requirements of it also apply for this function.
+    unsafe fn map_range_auto<A>(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        len: u64,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<(MappedPageCounts, MapperFlushAll), MapRangeAutoError>
+    where
+        Self: Sized,
+        A: FrameAllocator<Size4KiB> + ?Sized,
+    {
+        if !virt.is_aligned(Size4KiB::SIZE)
+            || !phys.is_aligned(Size4KiB::SIZE)
+            || crate::align_down(len, Size4KiB::SIZE) != len
+        {
+            return Err(MapRangeAutoError::NotAligned);
+        }
+        if len == 0 {
+            return Ok((MappedPageCounts::default(), MapperFlushAll::new()));
+        }
+        let virt_last = virt.as_u64().checked_add(len - 1);
+        let phys_last = phys.as_u64().checked_add(len - 1);
+        match (virt_last, phys_last.map(PhysAddr::try_new)) {
+            // the range must not leave the canonical half of the address space it starts in
+            (Some(last), Some(Ok(_))) if last >> 47 == virt.as_u64() >> 47 => {}
+            _ => return Err(MapRangeAutoError::InvalidAddress),
+        }
+
+        let mut counts = MappedPageCounts::default();
+        let mut offset = 0;
+        while offset < len {
+            let v = VirtAddr::new(virt.as_u64() + offset);
+            let p = PhysAddr::new(phys.as_u64() + offset);
+            let size = largest_page_size(v, p, len - offset);
+            let result = match size {
+                PageSizeKind::Size4KiB => Mapper::<Size4KiB>::map_to(
+                    self,
+                    Page::containing_address(v),
+                    PhysFrame::containing_address(p),
+                    flags,
+                    frame_allocator,
+                )
+                .map(MapperFlush::ignore)
+                .map_err(MapRangeAutoError::from),
+                PageSizeKind::Size2MiB => Mapper::<Size2MiB>::map_to(
+                    self,
+                    Page::containing_address(v),
+                    PhysFrame::containing_address(p),
+                    flags,
+                    frame_allocator,
+                )
+                .map(MapperFlush::ignore)
+                .map_err(MapRangeAutoError::from),
+                PageSizeKind::Size1GiB => Mapper::<Size1GiB>::map_to(
+                    self,
+                    Page::containing_address(v),
+                    PhysFrame::containing_address(p),
+                    flags,
+                    frame_allocator,
+                )
+                .map(MapperFlush::ignore)
+                .map_err(MapRangeAutoError::from),
+            };
+
+            if let Err(err) = result {
+                // the page sizes are chosen deterministically, so the mapped pages can be
+                // found again
+                let mut rollback_offset = 0;
+                while rollback_offset < offset {
+                    let v = VirtAddr::new(virt.as_u64() + rollback_offset);
+                    let p = PhysAddr::new(phys.as_u64() + rollback_offset);
+                    let size = largest_page_size(v, p, len - rollback_offset);
+                    // the page was mapped above, so unmapping it can't fail
+                    match size {
+                        PageSizeKind::Size4KiB => {
+                            let page = Page::<Size4KiB>::containing_address(v);
+                            if let Ok((_, flush)) = self.unmap(page) {
+                                flush.ignore();
+                            }
+                        }
+                        PageSizeKind::Size2MiB => {
+                            let page = Page::<Size2MiB>::containing_address(v);
+                            if let Ok((_, flush)) = self.unmap(page) {
+                                flush.ignore();
+                            }
+                        }
+                        PageSizeKind::Size1GiB => {
+                            let page = Page::<Size1GiB>::containing_address(v);
+                            if let Ok((_, flush)) = self.unmap(page) {
+                                flush.ignore();
+                            }
+                        }
+                    }
+                    rollback_offset += size.size();
+                }
+                return Err(err);
+            }
+
+            match size {
+                PageSizeKind::Size4KiB => counts.size_4kib += 1,
+                PageSizeKind::Size2MiB => counts.size_2mib += 1,
+                PageSizeKind::Size1GiB => counts.size_1gib += 1,
+            }
+            offset += size.size();
+        }
+        Ok((counts, MapperFlushAll::new()))
+    }
+}
 
 impl<T> MapperAllSizes for T where T: Mapper<Size4KiB> + Mapper<Size2MiB> + Mapper<Size1GiB> {}
 
+/// Returns the largest page size that both addresses are aligned to and that is not larger
+/// than `remaining`.
+fn largest_page_size(virt: VirtAddr, phys: PhysAddr, remaining: u64) -> PageSizeKind {
+    for &size in &[PageSizeKind::Size1GiB, PageSizeKind::Size2MiB] {
+        if virt.is_aligned(size.size()) && phys.is_aligned(size.size()) && remaining >= size.size()
+        {
+            return size;
+        }
+    }
+    PageSizeKind::Size4KiB
+}
+
+/// The number of pages of each size that were created by [`MapperAllSizes::map_range_auto`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MappedPageCounts {
+    /// The number of 4KiB pages.
+    pub size_4kib: u64,
+    /// The number of 2MiB pages.
+    pub size_2mib: u64,
+    /// The number of 1GiB pages.
+    pub size_1gib: u64,
+}
+
 /// Provides methods for translating virtual addresses.
+///
+/// All methods only need a shared reference to the page table, so translations can be done
+/// while the page table is borrowed immutably (e.g. behind the read guard of a lock), while
+/// changing mappings through [`Mapper`] requires exclusive access. Page table entries are
+/// read through [`PageTableEntry::read_volatile`](super::page_table::PageTableEntry::read_volatile),
+/// so concurrent updates of the `ACCESSED` and `DIRTY` flags by the CPU are fine.
 pub trait Translate {
     /// Return the frame that the given virtual address is mapped to and the offset within that
     /// frame.
@@ -31,7 +192,99 @@ pub trait Translate {
     /// frame is returned. Otherwise an error value is returned.
     ///
     /// This function works with huge pages of all sizes.
-    fn translate(&self, addr: VirtAddr) -> TranslateResult;
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the page table hierarchy contains an entry with the `HUGE_PAGE` flag set for
+    /// a level that doesn't support huge pages. Use
+    /// [`translate_detailed`](Translate::translate_detailed) to get an error instead.
+    ///
+    /// The default implementation is based on `translate_detailed`.
+    #[inline]
+    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+        match self.translate_detailed(addr) {
+            Ok(Translation {
+                frame,
+                offset,
+                flags,
+            }) => TranslateResult::Mapped {
+                frame,
+                offset,
+                flags,
+            },
+            Err(TranslateErrorDetailed::InvalidFrameAddress { addr, .. }) => {
+                TranslateResult::InvalidFrameAddress(addr)
+            }
+            Err(TranslateErrorDetailed::InvalidHugePage { level, .. }) => {
+                panic!("level {} entry has huge page bit set", level as u8)
+            }
+            Err(_) => TranslateResult::NotMapped,
+        }
+    }
+
+    /// Return the frame that the given virtual address is mapped to and the offset within that
+    /// frame, or an error that describes at which level the page table walk failed.
+    ///
+    /// This works like [`translate`](Translate::translate), but the returned
+    /// [`TranslateErrorDetailed`] includes the level and the flags of the page table entry
+    /// that caused the error.
+    ///
+    /// The default implementation is based on `translate`, which doesn't report where the
+    /// walk failed, so its errors always refer to level 1 and have empty flags. Implementors
+    /// must override at least one of `translate` and `translate_detailed`, since their default
+    /// implementations call each other.
+    #[inline]
+    fn translate_detailed(&self, addr: VirtAddr) -> Result<Translation, TranslateErrorDetailed> {
+        match self.translate(addr) {
+            TranslateResult::Mapped {
+                frame,
+                offset,
+                flags,
+            } => Ok(Translation {
+                frame,
+                offset,
+                flags,
+            }),
+            TranslateResult::NotMapped => Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::One,
+                flags: PageTableFlags::empty(),
+            }),
+            TranslateResult::InvalidFrameAddress(addr) => {
+                Err(TranslateErrorDetailed::InvalidFrameAddress {
+                    level: PageTableLevel::One,
+                    flags: PageTableFlags::empty(),
+                    addr,
+                })
+            }
+        }
+    }
+
+    /// Return the frame that the given page is mapped to, or an error that describes at which
+    /// level the page table walk failed.
+    ///
+    /// This works like [`Mapper::translate_page`], but the returned [`TranslateErrorDetailed`]
+    /// includes the level and the flags of the page table entry that caused the error. If the
+    /// page is mapped with a different page size than `S`, the size of the actual mapping
+    /// is reported too.
+    #[inline]
+    fn translate_page_detailed<S: PageSize>(
+        &self,
+        page: Page<S>,
+    ) -> Result<PhysFrame<S>, TranslateErrorDetailed>
+    where
+        Self: Sized,
+    {
+        let translation = self.translate_detailed(page.start_address())?;
+        let frame = translation.frame;
+        let (level, flags, size) = (frame.level(), translation.flags, frame.size_kind());
+        if frame.size() > S::SIZE {
+            Err(TranslateErrorDetailed::ParentEntryHugePage { level, flags, size })
+        } else if frame.size() < S::SIZE {
+            Err(TranslateErrorDetailed::NotHugePage { level, flags, size })
+        } else {
+            Ok(PhysFrame::containing_address(frame.start_address()))
+        }
+    }
 
     /// Translates the given virtual address to the physical address that it maps to.
     ///
@@ -73,8 +326,23 @@ pub enum TranslateResult {
     InvalidFrameAddress(PhysAddr),
 }
 
+/// A successful translation returned by [`Translate::translate_detailed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Translation {
+    /// The mapped frame.
+    pub frame: MappedFrame,
+    /// The offset whithin the mapped frame.
+    pub offset: u64,
+    /// The entry flags in the lowest-level page table.
+    ///
+    /// See [`TranslateResult::Mapped`] for how they relate to the effective flags.
+    pub flags: PageTableFlags,
+}
+
 /// Represents a physical frame mapped in a page table.
-#[derive(Debug)]
+///
+/// The frame can be converted into an [`AnyFrame`] for handling all sizes uniformly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MappedFrame {
     /// The virtual address is mapped to a 4KiB frame.
     Size4KiB(PhysFrame<Size4KiB>),
@@ -106,9 +374,164 @@ impl MappedFrame {
             }
         }
     }
+
+    /// Returns the size of the frame as a [`PageSizeKind`].
+    #[inline]
+    pub const fn size_kind(&self) -> PageSizeKind {
+        match self {
+            MappedFrame::Size4KiB(_) => PageSizeKind::Size4KiB,
+            MappedFrame::Size2MiB(_) => PageSizeKind::Size2MiB,
+            MappedFrame::Size1GiB(_) => PageSizeKind::Size1GiB,
+        }
+    }
+
+    /// Returns the level of the page table entry that maps the frame.
+    #[inline]
+    pub const fn level(&self) -> PageTableLevel {
+        match self {
+            MappedFrame::Size4KiB(_) => PageTableLevel::One,
+            MappedFrame::Size2MiB(_) => PageTableLevel::Two,
+            MappedFrame::Size1GiB(_) => PageTableLevel::Three,
+        }
+    }
+}
+
+impl From<MappedFrame> for AnyFrame {
+    #[inline]
+    fn from(frame: MappedFrame) -> Self {
+        match frame {
+            MappedFrame::Size4KiB(frame) => frame.into(),
+            MappedFrame::Size2MiB(frame) => frame.into(),
+            MappedFrame::Size1GiB(frame) => frame.into(),
+        }
+    }
+}
+
+impl From<AnyFrame> for MappedFrame {
+    #[inline]
+    fn from(frame: AnyFrame) -> Self {
+        let start_address = frame.start_address();
+        // SAFETY: the start address of an `AnyFrame` is aligned to its size
+        unsafe {
+            match frame.size_kind() {
+                PageSizeKind::Size4KiB => {
+                    MappedFrame::Size4KiB(PhysFrame::from_start_address_unchecked(start_address))
+                }
+                PageSizeKind::Size2MiB => {
+                    MappedFrame::Size2MiB(PhysFrame::from_start_address_unchecked(start_address))
+                }
+                PageSizeKind::Size1GiB => {
+                    MappedFrame::Size1GiB(PhysFrame::from_start_address_unchecked(start_address))
+                }
+            }
+        }
+    }
+}
+
+/// Translates the given virtual address in the currently active address space to the physical
+/// address that it maps to, or `None` if the address is not mapped.
+///
+/// This walks the page table hierarchy pointed to by CR3, using the same mapping of the
+/// physical memory as [`OffsetPageTable`]. Huge pages are handled. For repeated translations,
+/// creating an `OffsetPageTable` and using the [`Translate`] trait is more flexible.
+///
+/// ## Safety
+///
+/// This function is unsafe because the caller must guarantee that the complete physical memory
+/// is mapped to virtual memory at the passed `phys_offset`.
+#[cfg(feature = "instructions")]
+#[inline]
+pub unsafe fn translate_addr(addr: VirtAddr, phys_offset: VirtAddr) -> Option<PhysAddr> {
+    translate_addr_with_encryption_mask(addr, phys_offset, 0)
+}
+
+/// Translates the given virtual address in the currently active address space like
+/// [`translate_addr`], for a page table hierarchy that uses memory encryption, e.g. inside an
+/// AMD SEV guest.
+///
+/// The bits in `encryption_mask` are cleared from CR3 and from the addresses of all page table
+/// entries, so the returned address doesn't contain the encryption bit. See
+/// [`MappedPageTable::with_encryption_mask`] for how to obtain the mask.
+///
+/// ## Safety
+///
+/// Same as for [`translate_addr`]. Additionally, the caller must guarantee that
+/// `encryption_mask` matches the encryption bit of the CPU.
+#[cfg(feature = "instructions")]
+pub unsafe fn translate_addr_with_encryption_mask(
+    addr: VirtAddr,
+    phys_offset: VirtAddr,
+    encryption_mask: u64,
+) -> Option<PhysAddr> {
+    let (level_4_table_frame, _) = crate::registers::control::Cr3::read();
+    let level_4_table_addr =
+        PhysAddr::new(level_4_table_frame.start_address().as_u64() & !encryption_mask);
+    walk_page_tables(addr, level_4_table_addr, phys_offset, encryption_mask)
+}
+
+/// Translates `addr` through the page table hierarchy whose level 4 table is stored at
+/// `level_4_table_addr`.
+///
+/// ## Safety
+///
+/// The complete physical memory must be mapped to virtual memory at `phys_offset`.
+#[cfg(feature = "instructions")]
+unsafe fn walk_page_tables(
+    addr: VirtAddr,
+    level_4_table_addr: PhysAddr,
+    phys_offset: VirtAddr,
+    encryption_mask: u64,
+) -> Option<PhysAddr> {
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+    let mut frame_addr = level_4_table_addr;
+
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let table =
+            &*(phys_offset + frame_addr.as_u64()).as_ptr::<crate::structures::paging::PageTable>();
+        let entry = table[index].read_volatile();
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        // level 1 entries use the bit of the huge page flag for the PAT
+        if level < 3 && flags.contains(PageTableFlags::HUGE_PAGE) {
+            let page_size = match level {
+                1 => Size1GiB::SIZE,
+                2 => Size2MiB::SIZE,
+                // huge pages are not allowed in the level 4 table
+                _ => return None,
+            };
+            // mask out the PAT bit, which is stored in bit 12 of huge page entries
+            let frame_start = entry.addr_with_mask(encryption_mask).align_down(page_size);
+            return Some(frame_start + (addr.as_u64() & (page_size - 1)));
+        }
+        frame_addr = entry.addr_with_mask(encryption_mask);
+    }
+
+    Some(frame_addr + u64::from(addr.page_offset()))
 }
 
 /// A trait for common page table operations on pages of size `S`.
+///
+/// ## Concurrency
+///
+/// The methods that change mappings require exclusive access to the mapper and assume that
+/// no other CPU modifies the page tables at the same time. In particular, `map_to` and
+/// [`unmap`](Mapper::unmap) perform non-atomic read-modify-write sequences on the entries and
+/// may create parent tables, so they must not race with each other, even for different
+/// pages. The flag updates that are documented as atomic, e.g.
+/// [`clear_accessed`](Mapper::clear_accessed), only race safely with the CPU itself.
+///
+/// Kernels that need concurrent updates, e.g. for populating lazily mapped pages from the
+/// page fault handlers of multiple CPUs, can use the atomic primitives of
+/// [`PageTableEntry`](super::page_table::PageTableEntry) such as
+/// [`compare_exchange`](super::page_table::PageTableEntry::compare_exchange) on the entries
+/// returned by their own page table walk.
 pub trait Mapper<S: PageSize> {
     /// Creates a new mapping in the page table.
     ///
@@ -120,7 +543,10 @@ pub trait Mapper<S: PageSize> {
     /// the `PRESENT` and `WRITABLE` flags might be set for parent tables,
     /// even if they are not set in `PageTableFlags`.
     ///
-    /// The `map_to_with_table_flags` method gives explicit control over the parent page table flags.
+    /// The [`map_to_with_table_flags`](Self::map_to_with_table_flags) method gives explicit
+    /// control over the parent page table flags. This method is equivalent to calling it with
+    /// `flags & (PRESENT | WRITABLE | USER_ACCESSIBLE)` as parent table flags, see its
+    /// documentation for how the flags of existing parent entries are updated.
     ///
     /// ## Safety
     ///
@@ -177,6 +603,10 @@ pub trait Mapper<S: PageSize> {
     ///           .flush();
     /// # }
     /// ```
+    ///
+    /// To map the page with a protection key, pass flags created through
+    /// [`PageTableFlags::with_protection_key`]. The key is only applied to the entry that
+    /// maps the page, not to the parent table entries.
     #[inline]
     unsafe fn map_to<A>(
         &mut self,
@@ -207,6 +637,19 @@ pub trait Mapper<S: PageSize> {
     ///
     /// Depending on the used mapper implementation, the `PRESENT` and `WRITABLE` flags might
     /// be set for parent tables, even if they are not specified in `parent_table_flags`.
+    /// Callers should always include `PRESENT`, since the CPU ignores the newly created tables
+    /// otherwise.
+    ///
+    /// Existing parent entries are never restricted: the `parent_table_flags` are OR-merged
+    /// into their flags, and no flag is ever removed. Since the CPU combines the permissions of
+    /// all levels, merging `USER_ACCESSIBLE` or `WRITABLE` into an existing parent entry widens
+    /// the permissions of all other pages below that entry to the permissions of their own
+    /// entries. Kernel-only subtrees that must stay inaccessible from userspace should
+    /// therefore not be mapped into with `USER_ACCESSIBLE` parent flags. Conversely,
+    /// `NO_EXECUTE` in the parent flags makes all pages below that entry non-executable, which
+    /// is why [`map_to`](Self::map_to) never passes it on. The flags of existing parent
+    /// entries can be changed explicitly through methods like
+    /// [`set_flags_p4_entry`](Self::set_flags_p4_entry).
     ///
     /// ## Safety
     ///
@@ -284,8 +727,33 @@ pub trait Mapper<S: PageSize> {
     /// Note that no page tables or pages are deallocated.
     fn unmap(&mut self, page: Page<S>) -> Result<(PhysFrame<S>, MapperFlush<S>), UnmapError>;
 
+    /// Replaces the frame and flags of an existing mapping and returns the frame that used
+    /// to be mapped.
+    ///
+    /// The page table entry is overwritten with a single write, so the page is never
+    /// observed as unmapped. For huge pages, the `HUGE_PAGE` flag is set automatically.
+    /// No page tables are allocated or deallocated.
+    ///
+    /// ## Safety
+    ///
+    /// This method is unsafe because the caller must guarantee that the new frame is not
+    /// used elsewhere and that no references into the old frame exist through this page.
+    /// The returned `MapperFlush` must be flushed (or the TLB flushed otherwise) before the
+    /// new mapping is guaranteed to be used by the CPU.
+    unsafe fn remap(
+        &mut self,
+        page: Page<S>,
+        new_frame: PhysFrame<S>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<S>, MapperFlush<S>), RemapError>;
+
     /// Updates the flags of an existing mapping.
     ///
+    /// The OS-available bits (`BIT_9` to `BIT_11` and `BIT_52` to `BIT_62`) that are set in the
+    /// entry are preserved, so that the OS can store its own data in them. Bits 59 to 62 are
+    /// replaced instead if `flags` contains a protection key. To clear OS-available bits, use
+    /// the accessors of the [`PageTableEntry`](super::page_table::PageTableEntry).
+    ///
     /// ## Safety
     ///
     /// This method is unsafe because changing the flags of a mapping
@@ -299,8 +767,30 @@ pub trait Mapper<S: PageSize> {
         flags: PageTableFlags,
     ) -> Result<MapperFlush<S>, FlagUpdateError>;
 
+    /// Atomically clears the given flags of an existing mapping and returns the flags
+    /// that were set before.
+    ///
+    /// The update is performed atomically, so flags that are set concurrently by the CPU
+    /// (i.e. `ACCESSED` and `DIRTY`) are not lost.
+    ///
+    /// ## Safety
+    ///
+    /// This method is unsafe because clearing flags of a mapping might result in undefined
+    /// behavior. For example, clearing the `HUGE_PAGE` flag of a huge page mapping makes
+    /// the CPU interpret the mapped frame as a page table.
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<S>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<S>), FlagUpdateError>;
+
     /// Set the flags of an existing page level 4 table entry
     ///
+    /// This changes the flags for all pages mapped through this entry. Returns
+    /// `FlagUpdateError::PageNotMapped` if the entry or one of its parent entries is unused, and
+    /// `FlagUpdateError::ParentEntryHugePage` if the entry or one of its parent entries maps a
+    /// huge page instead of a page table.
+    ///
     /// ## Safety
     ///
     /// This method is unsafe because changing the flags of a mapping
@@ -316,6 +806,11 @@ pub trait Mapper<S: PageSize> {
 
     /// Set the flags of an existing page table level 3 entry
     ///
+    /// This changes the flags for all pages mapped through this entry. Returns
+    /// `FlagUpdateError::PageNotMapped` if the entry or one of its parent entries is unused, and
+    /// `FlagUpdateError::ParentEntryHugePage` if the entry or one of its parent entries maps a
+    /// huge page instead of a page table.
+    ///
     /// ## Safety
     ///
     /// This method is unsafe because changing the flags of a mapping
@@ -331,6 +826,11 @@ pub trait Mapper<S: PageSize> {
 
     /// Set the flags of an existing page table level 2 entry
     ///
+    /// This changes the flags for all pages mapped through this entry. Returns
+    /// `FlagUpdateError::PageNotMapped` if the entry or one of its parent entries is unused, and
+    /// `FlagUpdateError::ParentEntryHugePage` if the entry or one of its parent entries maps a
+    /// huge page instead of a page table.
+    ///
     /// ## Safety
     ///
     /// This method is unsafe because changing the flags of a mapping
@@ -350,6 +850,101 @@ pub trait Mapper<S: PageSize> {
     /// error otherwise.
     fn translate_page(&self, page: Page<S>) -> Result<PhysFrame<S>, TranslateError>;
 
+    /// Return the flags of the lowest-level page table entry that maps the specified page.
+    ///
+    /// This function assumes that the page is mapped to a frame of size `S` and returns an
+    /// error otherwise.
+    fn page_flags(&self, page: Page<S>) -> Result<PageTableFlags, TranslateError>;
+
+    /// Returns whether the CPU has accessed the specified page since the `ACCESSED` flag was
+    /// last cleared.
+    #[inline]
+    fn is_accessed(&self, page: Page<S>) -> Result<bool, TranslateError> {
+        self.page_flags(page)
+            .map(|flags| flags.contains(PageTableFlags::ACCESSED))
+    }
+
+    /// Returns whether the CPU has written to the specified page since the `DIRTY` flag was
+    /// last cleared.
+    #[inline]
+    fn is_dirty(&self, page: Page<S>) -> Result<bool, TranslateError> {
+        self.page_flags(page)
+            .map(|flags| flags.contains(PageTableFlags::DIRTY))
+    }
+
+    /// Atomically clears the `ACCESSED` flag of an existing mapping and returns whether it
+    /// was set before.
+    ///
+    /// The CPU only sets the flag again after the page was flushed from the TLB, so the
+    /// returned `MapperFlush` should be flushed before the flag is queried again.
+    #[inline]
+    fn clear_accessed(&mut self, page: Page<S>) -> Result<(bool, MapperFlush<S>), FlagUpdateError> {
+        // SAFETY: clearing the `ACCESSED` flag has no effect on memory safety.
+        let (flags, flush) = unsafe { self.clear_flags(page, PageTableFlags::ACCESSED)? };
+        Ok((flags.contains(PageTableFlags::ACCESSED), flush))
+    }
+
+    /// Atomically clears the `DIRTY` flag of an existing mapping and returns whether it
+    /// was set before.
+    ///
+    /// The CPU only sets the flag again after the page was flushed from the TLB, so the
+    /// returned `MapperFlush` should be flushed before the flag is queried again.
+    #[inline]
+    fn clear_dirty(&mut self, page: Page<S>) -> Result<(bool, MapperFlush<S>), FlagUpdateError> {
+        // SAFETY: clearing the `DIRTY` flag has no effect on memory safety.
+        let (flags, flush) = unsafe { self.clear_flags(page, PageTableFlags::DIRTY)? };
+        Ok((flags.contains(PageTableFlags::DIRTY), flush))
+    }
+
+    /// Atomically clears the `ACCESSED` flag of all mapped pages in the given range and
+    /// records which of them had it set.
+    ///
+    /// This is the range variant of [`Mapper::clear_accessed`], e.g. for the clock algorithm
+    /// of a page replacement policy. Bit `i % 64` of `bitmap[i / 64]` is set if the `i`th page
+    /// of the range was accessed and cleared otherwise. Pages that aren't mapped are reported
+    /// as not accessed.
+    ///
+    /// The CPU doesn't set the flag again for translations that are still cached in the TLB,
+    /// so the returned flush promise should be flushed before the flags are harvested again.
+    /// If a page of the range is part of a larger huge page, the returned error contains
+    /// that page and a flush promise for the pages before it, whose flags were already
+    /// cleared.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the bitmap has less than one bit per page of the range.
+    fn clear_accessed_range(
+        &mut self,
+        pages: PageRange<S>,
+        bitmap: &mut [u64],
+    ) -> Result<MapperFlushRange<S>, FlagUpdateRangeError<S>>
+    where
+        Self: Sized,
+    {
+        clear_flag_range(self, pages, PageTableFlags::ACCESSED, bitmap)
+    }
+
+    /// Atomically clears the `DIRTY` flag of all mapped pages in the given range and records
+    /// which of them had it set.
+    ///
+    /// This is the range variant of [`Mapper::clear_dirty`]. See
+    /// [`Mapper::clear_accessed_range`] for the layout of the bitmap, the TLB caveat and the
+    /// errors.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the bitmap has less than one bit per page of the range.
+    fn clear_dirty_range(
+        &mut self,
+        pages: PageRange<S>,
+        bitmap: &mut [u64],
+    ) -> Result<MapperFlushRange<S>, FlagUpdateRangeError<S>>
+    where
+        Self: Sized,
+    {
+        clear_flag_range(self, pages, PageTableFlags::DIRTY, bitmap)
+    }
+
     /// Maps the given frame to the virtual page with the same address.
     ///
     /// ## Safety
@@ -372,6 +967,311 @@ pub trait Mapper<S: PageSize> {
         let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64()));
         self.map_to(page, frame, flags, frame_allocator)
     }
+
+    /// Maps the given range of pages to consecutive frames starting at `start_frame`.
+    ///
+    /// Instead of a [`MapperFlush`] for every page, a single [`MapperFlushAll`] is returned,
+    /// so the TLB can be flushed once for the whole range. For small ranges calling
+    /// [`Mapper::map_to`] for each page and flushing the pages individually might be cheaper.
+    ///
+    /// If an error occurs, the pages before the failing page stay mapped. The error is returned
+    /// without a flush promise, so the caller is responsible for flushing the TLB in that case.
+    ///
+    /// ## Safety
+    ///
+    /// This is a convencience function that invokes [`Mapper::map_to`] internally, so
+    /// all safety requirements of it also apply for this function.
+    #[inline]
+    unsafe fn map_to_range<A>(
+        &mut self,
+        pages: PageRange<S>,
+        start_frame: PhysFrame<S>,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<MapperFlushAll, MapToError<S>>
+    where
+        Self: Sized,
+        A: FrameAllocator<Size4KiB> + ?Sized,
+    {
+        for (i, page) in pages.enumerate() {
+            let frame = start_frame + i as u64;
+            self.map_to(page, frame, flags, frame_allocator)?.ignore();
+        }
+        Ok(MapperFlushAll::new())
+    }
+
+    /// Maps the given range of pages to the given range of frames.
+    ///
+    /// Either the complete range is mapped or none of it: if mapping a page fails, all pages
+    /// that were mapped by this call are unmapped again. In that case, the error is returned
+    /// together with the range of rolled back pages, which ends at the page that failed to be
+    /// mapped. The rolled back pages might still be cached in the TLB, so they should be
+    /// flushed before the range is used again. Page tables created by this call are not freed.
+    ///
+    /// Returns [`MapRangeError::LengthMismatch`] without changing any mapping if the page and
+    /// frame ranges have a different length. Mapping an empty range does nothing.
+    ///
+    /// ## Safety
+    ///
+    /// This is a convencience function that invokes [`Mapper::map_to`] internally, so
+    /// all safety requirements of it also apply for this function.
+    unsafe fn map_range<A>(
+        &mut self,
+        pages: PageRange<S>,
+        frames: PhysFrameRange<S>,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<MapperFlushRange<S>, (MapRangeError<S>, PageRange<S>)>
+    where
+        Self: Sized,
+        A: FrameAllocator<Size4KiB> + ?Sized,
+    {
+        if pages.len_u64() != frames.len_u64() {
+            let untouched = PageRange {
+                start: pages.start,
+                end: pages.start,
+            };
+            return Err((MapRangeError::LengthMismatch, untouched));
+        }
+
+        for (page, frame) in pages.zip(frames) {
+            match self.map_to(page, frame, flags, frame_allocator) {
+                Ok(flush) => flush.ignore(),
+                Err(err) => {
+                    let mapped = PageRange {
+                        start: pages.start,
+                        end: page,
+                    };
+                    for page in mapped {
+                        // the page was mapped above, so unmapping it can't fail
+                        if let Ok((_, flush)) = self.unmap(page) {
+                            flush.ignore();
+                        }
+                    }
+                    return Err((MapRangeError::MapTo(err), mapped));
+                }
+            }
+        }
+        Ok(MapperFlushRange::new(pages))
+    }
+
+    /// Maps the given range of frames to the virtual pages with the same addresses.
+    ///
+    /// This has the same all-or-nothing semantics as [`Mapper::map_range`]. On error, the
+    /// range of rolled back frames is returned. Returns [`MapRangeError::NonCanonical`]
+    /// without changing any mapping if the identity-mapped virtual addresses, including the
+    /// exclusive end of the range, would not be canonical.
+    ///
+    /// ## Safety
+    ///
+    /// This is a convencience function that invokes [`Mapper::map_to`] internally, so
+    /// all safety requirements of it also apply for this function.
+    unsafe fn identity_map_range<A>(
+        &mut self,
+        frames: PhysFrameRange<S>,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<MapperFlushRange<S>, (MapRangeError<S>, PhysFrameRange<S>)>
+    where
+        Self: Sized,
+        A: FrameAllocator<Size4KiB> + ?Sized,
+    {
+        fn page<S: PageSize>(frame: PhysFrame<S>) -> Page<S> {
+            Page::containing_address(VirtAddr::new(frame.start_address().as_u64()))
+        }
+
+        if frames.end.start_address().as_u64() >= 0x8000_0000_0000 {
+            let untouched = PhysFrame::range(frames.start, frames.start);
+            return Err((MapRangeError::NonCanonical, untouched));
+        }
+
+        let pages = Page::range(page(frames.start), page(frames.end));
+        self.map_range(pages, frames, flags, frame_allocator)
+            .map_err(|(err, rolled_back)| {
+                let rolled_back =
+                    PhysFrame::range(frames.start, frames.start + rolled_back.len_u64());
+                (err, rolled_back)
+            })
+    }
+
+    /// Identity maps all frames that contain a byte of the `len` bytes starting at `addr`.
+    ///
+    /// The start address is rounded down and the end address is rounded up to a frame
+    /// boundary of size `S`, so the mapped range might be larger than requested. Nothing is
+    /// mapped if `len` is 0. See [`Mapper::identity_map_range`] for the semantics and
+    /// errors; an `addr + len` that overflows is reported as [`MapRangeError::NonCanonical`].
+    ///
+    /// ## Safety
+    ///
+    /// This is a convencience function that invokes [`Mapper::map_to`] internally, so
+    /// all safety requirements of it also apply for this function.
+    unsafe fn identity_map_region<A>(
+        &mut self,
+        addr: PhysAddr,
+        len: u64,
+        flags: PageTableFlags,
+        frame_allocator: &mut A,
+    ) -> Result<MapperFlushRange<S>, (MapRangeError<S>, PhysFrameRange<S>)>
+    where
+        Self: Sized,
+        A: FrameAllocator<Size4KiB> + ?Sized,
+    {
+        let start = PhysFrame::containing_address(addr);
+        let end = match addr.as_u64().checked_add(len) {
+            Some(_) if len == 0 => start,
+            Some(end) if end < 0x8000_0000_0000 => {
+                PhysFrame::containing_address(PhysAddr::new(end - 1)) + 1
+            }
+            _ => return Err((MapRangeError::NonCanonical, PhysFrame::range(start, start))),
+        };
+        self.identity_map_range(PhysFrame::range(start, end), flags, frame_allocator)
+    }
+
+    /// Removes the mappings of all pages in the given range.
+    ///
+    /// Unmapping stops at the first page that can't be unmapped. The returned error then
+    /// contains the failing page and a flush promise for the pages that were already unmapped.
+    /// Unmapping an empty range does nothing.
+    ///
+    /// Note that no page tables or pages are deallocated.
+    fn unmap_range(
+        &mut self,
+        pages: PageRange<S>,
+    ) -> Result<MapperFlushRange<S>, UnmapRangeError<S>>
+    where
+        Self: Sized,
+    {
+        for page in pages {
+            match self.unmap(page) {
+                Ok((_, flush)) => flush.ignore(),
+                Err(error) => {
+                    let unmapped = PageRange {
+                        start: pages.start,
+                        end: page,
+                    };
+                    return Err(UnmapRangeError {
+                        page,
+                        error,
+                        flush: MapperFlushRange::new(unmapped),
+                    });
+                }
+            }
+        }
+        Ok(MapperFlushRange::new(pages))
+    }
+
+    /// Removes a mapping from the page table and returns the frame that used to be mapped to
+    /// the given deallocator.
+    ///
+    /// If unmapping fails, nothing is deallocated. Note that no page tables are deallocated.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must guarantee that the frame is no longer used once the page is unmapped.
+    /// In particular, the frame must not be mapped by any other page, neither in this page
+    /// table nor in another one, and no references into the frame may remain. Since the frame
+    /// can be handed out again by the deallocator, the returned `MapperFlush` must be flushed
+    /// before the frame is reused.
+    unsafe fn unmap_and_deallocate<D>(
+        &mut self,
+        page: Page<S>,
+        frame_deallocator: &mut D,
+    ) -> Result<MapperFlush<S>, UnmapError>
+    where
+        Self: Sized,
+        D: FrameDeallocator<S> + ?Sized,
+    {
+        let (frame, flush) = self.unmap(page)?;
+        frame_deallocator.deallocate_frame(frame);
+        Ok(flush)
+    }
+
+    /// Removes the mappings of all pages in the given range, returns the mapped frames to the
+    /// given deallocator, and frees the page tables that became empty.
+    ///
+    /// This combines [`Mapper::unmap_range`] with [`CleanUp::clean_up_addr_range`], so that a
+    /// whole region can be torn down in one call. If a page can't be unmapped, the frames of
+    /// the pages that were unmapped before are still deallocated, but no page tables are freed.
+    ///
+    /// ## Safety
+    ///
+    /// The caller must guarantee that the frames of all pages in the range are no longer used
+    /// once the pages are unmapped, see [`Mapper::unmap_and_deallocate`]. In addition, the
+    /// safety requirements of [`CleanUp::clean_up_addr_range`] apply. The returned flush
+    /// promise must be flushed before any of the deallocated frames are reused.
+    unsafe fn unmap_range_and_deallocate<D>(
+        &mut self,
+        pages: PageRange<S>,
+        frame_deallocator: &mut D,
+    ) -> Result<MapperFlushRange<S>, UnmapRangeError<S>>
+    where
+        Self: Sized + CleanUp,
+        D: FrameDeallocator<S> + FrameDeallocator<Size4KiB>,
+    {
+        for page in pages {
+            match self.unmap(page) {
+                Ok((frame, flush)) => {
+                    flush.ignore();
+                    FrameDeallocator::<S>::deallocate_frame(frame_deallocator, frame);
+                }
+                Err(error) => {
+                    let unmapped = PageRange {
+                        start: pages.start,
+                        end: page,
+                    };
+                    return Err(UnmapRangeError {
+                        page,
+                        error,
+                        flush: MapperFlushRange::new(unmapped),
+                    });
+                }
+            }
+        }
+        if !pages.is_empty() {
+            let start = Page::containing_address(pages.start.start_address());
+            let end = Page::containing_address(pages.end.start_address() - 1u64);
+            self.clean_up_addr_range(Page::range_inclusive(start, end), frame_deallocator);
+        }
+        Ok(MapperFlushRange::new(pages))
+    }
+}
+
+/// Provides methods for freeing page table frames that are no longer used.
+pub trait CleanUp {
+    /// Removes all empty P1-P3 tables and returns their frames to the given deallocator.
+    ///
+    /// A table is empty if all of its entries are unused. The level 4 table is never freed.
+    /// Tables that map huge pages are not empty, so they are kept.
+    ///
+    /// ## Safety
+    ///
+    /// The caller has to guarantee that it's safe to free page table frames: all page table
+    /// frames must only be used once and only in this page table (e.g. no reference counted
+    /// page tables or reusing the same page tables for different virtual address ranges in
+    /// the same page table).
+    unsafe fn clean_up<D>(&mut self, frame_deallocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>;
+
+    /// Removes all empty P1-P3 tables in a certain range and returns their frames to the
+    /// given deallocator.
+    ///
+    /// Only entries for pages within the given range are visited. A table that is only
+    /// partially covered by the range is still freed if it is empty afterwards. The level 4
+    /// table is never freed.
+    ///
+    /// ## Safety
+    ///
+    /// The caller has to guarantee that it's safe to free page table frames: all page table
+    /// frames must only be used once and only in this page table (e.g. no reference counted
+    /// page tables or reusing the same page tables for different virtual address ranges in
+    /// the same page table).
+    unsafe fn clean_up_addr_range<D>(
+        &mut self,
+        range: PageRangeInclusive,
+        frame_deallocator: &mut D,
+    ) where
+        D: FrameDeallocator<Size4KiB>;
 }
 
 /// This type represents a page whose mapping has changed in the page table.
@@ -398,6 +1298,10 @@ impl<S: PageSize> MapperFlush<S> {
     }
 
     /// Don't flush the TLB and silence the “must be used” warning.
+    ///
+    /// Use this if the TLB is flushed in some other way later, e.g. through a single
+    /// [`MapperFlushAll`] or a reload of CR3 after a batch of changes. Until then, the CPU
+    /// might still use the old mapping of the page.
     #[inline]
     pub fn ignore(self) {}
 }
@@ -430,6 +1334,189 @@ impl MapperFlushAll {
     pub fn ignore(self) {}
 }
 
+/// This type represents a range of pages whose mappings have changed in the page table.
+///
+/// Like [`MapperFlush`], this ensures that the TLB flush is not forgotten. It allows
+/// flushing the complete range at once, which is returned from [`Mapper::map_range`] and
+/// [`Mapper::unmap_range`].
+#[derive(Debug)]
+#[must_use = "Page Table changes must be flushed or ignored."]
+pub struct MapperFlushRange<S: PageSize>(PageRange<S>);
+
+impl<S: PageSize> MapperFlushRange<S> {
+    /// The number of pages above which the complete TLB is flushed instead of flushing the
+    /// pages individually.
+    #[cfg(feature = "instructions")]
+    const FLUSH_ALL_THRESHOLD: u64 = 32;
+
+    /// Create a new flush promise
+    #[inline]
+    fn new(pages: PageRange<S>) -> Self {
+        MapperFlushRange(pages)
+    }
+
+    /// Returns the range of pages that need to be flushed.
+    #[inline]
+    pub fn pages(&self) -> PageRange<S> {
+        self.0
+    }
+
+    /// Flush the pages from the TLB to ensure that the newest mappings are used.
+    ///
+    /// For large ranges, the complete TLB is flushed by reloading CR3 instead of flushing
+    /// every page individually.
+    #[cfg(feature = "instructions")]
+    #[inline]
+    pub fn flush(self) {
+        if self.0.len_u64() > Self::FLUSH_ALL_THRESHOLD {
+            crate::instructions::tlb::flush_all();
+        } else {
+            for page in self.0 {
+                crate::instructions::tlb::flush(page.start_address());
+            }
+        }
+    }
+
+    /// Don't flush the TLB and silence the “must be used” warning.
+    #[inline]
+    pub fn ignore(self) {}
+}
+
+/// Collects flush promises of individual pages so that the TLB can be flushed once at the
+/// end of a batch of page table changes.
+///
+/// Up to a threshold, the addresses of the consumed pages are recorded and flushed
+/// individually through `invlpg`. If more pages are consumed, the batch escalates to a
+/// complete TLB flush by reloading CR3. The default threshold is [`FlushBatch::CAPACITY`],
+/// a lower one can be set through [`FlushBatch::with_threshold`].
+///
+/// ```
+/// # use x86_64::structures::paging::mapper::{FlushBatch, Mapper, UnmapError};
+/// # use x86_64::structures::paging::Page;
+/// fn unmap_all(
+///     mapper: &mut impl Mapper<x86_64::structures::paging::Size4KiB>,
+///     pages: &[Page],
+/// ) -> Result<FlushBatch, UnmapError> {
+///     let mut batch = FlushBatch::new();
+///     for &page in pages {
+///         let (_frame, flush) = mapper.unmap(page)?;
+///         batch.consume(flush);
+///     }
+///     // call `batch.flush()` to flush the TLB once for all pages
+///     Ok(batch)
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use = "Page Table changes must be flushed or ignored."]
+pub struct FlushBatch {
+    pages: [VirtAddr; FlushBatch::CAPACITY],
+    len: usize,
+    threshold: usize,
+    flush_all: bool,
+}
+
+impl FlushBatch {
+    /// The maximum number of pages that are recorded for individual flushing.
+    pub const CAPACITY: usize = 32;
+
+    /// Creates an empty batch that escalates to a complete TLB flush after
+    /// [`FlushBatch::CAPACITY`] pages.
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_threshold(Self::CAPACITY)
+    }
+
+    /// Creates an empty batch that escalates to a complete TLB flush if more than
+    /// `threshold` pages are consumed.
+    ///
+    /// Thresholds larger than [`FlushBatch::CAPACITY`] are capped at the capacity. A threshold
+    /// of 0 always results in a complete TLB flush, unless the batch stays empty.
+    #[inline]
+    pub fn with_threshold(threshold: usize) -> Self {
+        FlushBatch {
+            pages: [VirtAddr::zero(); Self::CAPACITY],
+            len: 0,
+            threshold: threshold.min(Self::CAPACITY),
+            flush_all: false,
+        }
+    }
+
+    /// Records the page of the given flush promise.
+    #[inline]
+    pub fn consume<S: PageSize>(&mut self, flush: MapperFlush<S>) {
+        self.push(flush.0.start_address());
+    }
+
+    /// Records all pages of the given flush promise.
+    #[inline]
+    pub fn consume_range<S: PageSize>(&mut self, flush: MapperFlushRange<S>) {
+        if flush.0.len_u64() > (self.threshold - self.len) as u64 {
+            self.flush_all = true;
+        } else {
+            for page in flush.0 {
+                self.push(page.start_address());
+            }
+        }
+    }
+
+    /// Escalates the batch to a complete TLB flush.
+    #[inline]
+    pub fn consume_all(&mut self, flush: MapperFlushAll) {
+        flush.ignore();
+        self.flush_all = true;
+    }
+
+    /// Returns the addresses of the recorded pages, or `None` if the batch escalated to a
+    /// complete TLB flush.
+    #[inline]
+    pub fn pages(&self) -> Option<&[VirtAddr]> {
+        if self.flush_all {
+            None
+        } else {
+            Some(&self.pages[..self.len])
+        }
+    }
+
+    /// Flush the recorded pages from the TLB, or the complete TLB if the threshold was
+    /// exceeded.
+    #[cfg(feature = "instructions")]
+    #[inline]
+    pub fn flush(self) {
+        match self.pages() {
+            Some(pages) => {
+                for &addr in pages {
+                    crate::instructions::tlb::flush(addr);
+                }
+            }
+            None => crate::instructions::tlb::flush_all(),
+        }
+    }
+
+    /// Don't flush the TLB and silence the “must be used” warning.
+    #[inline]
+    pub fn ignore(self) {}
+
+    #[inline]
+    fn push(&mut self, addr: VirtAddr) {
+        if self.flush_all {
+            return;
+        }
+        if self.len < self.threshold {
+            self.pages[self.len] = addr;
+            self.len += 1;
+        } else {
+            self.flush_all = true;
+        }
+    }
+}
+
+impl Default for FlushBatch {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// This error is returned from `map_to` and similar methods.
 #[derive(Debug)]
 pub enum MapToError<S: PageSize> {
@@ -443,6 +1530,53 @@ pub enum MapToError<S: PageSize> {
     PageAlreadyMapped(PhysFrame<S>),
 }
 
+/// This error is returned from [`Mapper::map_range`] and [`Mapper::identity_map_range`].
+#[derive(Debug)]
+pub enum MapRangeError<S: PageSize> {
+    /// The page range and the frame range have a different length.
+    LengthMismatch,
+    /// The virtual addresses of an identity mapping would not be canonical, i.e. the frame
+    /// range reaches `0x8000_0000_0000` or above.
+    NonCanonical,
+    /// Mapping one of the pages failed, see [`MapToError`].
+    MapTo(MapToError<S>),
+}
+
+/// This error is returned from [`MapperAllSizes::map_range_auto`].
+#[derive(Debug)]
+pub enum MapRangeAutoError {
+    /// The virtual address, the physical address, or the length is not 4KiB aligned.
+    NotAligned,
+    /// The virtual range is not canonical or the physical range exceeds 52 bits.
+    InvalidAddress,
+    /// An additional frame was needed for the mapping process, but the frame allocator
+    /// returned `None`.
+    FrameAllocationFailed,
+    /// An upper level page table entry has the `HUGE_PAGE` flag set, which means that a
+    /// page of the range is part of an already mapped huge page.
+    ParentEntryHugePage,
+    /// A page of the range is already mapped to the given physical frame.
+    PageAlreadyMapped(AnyFrame),
+}
+
+impl<S: PageSize> From<MapToError<S>> for MapRangeAutoError {
+    #[inline]
+    fn from(err: MapToError<S>) -> Self {
+        match err {
+            MapToError::FrameAllocationFailed => MapRangeAutoError::FrameAllocationFailed,
+            MapToError::ParentEntryHugePage => MapRangeAutoError::ParentEntryHugePage,
+            MapToError::PageAlreadyMapped(frame) => {
+                // `S::SIZE` is always a valid page size
+                let size = PageSizeKind::from_size(S::SIZE).unwrap();
+                MapRangeAutoError::PageAlreadyMapped(AnyFrame::containing_address(
+                    frame.start_address(),
+                    size,
+                ))
+            }
+        }
+    }
+}
+
 /// An error indicating that an `unmap` call failed.
 #[derive(Debug)]
 pub enum UnmapError {
@@ -455,6 +1589,40 @@ pub enum UnmapError {
     InvalidFrameAddress(PhysAddr),
 }
 
+/// An error indicating that a `remap` call failed.
+#[derive(Debug)]
+pub enum RemapError {
+    /// An upper level page table entry has the `HUGE_PAGE` flag set, which means that the
+    /// given page is part of a huge page and can't be remapped individually.
+    ParentEntryHugePage,
+    /// The given page is not mapped to a physical frame.
+    PageNotMapped,
+    /// The page table entry for the given page points to an invalid physical address.
+    InvalidFrameAddress(PhysAddr),
+}
+
+/// An error indicating that an `unmap_range` call failed.
+#[derive(Debug)]
+pub struct UnmapRangeError<S: PageSize> {
+    /// The page that couldn't be unmapped.
+    pub page: Page<S>,
+    /// The reason why the page couldn't be unmapped.
+    pub error: UnmapError,
+    /// The pages that were unmapped before the error occurred.
+    pub flush: MapperFlushRange<S>,
+}
+
+/// An error indicating that a `clear_accessed_range` or `clear_dirty_range` call failed.
+#[derive(Debug)]
+pub struct FlagUpdateRangeError<S: PageSize> {
+    /// The page whose flags couldn't be updated.
+    pub page: Page<S>,
+    /// The reason why the flags couldn't be updated.
+    pub error: FlagUpdateError,
+    /// The pages whose flags were updated before the error occurred.
+    pub flush: MapperFlushRange<S>,
+}
+
 /// An error indicating that an `update_flags` call failed.
 #[derive(Debug)]
 pub enum FlagUpdateError {
@@ -477,4 +1645,250 @@ pub enum TranslateError {
     InvalidFrameAddress(PhysAddr),
 }
 
+/// An error of a page table walk that includes the failing page table level.
+///
+/// It is returned by [`Translate::translate_detailed`] and
+/// [`Translate::translate_page_detailed`]. In contrast to [`TranslateError`], it contains the
+/// level and the raw flags of the page table entry that caused the error. This allows e.g. a
+/// page fault handler to tell apart a mapping that was not created yet from corrupted page
+/// tables without walking the page tables again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslateErrorDetailed {
+    /// The page table entry at the given level doesn't map anything.
+    ///
+    /// For level 1, this means that the entry is unused. For all other levels, it means that
+    /// the `PRESENT` flag is not set. The `flags` can be used to check for markers that were
+    /// put into non-present entries.
+    NotMapped {
+        /// The level of the page table that contains the entry.
+        level: PageTableLevel,
+        /// The flags of the entry.
+        flags: PageTableFlags,
+    },
+    /// The page table entry at the given level has the `HUGE_PAGE` flag set, but huge pages
+    /// are not supported at that level, e.g. in level 4 tables.
+    InvalidHugePage {
+        /// The level of the page table that contains the entry.
+        level: PageTableLevel,
+        /// The flags of the entry.
+        flags: PageTableFlags,
+    },
+    /// The page table entry at the given level points to an invalid physical address.
+    InvalidFrameAddress {
+        /// The level of the page table that contains the entry.
+        level: PageTableLevel,
+        /// The flags of the entry.
+        flags: PageTableFlags,
+        /// The invalid address.
+        addr: PhysAddr,
+    },
+    /// The given page is part of a huge page of `size` that is mapped by the entry at the
+    /// given level.
+    ///
+    /// Only returned by [`Translate::translate_page_detailed`].
+    ParentEntryHugePage {
+        /// The level of the page table that contains the entry.
+        level: PageTableLevel,
+        /// The flags of the entry.
+        flags: PageTableFlags,
+        /// The size of the huge page.
+        size: PageSizeKind,
+    },
+    /// The given page is not mapped as a whole, but its start address is mapped by a smaller
+    /// page of `size` by the entry at the given level.
+    ///
+    /// Only returned by [`Translate::translate_page_detailed`].
+    NotHugePage {
+        /// The level of the page table that contains the entry.
+        level: PageTableLevel,
+        /// The flags of the entry.
+        flags: PageTableFlags,
+        /// The size of the smaller page.
+        size: PageSizeKind,
+    },
+}
+
+impl TranslateErrorDetailed {
+    /// Returns the level of the page table that contains the entry that caused the error.
+    #[inline]
+    pub const fn level(&self) -> PageTableLevel {
+        match *self {
+            TranslateErrorDetailed::NotMapped { level, .. }
+            | TranslateErrorDetailed::InvalidHugePage { level, .. }
+            | TranslateErrorDetailed::InvalidFrameAddress { level, .. }
+            | TranslateErrorDetailed::ParentEntryHugePage { level, .. }
+            | TranslateErrorDetailed::NotHugePage { level, .. } => level,
+        }
+    }
+
+    /// Returns the flags of the page table entry that caused the error.
+    #[inline]
+    pub const fn flags(&self) -> PageTableFlags {
+        match *self {
+            TranslateErrorDetailed::NotMapped { flags, .. }
+            | TranslateErrorDetailed::InvalidHugePage { flags, .. }
+            | TranslateErrorDetailed::InvalidFrameAddress { flags, .. }
+            | TranslateErrorDetailed::ParentEntryHugePage { flags, .. }
+            | TranslateErrorDetailed::NotHugePage { flags, .. } => flags,
+        }
+    }
+}
+
+impl From<TranslateErrorDetailed> for TranslateError {
+    #[inline]
+    fn from(err: TranslateErrorDetailed) -> Self {
+        match err {
+            TranslateErrorDetailed::NotMapped { .. }
+            | TranslateErrorDetailed::NotHugePage { .. } => TranslateError::PageNotMapped,
+            TranslateErrorDetailed::InvalidHugePage { .. }
+            | TranslateErrorDetailed::ParentEntryHugePage { .. } => {
+                TranslateError::ParentEntryHugePage
+            }
+            TranslateErrorDetailed::InvalidFrameAddress { addr, .. } => {
+                TranslateError::InvalidFrameAddress(addr)
+            }
+        }
+    }
+}
+
 static _ASSERT_OBJECT_SAFE: Option<&(dyn Translate + Sync)> = None;
+
+/// Shared implementation of `Mapper::clear_accessed_range` and `Mapper::clear_dirty_range`.
+fn clear_flag_range<S, M>(
+    mapper: &mut M,
+    pages: PageRange<S>,
+    flag: PageTableFlags,
+    bitmap: &mut [u64],
+) -> Result<MapperFlushRange<S>, FlagUpdateRangeError<S>>
+where
+    S: PageSize,
+    M: Mapper<S>,
+{
+    let len = pages.len_u64();
+    assert!(
+        bitmap.len() as u64 >= len.saturating_add(63) / 64,
+        "bitmap is too small for the page range"
+    );
+
+    for (i, page) in pages.enumerate() {
+        // SAFETY: clearing the `ACCESSED` or `DIRTY` flag has no effect on memory safety.
+        let was_set = match unsafe { mapper.clear_flags(page, flag) } {
+            Ok((flags, flush)) => {
+                flush.ignore();
+                flags.contains(flag)
+            }
+            Err(FlagUpdateError::PageNotMapped) => false,
+            Err(error) => {
+                return Err(FlagUpdateRangeError {
+                    page,
+                    error,
+                    flush: MapperFlushRange::new(PageRange {
+                        start: pages.start,
+                        end: page,
+                    }),
+                });
+            }
+        };
+        let (word, bit) = (i / 64, i % 64);
+        if was_set {
+            bitmap[word] |= 1 << bit;
+        } else {
+            bitmap[word] &= !(1 << bit);
+        }
+    }
+    Ok(MapperFlushRange::new(pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_flush_batch() {
+        let page = |n: u64| Page::<Size4KiB>::containing_address(VirtAddr::new(n * 4096));
+
+        let mut batch = FlushBatch::with_threshold(3);
+        batch.consume(MapperFlush::new(page(1)));
+        batch.consume_range(MapperFlushRange::new(Page::range(page(5), page(7))));
+        let expected = [page(1), page(5), page(6)];
+        let recorded = batch.pages().unwrap();
+        assert!(recorded
+            .iter()
+            .copied()
+            .eq(expected.iter().map(|p| p.start_address())));
+        batch.consume(MapperFlush::new(page(8)));
+        assert_eq!(batch.pages(), None);
+        batch.ignore();
+
+        let mut batch = FlushBatch::new();
+        batch.consume_range(MapperFlushRange::new(Page::range(page(0), page(33))));
+        assert_eq!(batch.pages(), None);
+        batch.ignore();
+    }
+
+    #[cfg(feature = "instructions")]
+    #[test]
+    pub fn test_walk_page_tables() {
+        use crate::structures::paging::PageTable;
+
+        // the table with index `n` is accessed as physical frame `n` through the offset
+        let mut tables = vec![PageTable::new(); 4];
+        let phys_offset = VirtAddr::from_ptr(tables.as_ptr());
+        let c_bit = 1 << 47;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let addr = VirtAddr::new(0x1234_5678);
+        tables[0][addr.p4_index()].set_addr(PhysAddr::new(0x1000 | c_bit), flags);
+        tables[1][addr.p3_index()].set_addr(PhysAddr::new(0x2000 | c_bit), flags);
+        tables[2][addr.p2_index()].set_addr(PhysAddr::new(0x3000 | c_bit), flags);
+        tables[3][addr.p1_index()].set_addr(PhysAddr::new(0x5_0000 | c_bit), flags);
+
+        let translate =
+            |addr| unsafe { walk_page_tables(addr, PhysAddr::new(0), phys_offset, c_bit) };
+        assert_eq!(translate(addr), Some(PhysAddr::new(0x5_0678)));
+        assert_eq!(translate(addr + 0x1000u64), None);
+
+        // 2MiB page
+        tables[2][addr.p2_index()].set_addr(
+            PhysAddr::new(0x4000_0000 | c_bit),
+            flags | PageTableFlags::HUGE_PAGE,
+        );
+        assert_eq!(translate(addr), Some(PhysAddr::new(0x4014_5678)));
+    }
+
+    #[test]
+    pub fn test_default_translate_detailed() {
+        /// Maps every address to the frame 0x1000, except for the first page.
+        struct FixedTranslation;
+
+        impl Translate for FixedTranslation {
+            fn translate(&self, addr: VirtAddr) -> TranslateResult {
+                if addr.as_u64() < 0x1000 {
+                    return TranslateResult::NotMapped;
+                }
+                TranslateResult::Mapped {
+                    frame: MappedFrame::Size4KiB(PhysFrame::containing_address(PhysAddr::new(
+                        0x1000,
+                    ))),
+                    offset: u64::from(addr.page_offset()),
+                    flags: PageTableFlags::PRESENT,
+                }
+            }
+        }
+
+        assert_eq!(
+            FixedTranslation.translate_detailed(VirtAddr::new(0x5123)),
+            Ok(Translation {
+                frame: MappedFrame::Size4KiB(PhysFrame::containing_address(PhysAddr::new(0x1000))),
+                offset: 0x123,
+                flags: PageTableFlags::PRESENT,
+            })
+        );
+        assert_eq!(
+            FixedTranslation.translate_detailed(VirtAddr::new(0x123)),
+            Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::One,
+                flags: PageTableFlags::empty(),
+            })
+        );
+    }
+}
@@ -1,11 +1,18 @@
 #![cfg(target_pointer_width = "64")]
 
 use crate::structures::paging::{
-    frame::PhysFrame, mapper::*, page_table::PageTable, Page, PageTableFlags,
+    frame::PhysFrame, frame_alloc::FrameDeallocator, mapper::*, page::PageRangeInclusive,
+    page_table::PageTable, Page, PageTableFlags, PageTableIndex,
 };
+use core::ops::RangeInclusive;
 
 /// A Mapper implementation that requires that the complete physically memory is mapped at some
 /// offset in the virtual address space.
+///
+/// This is a thin wrapper around a [`MappedPageTable`] with a [`PhysOffset`] mapping.
+///
+/// Only 4-level paging is supported, so the mapper can't be used while 5-level paging is
+/// enabled through [`Cr4Flags::L5_PAGING`](crate::registers::control::Cr4Flags::L5_PAGING).
 #[derive(Debug)]
 pub struct OffsetPageTable<'a> {
     inner: MappedPageTable<'a, PhysOffset>,
@@ -21,33 +28,142 @@ impl<'a> OffsetPageTable<'a> {
     /// the mapper needs to access page tables, which are not mapped into the virtual address
     /// space by default.
     ///
+    /// In debug builds, this function panics if `phys_offset` is not aligned to a 4KiB page
+    /// boundary, since such an offset can't be correct.
+    ///
     /// ## Safety
     ///
     /// This function is unsafe because the caller must guarantee that the passed `phys_offset`
-    /// is correct. Also, the passed `level_4_table` must point to the level 4 page table
+    /// is correct, i.e. that every physical address `p` that is used by the page table
+    /// hierarchy is mapped and readable and writable at virtual address `phys_offset + p`.
+    /// Also, the passed `level_4_table` must point to the level 4 page table
     /// of a valid page table hierarchy. Otherwise this function might break memory safety, e.g.
     /// by writing to an illegal memory location.
     #[inline]
     pub unsafe fn new(level_4_table: &'a mut PageTable, phys_offset: VirtAddr) -> Self {
+        Self::with_encryption_mask(level_4_table, phys_offset, 0)
+    }
+
+    /// Creates a new `OffsetPageTable` for a page table hierarchy that uses memory encryption,
+    /// e.g. inside an AMD SEV guest.
+    ///
+    /// See [`MappedPageTable::with_encryption_mask`] for how `encryption_mask` is used.
+    ///
+    /// ## Safety
+    ///
+    /// Same as for [`OffsetPageTable::new`]. Additionally, the caller must guarantee that
+    /// `encryption_mask` matches the encryption bit of the CPU.
+    #[inline]
+    pub unsafe fn with_encryption_mask(
+        level_4_table: &'a mut PageTable,
+        phys_offset: VirtAddr,
+        encryption_mask: u64,
+    ) -> Self {
+        debug_assert!(
+            phys_offset.is_aligned(Size4KiB::SIZE),
+            "physical memory offset must be page aligned"
+        );
         let phys_offset = PhysOffset {
             offset: phys_offset,
         };
         Self {
-            inner: MappedPageTable::new(level_4_table, phys_offset),
+            inner: MappedPageTable::with_encryption_mask(
+                level_4_table,
+                phys_offset,
+                encryption_mask,
+            ),
         }
     }
 
-    /// Returns a mutable reference to the wrapped level 4 `PageTable` instance.
-    pub fn level_4_table(&mut self) -> &mut PageTable {
+    /// Returns an immutable reference to the wrapped level 4 `PageTable` instance.
+    #[inline]
+    pub fn level_4_table(&self) -> &PageTable {
         self.inner.level_4_table()
     }
+
+    /// Returns a mutable reference to the wrapped level 4 `PageTable` instance.
+    #[inline]
+    pub fn level_4_table_mut(&mut self) -> &mut PageTable {
+        self.inner.level_4_table_mut()
+    }
+
+    /// Returns the virtual address at which the physical memory is mapped.
+    #[inline]
+    pub fn phys_offset(&self) -> VirtAddr {
+        self.inner.page_table_frame_mapping().offset()
+    }
+
+    /// Returns the virtual address through which the given physical address can be accessed.
+    ///
+    /// See [`PhysOffset::phys_to_virt`].
+    #[inline]
+    pub fn phys_to_virt(&self, addr: PhysAddr) -> VirtAddr {
+        self.inner.page_table_frame_mapping().phys_to_virt(addr)
+    }
+
+    /// Returns an iterator over all mapped regions of the page table.
+    ///
+    /// See [`MappedPageTable::mapped_regions`] for details.
+    #[inline]
+    pub fn mapped_regions(&self) -> impl Iterator<Item = MappedRegion> + '_ {
+        self.inner.mapped_regions()
+    }
+
+    /// Initializes `new_table` as a level 4 table that shares the entries in the given index
+    /// range with the wrapped level 4 table.
+    ///
+    /// See [`MappedPageTable::clone_level_4_into`] for details.
+    #[inline]
+    pub fn clone_level_4_into(
+        &self,
+        new_table: &mut PageTable,
+        range: RangeInclusive<PageTableIndex>,
+    ) {
+        self.inner.clone_level_4_into(new_table, range)
+    }
 }
 
-#[derive(Debug)]
-struct PhysOffset {
+/// A [`PageTableFrameMapping`] for a complete mapping of the physical memory at some offset
+/// in the virtual address space.
+///
+/// The frame with the start address `p` is accessed through the virtual address `offset + p`.
+/// This is the mapping used by [`OffsetPageTable`], but it can also be passed to
+/// [`MappedPageTable::new`] directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysOffset {
     offset: VirtAddr,
 }
 
+impl PhysOffset {
+    /// Creates a new mapping for physical memory mapped at the given virtual offset.
+    #[inline]
+    pub const fn new(offset: VirtAddr) -> Self {
+        PhysOffset { offset }
+    }
+
+    /// Returns the virtual address at which the physical memory is mapped.
+    #[inline]
+    pub const fn offset(self) -> VirtAddr {
+        self.offset
+    }
+
+    /// Returns the virtual address through which the given physical address can be accessed,
+    /// i.e. `offset + addr`.
+    ///
+    /// The result is only valid if the physical memory is actually mapped up to `addr`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the addition overflows or the result is not a canonical address. In contrast
+    /// to `VirtAddr + u64`, the result is not sign extended, see [`VirtAddr::checked_add`].
+    #[inline]
+    pub fn phys_to_virt(self, addr: PhysAddr) -> VirtAddr {
+        self.offset
+            .checked_add(addr.as_u64())
+            .expect("physical address is outside of the offset mapping")
+    }
+}
+
 unsafe impl PageTableFrameMapping for PhysOffset {
     fn frame_to_pointer(&self, frame: PhysFrame) -> *mut PageTable {
         let virt = self.offset + frame.start_address().as_u64();
@@ -82,6 +198,16 @@ impl<'a> Mapper<Size1GiB> for OffsetPageTable<'a> {
         self.inner.unmap(page)
     }
 
+    #[inline]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size1GiB>,
+        new_frame: PhysFrame<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size1GiB>, MapperFlush<Size1GiB>), RemapError> {
+        self.inner.remap(page, new_frame, flags)
+    }
+
     #[inline]
     unsafe fn update_flags(
         &mut self,
@@ -91,6 +217,15 @@ impl<'a> Mapper<Size1GiB> for OffsetPageTable<'a> {
         self.inner.update_flags(page, flags)
     }
 
+    #[inline]
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size1GiB>), FlagUpdateError> {
+        self.inner.clear_flags(page, flags)
+    }
+
     #[inline]
     unsafe fn set_flags_p4_entry(
         &mut self,
@@ -122,6 +257,11 @@ impl<'a> Mapper<Size1GiB> for OffsetPageTable<'a> {
     fn translate_page(&self, page: Page<Size1GiB>) -> Result<PhysFrame<Size1GiB>, TranslateError> {
         self.inner.translate_page(page)
     }
+
+    #[inline]
+    fn page_flags(&self, page: Page<Size1GiB>) -> Result<PageTableFlags, TranslateError> {
+        self.inner.page_flags(page)
+    }
 }
 
 impl<'a> Mapper<Size2MiB> for OffsetPageTable<'a> {
@@ -149,6 +289,16 @@ impl<'a> Mapper<Size2MiB> for OffsetPageTable<'a> {
         self.inner.unmap(page)
     }
 
+    #[inline]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size2MiB>,
+        new_frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size2MiB>, MapperFlush<Size2MiB>), RemapError> {
+        self.inner.remap(page, new_frame, flags)
+    }
+
     #[inline]
     unsafe fn update_flags(
         &mut self,
@@ -158,6 +308,15 @@ impl<'a> Mapper<Size2MiB> for OffsetPageTable<'a> {
         self.inner.update_flags(page, flags)
     }
 
+    #[inline]
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size2MiB>), FlagUpdateError> {
+        self.inner.clear_flags(page, flags)
+    }
+
     #[inline]
     unsafe fn set_flags_p4_entry(
         &mut self,
@@ -189,6 +348,11 @@ impl<'a> Mapper<Size2MiB> for OffsetPageTable<'a> {
     fn translate_page(&self, page: Page<Size2MiB>) -> Result<PhysFrame<Size2MiB>, TranslateError> {
         self.inner.translate_page(page)
     }
+
+    #[inline]
+    fn page_flags(&self, page: Page<Size2MiB>) -> Result<PageTableFlags, TranslateError> {
+        self.inner.page_flags(page)
+    }
 }
 
 impl<'a> Mapper<Size4KiB> for OffsetPageTable<'a> {
@@ -216,6 +380,16 @@ impl<'a> Mapper<Size4KiB> for OffsetPageTable<'a> {
         self.inner.unmap(page)
     }
 
+    #[inline]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size4KiB>,
+        new_frame: PhysFrame<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), RemapError> {
+        self.inner.remap(page, new_frame, flags)
+    }
+
     #[inline]
     unsafe fn update_flags(
         &mut self,
@@ -225,6 +399,15 @@ impl<'a> Mapper<Size4KiB> for OffsetPageTable<'a> {
         self.inner.update_flags(page, flags)
     }
 
+    #[inline]
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size4KiB>), FlagUpdateError> {
+        self.inner.clear_flags(page, flags)
+    }
+
     #[inline]
     unsafe fn set_flags_p4_entry(
         &mut self,
@@ -256,11 +439,61 @@ impl<'a> Mapper<Size4KiB> for OffsetPageTable<'a> {
     fn translate_page(&self, page: Page<Size4KiB>) -> Result<PhysFrame<Size4KiB>, TranslateError> {
         self.inner.translate_page(page)
     }
+
+    #[inline]
+    fn page_flags(&self, page: Page<Size4KiB>) -> Result<PageTableFlags, TranslateError> {
+        self.inner.page_flags(page)
+    }
 }
 
 impl<'a> Translate for OffsetPageTable<'a> {
     #[inline]
-    fn translate(&self, addr: VirtAddr) -> TranslateResult {
-        self.inner.translate(addr)
+    fn translate_detailed(&self, addr: VirtAddr) -> Result<Translation, TranslateErrorDetailed> {
+        self.inner.translate_detailed(addr)
+    }
+}
+
+impl<'a> CleanUp for OffsetPageTable<'a> {
+    #[inline]
+    unsafe fn clean_up<D>(&mut self, frame_deallocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        self.inner.clean_up(frame_deallocator)
+    }
+
+    #[inline]
+    unsafe fn clean_up_addr_range<D>(
+        &mut self,
+        range: PageRangeInclusive,
+        frame_deallocator: &mut D,
+    ) where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        self.inner.clean_up_addr_range(range, frame_deallocator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_accessors() {
+        let mut tables = vec![PageTable::new(); 1];
+        let phys_offset = VirtAddr::from_ptr(tables.as_mut_ptr());
+        let mut mapper = unsafe { OffsetPageTable::new(&mut tables[0], phys_offset) };
+
+        assert_eq!(mapper.phys_offset(), phys_offset);
+        assert_eq!(
+            mapper.phys_to_virt(PhysAddr::new(0x42)),
+            phys_offset + 0x42u64
+        );
+        assert_eq!(
+            VirtAddr::from_ptr(mapper.level_4_table()),
+            mapper.phys_to_virt(PhysAddr::zero())
+        );
+        mapper.level_4_table_mut()[1].set_flags(PageTableFlags::BIT_9);
+        assert_eq!(mapper.level_4_table().count_used(), 1);
     }
 }
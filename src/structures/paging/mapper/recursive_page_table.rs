@@ -2,13 +2,14 @@
 
 use core::fmt;
 
+use super::mapped_regions::MappedRegions;
 use super::*;
 use crate::registers::control::Cr3;
 use crate::structures::paging::PageTableIndex;
 use crate::structures::paging::{
-    frame_alloc::FrameAllocator,
-    page::{AddressNotAligned, NotGiantPageSize},
-    page_table::{FrameError, PageTable, PageTableEntry, PageTableFlags},
+    frame_alloc::{FrameAllocator, FrameDeallocator},
+    page::{NotGiantPageSize, PageRangeInclusive},
+    page_table::{FrameError, PageTable, PageTableEntry, PageTableFlags, PageTableLevel},
     Page, PageSize, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
 };
 use crate::VirtAddr;
@@ -83,9 +84,37 @@ impl<'a> RecursivePageTable<'a> {
         }
     }
 
+    /// Returns an immutable reference to the wrapped level 4 `PageTable` instance.
+    #[inline]
+    pub fn level_4_table(&self) -> &PageTable {
+        self.p4
+    }
+
     /// Returns a mutable reference to the wrapped level 4 `PageTable` instance.
-    pub fn level_4_table(&mut self) -> &mut PageTable {
-        &mut self.p4
+    #[inline]
+    pub fn level_4_table_mut(&mut self) -> &mut PageTable {
+        self.p4
+    }
+
+    /// Returns an iterator over all mapped regions of the page table.
+    ///
+    /// The page table hierarchy is walked depth-first, so the regions are returned in the order
+    /// of their virtual addresses. Adjacent pages are coalesced into a single [`MappedRegion`]
+    /// if they are mapped with the same page size and flags to contiguous physical memory.
+    /// Huge pages are supported at all levels, level 4 entries with the `HUGE_PAGE` flag set
+    /// are skipped, and so is the recursive entry. The iterator doesn't allocate, regions are
+    /// yielded while walking.
+    pub fn mapped_regions(&self) -> impl Iterator<Item = MappedRegion> + '_ {
+        let recursive_index = self.recursive_index;
+        MappedRegions::new(self.p4, Some(recursive_index), 0, move |depth, addr, _| {
+            let page = Page::<Size4KiB>::containing_address(addr);
+            let page_table = match depth {
+                0 => p3_ptr(page, recursive_index),
+                1 => p2_ptr(page, recursive_index),
+                _ => p1_ptr(page, recursive_index),
+            };
+            Some(unsafe { &*page_table })
+        })
     }
 
     /// Internal helper function to create the page table of the next level if needed.
@@ -145,7 +174,7 @@ impl<'a> RecursivePageTable<'a> {
                 return Err(MapToError::ParentEntryHugePage);
             }
 
-            let page_table_ptr = next_table_page.start_address().as_mut_ptr();
+            let page_table_ptr = table_ptr(next_table_page);
             let page_table: &mut PageTable = unsafe { &mut *(page_table_ptr) };
             if created {
                 page_table.zero();
@@ -328,12 +357,46 @@ impl<'a> Mapper<Size1GiB> for RecursivePageTable<'a> {
         }
 
         let frame = PhysFrame::from_start_address(p3_entry.addr())
-            .map_err(|AddressNotAligned| UnmapError::InvalidFrameAddress(p3_entry.addr()))?;
+            .map_err(|_| UnmapError::InvalidFrameAddress(p3_entry.addr()))?;
 
         p3_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
+    #[allow(unused_unsafe)]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size1GiB>,
+        new_frame: PhysFrame<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size1GiB>, MapperFlush<Size1GiB>), RemapError> {
+        let p4 = &mut self.p4;
+        let p4_entry = &p4[page.p4_index()];
+
+        p4_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p3 = unsafe { &mut *(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = &mut p3[page.p3_index()];
+        let old_flags = p3_entry.flags();
+
+        if !old_flags.contains(PageTableFlags::PRESENT) {
+            return Err(RemapError::PageNotMapped);
+        }
+        if !old_flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(RemapError::ParentEntryHugePage);
+        }
+
+        let frame = PhysFrame::from_start_address(p3_entry.addr())
+            .map_err(|_| RemapError::InvalidFrameAddress(p3_entry.addr()))?;
+
+        p3_entry.set_addr(new_frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
     #[allow(unused_unsafe)]
     unsafe fn update_flags(
@@ -353,11 +416,32 @@ impl<'a> Mapper<Size1GiB> for RecursivePageTable<'a> {
         if p3[page.p3_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
-        p3[page.p3_index()].set_flags(flags | Flags::HUGE_PAGE);
+        p3[page.p3_index()].set_flags_keep_os_bits(flags | Flags::HUGE_PAGE);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size1GiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size1GiB>), FlagUpdateError> {
+        let p4 = &mut self.p4;
+
+        if p4[page.p4_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p3 = &mut *(p3_ptr(page, self.recursive_index));
+
+        if p3[page.p3_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p3[page.p3_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size1GiB>,
@@ -394,19 +478,36 @@ impl<'a> Mapper<Size1GiB> for RecursivePageTable<'a> {
     fn translate_page(&self, page: Page<Size1GiB>) -> Result<PhysFrame<Size1GiB>, TranslateError> {
         let p4 = &self.p4;
 
-        if p4[page.p4_index()].is_unused() {
+        if p4[page.p4_index()].read_volatile().is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
-        let p3_entry = &p3[page.p3_index()];
+        let p3_entry = p3[page.p3_index()].read_volatile();
 
         if p3_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         PhysFrame::from_start_address(p3_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p3_entry.addr()))
+            .map_err(|_| TranslateError::InvalidFrameAddress(p3_entry.addr()))
+    }
+
+    fn page_flags(&self, page: Page<Size1GiB>) -> Result<PageTableFlags, TranslateError> {
+        let p4 = &self.p4;
+
+        if p4[page.p4_index()].read_volatile().is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = p3[page.p3_index()].read_volatile();
+
+        if p3_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        Ok(p3_entry.flags())
     }
 }
 
@@ -456,12 +557,52 @@ impl<'a> Mapper<Size2MiB> for RecursivePageTable<'a> {
         }
 
         let frame = PhysFrame::from_start_address(p2_entry.addr())
-            .map_err(|AddressNotAligned| UnmapError::InvalidFrameAddress(p2_entry.addr()))?;
+            .map_err(|_| UnmapError::InvalidFrameAddress(p2_entry.addr()))?;
 
         p2_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
+    #[allow(unused_unsafe)]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size2MiB>,
+        new_frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size2MiB>, MapperFlush<Size2MiB>), RemapError> {
+        let p4 = &mut self.p4;
+        let p4_entry = &p4[page.p4_index()];
+        p4_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p3 = unsafe { &mut *(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = &p3[page.p3_index()];
+        p3_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p2 = unsafe { &mut *(p2_ptr(page, self.recursive_index)) };
+        let p2_entry = &mut p2[page.p2_index()];
+        let old_flags = p2_entry.flags();
+
+        if !old_flags.contains(PageTableFlags::PRESENT) {
+            return Err(RemapError::PageNotMapped);
+        }
+        if !old_flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(RemapError::ParentEntryHugePage);
+        }
+
+        let frame = PhysFrame::from_start_address(p2_entry.addr())
+            .map_err(|_| RemapError::InvalidFrameAddress(p2_entry.addr()))?;
+
+        p2_entry.set_addr(new_frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
     #[allow(unused_unsafe)]
     unsafe fn update_flags(
@@ -488,11 +629,38 @@ impl<'a> Mapper<Size2MiB> for RecursivePageTable<'a> {
             return Err(FlagUpdateError::PageNotMapped);
         }
 
-        p2[page.p2_index()].set_flags(flags | Flags::HUGE_PAGE);
+        p2[page.p2_index()].set_flags_keep_os_bits(flags | Flags::HUGE_PAGE);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size2MiB>), FlagUpdateError> {
+        let p4 = &mut self.p4;
+
+        if p4[page.p4_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p3 = &mut *(p3_ptr(page, self.recursive_index));
+
+        if p3[page.p3_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p2 = &mut *(p2_ptr(page, self.recursive_index));
+
+        if p2[page.p2_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p2[page.p2_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size2MiB>,
@@ -527,6 +695,9 @@ impl<'a> Mapper<Size2MiB> for RecursivePageTable<'a> {
         if p3_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p3_entry.set_flags(flags);
 
@@ -544,26 +715,56 @@ impl<'a> Mapper<Size2MiB> for RecursivePageTable<'a> {
     fn translate_page(&self, page: Page<Size2MiB>) -> Result<PhysFrame<Size2MiB>, TranslateError> {
         let p4 = &self.p4;
 
-        if p4[page.p4_index()].is_unused() {
+        if p4[page.p4_index()].read_volatile().is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
-        let p3_entry = &p3[page.p3_index()];
+        let p3_entry = p3[page.p3_index()].read_volatile();
 
         if p3_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
 
         let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
-        let p2_entry = &p2[page.p2_index()];
+        let p2_entry = p2[page.p2_index()].read_volatile();
 
         if p2_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         PhysFrame::from_start_address(p2_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p2_entry.addr()))
+            .map_err(|_| TranslateError::InvalidFrameAddress(p2_entry.addr()))
+    }
+
+    fn page_flags(&self, page: Page<Size2MiB>) -> Result<PageTableFlags, TranslateError> {
+        let p4 = &self.p4;
+
+        if p4[page.p4_index()].read_volatile().is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = p3[page.p3_index()].read_volatile();
+
+        if p3_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
+
+        let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
+        let p2_entry = p2[page.p2_index()].read_volatile();
+
+        if p2_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        Ok(p2_entry.flags())
     }
 }
 
@@ -620,6 +821,47 @@ impl<'a> Mapper<Size4KiB> for RecursivePageTable<'a> {
         Ok((frame, MapperFlush::new(page)))
     }
 
+    // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
+    #[allow(unused_unsafe)]
+    unsafe fn remap(
+        &mut self,
+        page: Page<Size4KiB>,
+        new_frame: PhysFrame<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PhysFrame<Size4KiB>, MapperFlush<Size4KiB>), RemapError> {
+        let p4 = &mut self.p4;
+        let p4_entry = &p4[page.p4_index()];
+        p4_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p3 = unsafe { &mut *(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = &p3[page.p3_index()];
+        p3_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p2 = unsafe { &mut *(p2_ptr(page, self.recursive_index)) };
+        let p2_entry = &p2[page.p2_index()];
+        p2_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        let p1 = unsafe { &mut *(p1_ptr(page, self.recursive_index)) };
+        let p1_entry = &mut p1[page.p1_index()];
+
+        let frame = p1_entry.frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => RemapError::PageNotMapped,
+            FrameError::HugeFrame => RemapError::ParentEntryHugePage,
+        })?;
+
+        p1_entry.set_addr(new_frame.start_address(), flags);
+        Ok((frame, MapperFlush::new(page)))
+    }
+
     // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
     #[allow(unused_unsafe)]
     unsafe fn update_flags(
@@ -651,11 +893,44 @@ impl<'a> Mapper<Size4KiB> for RecursivePageTable<'a> {
             return Err(FlagUpdateError::PageNotMapped);
         }
 
-        p1[page.p1_index()].set_flags(flags);
+        p1[page.p1_index()].set_flags_keep_os_bits(flags);
 
         Ok(MapperFlush::new(page))
     }
 
+    unsafe fn clear_flags(
+        &mut self,
+        page: Page<Size4KiB>,
+        flags: PageTableFlags,
+    ) -> Result<(PageTableFlags, MapperFlush<Size4KiB>), FlagUpdateError> {
+        let p4 = &mut self.p4;
+
+        if p4[page.p4_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p3 = &mut *(p3_ptr(page, self.recursive_index));
+
+        if p3[page.p3_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p2 = &mut *(p2_ptr(page, self.recursive_index));
+
+        if p2[page.p2_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+
+        let p1 = &mut *(p1_ptr(page, self.recursive_index));
+
+        if p1[page.p1_index()].is_unused() {
+            return Err(FlagUpdateError::PageNotMapped);
+        }
+        let old_flags = p1[page.p1_index()].clear_flags_atomic(flags);
+
+        Ok((old_flags, MapperFlush::new(page)))
+    }
+
     unsafe fn set_flags_p4_entry(
         &mut self,
         page: Page<Size4KiB>,
@@ -690,6 +965,9 @@ impl<'a> Mapper<Size4KiB> for RecursivePageTable<'a> {
         if p3_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p3_entry.set_flags(flags);
 
@@ -719,6 +997,9 @@ impl<'a> Mapper<Size4KiB> for RecursivePageTable<'a> {
         if p2_entry.is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p2_entry.set_flags(flags);
 
@@ -728,104 +1009,259 @@ impl<'a> Mapper<Size4KiB> for RecursivePageTable<'a> {
     fn translate_page(&self, page: Page<Size4KiB>) -> Result<PhysFrame<Size4KiB>, TranslateError> {
         let p4 = &self.p4;
 
-        if p4[page.p4_index()].is_unused() {
+        if p4[page.p4_index()].read_volatile().is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
-        let p3_entry = &p3[page.p3_index()];
+        let p3_entry = p3[page.p3_index()].read_volatile();
 
         if p3_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
 
         let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
-        let p2_entry = &p2[page.p2_index()];
+        let p2_entry = p2[page.p2_index()].read_volatile();
 
         if p2_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
+        if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
 
         let p1 = unsafe { &*(p1_ptr(page, self.recursive_index)) };
-        let p1_entry = &p1[page.p1_index()];
+        let p1_entry = p1[page.p1_index()].read_volatile();
 
         if p1_entry.is_unused() {
             return Err(TranslateError::PageNotMapped);
         }
 
         PhysFrame::from_start_address(p1_entry.addr())
-            .map_err(|AddressNotAligned| TranslateError::InvalidFrameAddress(p1_entry.addr()))
+            .map_err(|_| TranslateError::InvalidFrameAddress(p1_entry.addr()))
+    }
+
+    fn page_flags(&self, page: Page<Size4KiB>) -> Result<PageTableFlags, TranslateError> {
+        let p4 = &self.p4;
+
+        if p4[page.p4_index()].read_volatile().is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
+        let p3_entry = p3[page.p3_index()].read_volatile();
+
+        if p3_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+        if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
+
+        let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
+        let p2_entry = p2[page.p2_index()].read_volatile();
+
+        if p2_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+        if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateError::ParentEntryHugePage);
+        }
+
+        let p1 = unsafe { &*(p1_ptr(page, self.recursive_index)) };
+        let p1_entry = p1[page.p1_index()].read_volatile();
+
+        if p1_entry.is_unused() {
+            return Err(TranslateError::PageNotMapped);
+        }
+
+        Ok(p1_entry.flags())
     }
 }
 
 impl<'a> Translate for RecursivePageTable<'a> {
     #[allow(clippy::inconsistent_digit_grouping)]
-    fn translate(&self, addr: VirtAddr) -> TranslateResult {
+    fn translate_detailed(&self, addr: VirtAddr) -> Result<Translation, TranslateErrorDetailed> {
         let page = Page::containing_address(addr);
 
         let p4 = &self.p4;
-        let p4_entry = &p4[addr.p4_index()];
+        let p4_entry = p4[addr.p4_index()].read_volatile();
         if p4_entry.is_unused() {
-            return TranslateResult::NotMapped;
+            return Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::Four,
+                flags: p4_entry.flags(),
+            });
         }
         if p4_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            panic!("level 4 entry has huge page bit set")
+            return Err(TranslateErrorDetailed::InvalidHugePage {
+                level: PageTableLevel::Four,
+                flags: p4_entry.flags(),
+            });
         }
 
         let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
-        let p3_entry = &p3[addr.p3_index()];
+        let p3_entry = p3[addr.p3_index()].read_volatile();
         if p3_entry.is_unused() {
-            return TranslateResult::NotMapped;
+            return Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::Three,
+                flags: p3_entry.flags(),
+            });
         }
         if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            let entry = &p3[addr.p3_index()];
-            let frame = PhysFrame::containing_address(entry.addr());
-            let offset = addr.as_u64() & 0o_777_777_7777;
-            let flags = entry.flags();
-            return TranslateResult::Mapped {
-                frame: MappedFrame::Size1GiB(frame),
-                offset,
-                flags,
-            };
+            return Ok(Translation {
+                frame: MappedFrame::Size1GiB(PhysFrame::containing_address(p3_entry.addr())),
+                offset: addr.as_u64() & 0o_777_777_7777,
+                flags: p3_entry.flags(),
+            });
         }
 
         let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
-        let p2_entry = &p2[addr.p2_index()];
+        let p2_entry = p2[addr.p2_index()].read_volatile();
         if p2_entry.is_unused() {
-            return TranslateResult::NotMapped;
+            return Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::Two,
+                flags: p2_entry.flags(),
+            });
         }
         if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            let entry = &p2[addr.p2_index()];
-            let frame = PhysFrame::containing_address(entry.addr());
-            let offset = addr.as_u64() & 0o_777_7777;
-            let flags = entry.flags();
-            return TranslateResult::Mapped {
-                frame: MappedFrame::Size2MiB(frame),
-                offset,
-                flags,
-            };
+            return Ok(Translation {
+                frame: MappedFrame::Size2MiB(PhysFrame::containing_address(p2_entry.addr())),
+                offset: addr.as_u64() & 0o_777_7777,
+                flags: p2_entry.flags(),
+            });
         }
 
         let p1 = unsafe { &*(p1_ptr(page, self.recursive_index)) };
-        let p1_entry = &p1[addr.p1_index()];
+        let p1_entry = p1[addr.p1_index()].read_volatile();
+        let flags = p1_entry.flags();
         if p1_entry.is_unused() {
-            return TranslateResult::NotMapped;
+            return Err(TranslateErrorDetailed::NotMapped {
+                level: PageTableLevel::One,
+                flags,
+            });
         }
-        if p1_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            panic!("level 1 entry has huge page bit set")
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            return Err(TranslateErrorDetailed::InvalidHugePage {
+                level: PageTableLevel::One,
+                flags,
+            });
         }
 
-        let frame = match PhysFrame::from_start_address(p1_entry.addr()) {
-            Ok(frame) => frame,
-            Err(AddressNotAligned) => return TranslateResult::InvalidFrameAddress(p1_entry.addr()),
-        };
-        let offset = u64::from(addr.page_offset());
-        let flags = p1_entry.flags();
-        TranslateResult::Mapped {
+        let frame = PhysFrame::from_start_address(p1_entry.addr()).map_err(|_| {
+            TranslateErrorDetailed::InvalidFrameAddress {
+                level: PageTableLevel::One,
+                flags,
+                addr: p1_entry.addr(),
+            }
+        })?;
+        Ok(Translation {
             frame: MappedFrame::Size4KiB(frame),
-            offset,
+            offset: u64::from(addr.page_offset()),
             flags,
+        })
+    }
+}
+
+impl<'a> CleanUp for RecursivePageTable<'a> {
+    #[inline]
+    unsafe fn clean_up<D>(&mut self, frame_deallocator: &mut D)
+    where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        self.clean_up_addr_range(
+            Page::range_inclusive(
+                Page::containing_address(VirtAddr::new(0)),
+                Page::containing_address(VirtAddr::new(0xffff_ffff_ffff_ffff)),
+            ),
+            frame_deallocator,
+        )
+    }
+
+    unsafe fn clean_up_addr_range<D>(
+        &mut self,
+        range: PageRangeInclusive,
+        frame_deallocator: &mut D,
+    ) where
+        D: FrameDeallocator<Size4KiB>,
+    {
+        /// Frees the empty tables below `page_table` and returns whether `page_table` itself
+        /// is empty afterwards.
+        fn clean_up<D: FrameDeallocator<Size4KiB>>(
+            recursive_index: PageTableIndex,
+            page_table: &mut PageTable,
+            level: PageTableLevel,
+            range: PageRangeInclusive,
+            frame_deallocator: &mut D,
+        ) -> bool {
+            if range.is_empty() {
+                return false;
+            }
+
+            let table_addr = range
+                .start
+                .start_address()
+                .align_down(level.table_address_space_alignment());
+
+            let start = range.start.page_table_index(level);
+            let end = range.end.page_table_index(level);
+
+            if let Some(next_level) = level.next_lower_level() {
+                let offset_per_entry = level.entry_address_space_alignment();
+                for (i, entry) in page_table
+                    .iter_mut()
+                    .enumerate()
+                    .take(usize::from(end) + 1)
+                    .skip(usize::from(start))
+                    // the recursive entry points to the level 4 table itself
+                    .filter(|(i, _)| {
+                        !(level == PageTableLevel::Four && *i == usize::from(recursive_index))
+                    })
+                {
+                    // fails for unused entries and huge pages, which are skipped
+                    if let Ok(frame) = entry.frame() {
+                        let start = VirtAddr::new_truncate(
+                            table_addr.as_u64() + offset_per_entry * i as u64,
+                        );
+                        let end = start + (offset_per_entry - 1);
+                        let start = Page::containing_address(start).max(range.start);
+                        let end = Page::containing_address(end).min(range.end);
+                        let next_table_ptr = match level {
+                            PageTableLevel::Four => p3_ptr(start, recursive_index),
+                            PageTableLevel::Three => p2_ptr(start, recursive_index),
+                            _ => p1_ptr(start, recursive_index),
+                        };
+                        let next_table = unsafe { &mut *next_table_ptr };
+                        if clean_up(
+                            recursive_index,
+                            next_table,
+                            next_level,
+                            Page::range_inclusive(start, end),
+                            frame_deallocator,
+                        ) {
+                            entry.set_unused();
+                            // remove the stale recursive mapping of the freed table
+                            crate::instructions::tlb::flush(VirtAddr::from_ptr(next_table_ptr));
+                            unsafe {
+                                frame_deallocator.deallocate_frame(frame);
+                            }
+                        }
+                    }
+                }
+            }
+
+            page_table.iter().all(PageTableEntry::is_unused)
         }
+
+        clean_up(
+            self.recursive_index,
+            self.level_4_table_mut(),
+            PageTableLevel::Four,
+            range,
+            frame_deallocator,
+        );
     }
 }
 
@@ -856,9 +1292,19 @@ impl fmt::Display for InvalidPageTable {
     }
 }
 
+/// Returns a pointer to the page table that is accessible through the given recursive page.
+#[inline]
+fn table_ptr(page: Page) -> *mut PageTable {
+    // the tests emulate the recursive mapping with in-memory tables
+    #[cfg(test)]
+    return tests::table_ptr(page);
+    #[cfg(not(test))]
+    page.start_address().as_mut_ptr()
+}
+
 #[inline]
 fn p3_ptr<S: PageSize>(page: Page<S>, recursive_index: PageTableIndex) -> *mut PageTable {
-    p3_page(page, recursive_index).start_address().as_mut_ptr()
+    table_ptr(p3_page(page, recursive_index))
 }
 
 #[inline]
@@ -873,7 +1319,7 @@ fn p3_page<S: PageSize>(page: Page<S>, recursive_index: PageTableIndex) -> Page
 
 #[inline]
 fn p2_ptr<S: NotGiantPageSize>(page: Page<S>, recursive_index: PageTableIndex) -> *mut PageTable {
-    p2_page(page, recursive_index).start_address().as_mut_ptr()
+    table_ptr(p2_page(page, recursive_index))
 }
 
 #[inline]
@@ -888,7 +1334,7 @@ fn p2_page<S: NotGiantPageSize>(page: Page<S>, recursive_index: PageTableIndex)
 
 #[inline]
 fn p1_ptr(page: Page<Size4KiB>, recursive_index: PageTableIndex) -> *mut PageTable {
-    p1_page(page, recursive_index).start_address().as_mut_ptr()
+    table_ptr(p1_page(page, recursive_index))
 }
 
 #[inline]
@@ -900,3 +1346,240 @@ fn p1_page(page: Page<Size4KiB>, recursive_index: PageTableIndex) -> Page {
         page.p2_index(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysAddr;
+    use std::boxed::Box;
+    use std::cell::RefCell;
+    use std::vec::Vec;
+
+    std::thread_local! {
+        static MAPPED: RefCell<Vec<(Page, *mut PageTable)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Resolves the recursive page of a page table through the tables of the current
+    /// [`RecursiveMappings`].
+    pub(super) fn table_ptr(page: Page) -> *mut PageTable {
+        MAPPED.with(|mapped| {
+            let mapped = mapped.borrow();
+            match mapped.iter().find(|(mapped_page, _)| *mapped_page == page) {
+                Some(&(_, table)) => table,
+                None => panic!("{:?} is not mapped", page),
+            }
+        })
+    }
+
+    /// Emulates the recursive mapping on the host with in-memory page tables, where the `n`th
+    /// table stands in for the frame with the start address `n * 4096`.
+    struct RecursiveMappings {
+        tables: Vec<*mut PageTable>,
+    }
+
+    impl RecursiveMappings {
+        fn new(frames: u64) -> Self {
+            RecursiveMappings {
+                tables: (0..frames)
+                    .map(|_| Box::into_raw(Box::new(PageTable::new())))
+                    .collect(),
+            }
+        }
+
+        /// Makes the page table in the given frame accessible through the given page.
+        fn map(&mut self, page: Page, frame: PhysFrame) -> *mut PageTable {
+            let table = self.tables[(frame.start_address().as_u64() / Size4KiB::SIZE) as usize];
+            MAPPED.with(|mapped| mapped.borrow_mut().push((page, table)));
+            table
+        }
+    }
+
+    impl Drop for RecursiveMappings {
+        fn drop(&mut self) {
+            MAPPED.with(|mapped| mapped.borrow_mut().clear());
+            for &table in &self.tables {
+                drop(unsafe { Box::from_raw(table) });
+            }
+        }
+    }
+
+    /// Returns no frames, all page tables are created upfront.
+    struct NoFrames;
+
+    unsafe impl FrameAllocator<Size4KiB> for NoFrames {
+        fn allocate_frame(&mut self) -> Option<PhysFrame> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_huge_pages() {
+        use crate::structures::paging::PageTableFlags as Flags;
+
+        let table_frame = |n: u64| PhysFrame::containing_address(PhysAddr::new(n * 4096));
+        let index = PageTableIndex::new;
+        let recursive_index = index(64);
+        let (p4_index, p3_index, p2_index) = (index(1), index(2), index(3));
+        let table_flags = Flags::PRESENT | Flags::WRITABLE;
+
+        let mut mappings = RecursiveMappings::new(4);
+        let r = recursive_index;
+        let p4 = mappings.map(Page::from_page_table_indices(r, r, r, r), table_frame(0));
+        let p4 = unsafe { &mut *p4 };
+        p4[r].set_frame(table_frame(0), table_flags);
+        p4[p4_index].set_frame(table_frame(1), table_flags);
+        mappings.map(
+            Page::from_page_table_indices(r, r, r, p4_index),
+            table_frame(1),
+        );
+        let mut mapper = unsafe { RecursivePageTable::new_unchecked(p4, recursive_index) };
+
+        // 1GiB page
+        let page = Page::<Size1GiB>::from_page_table_indices_1gib(p4_index, p3_index);
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x4000_0000));
+        unsafe { mapper.map_to(page, frame, table_flags, &mut NoFrames) }
+            .unwrap()
+            .ignore();
+        assert_eq!(mapper.translate_page(page).unwrap(), frame);
+        assert!(mapper.page_flags(page).unwrap().contains(Flags::HUGE_PAGE));
+        assert_eq!(
+            mapper.translate_addr(page.start_address() + 0x1234_5678u64),
+            Some(frame.start_address() + 0x1234_5678u64)
+        );
+        let small_page: Page<Size2MiB> = Page::containing_address(page.start_address());
+        assert!(matches!(
+            mapper.translate_page(small_page),
+            Err(TranslateError::ParentEntryHugePage)
+        ));
+        let new_frame = frame + 1;
+        let (old_frame, flush) = unsafe { mapper.remap(page, new_frame, table_flags) }.unwrap();
+        flush.ignore();
+        assert_eq!(old_frame, frame);
+        assert_eq!(mapper.translate_page(page).unwrap(), new_frame);
+        let frame = new_frame;
+        let (unmapped, flush) = mapper.unmap(page).unwrap();
+        flush.ignore();
+        assert_eq!(unmapped, frame);
+        assert!(matches!(
+            mapper.translate_page(page),
+            Err(TranslateError::PageNotMapped)
+        ));
+
+        // 2MiB page
+        let p3 = unsafe { &mut *p3_ptr(page, recursive_index) };
+        p3[p3_index].set_frame(table_frame(2), table_flags);
+        mappings.map(
+            Page::from_page_table_indices(r, r, p4_index, p3_index),
+            table_frame(2),
+        );
+
+        let page = Page::<Size2MiB>::from_page_table_indices_2mib(p4_index, p3_index, p2_index);
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x20_0000));
+        unsafe { mapper.map_to(page, frame, table_flags, &mut NoFrames) }
+            .unwrap()
+            .ignore();
+        assert_eq!(mapper.translate_page(page).unwrap(), frame);
+        assert_eq!(
+            mapper.translate_addr(page.start_address() + 0x1234u64),
+            Some(frame.start_address() + 0x1234u64)
+        );
+        unsafe { mapper.update_flags(page, table_flags | Flags::NO_EXECUTE) }
+            .unwrap()
+            .ignore();
+        assert_eq!(
+            mapper.page_flags(page).unwrap(),
+            table_flags | Flags::NO_EXECUTE | Flags::HUGE_PAGE
+        );
+        let small_page: Page = Page::containing_address(page.start_address());
+        assert!(matches!(
+            mapper.translate_page(small_page),
+            Err(TranslateError::ParentEntryHugePage)
+        ));
+        let new_frame = frame + 1;
+        let (old_frame, flush) = unsafe { mapper.remap(page, new_frame, table_flags) }.unwrap();
+        flush.ignore();
+        assert_eq!(old_frame, frame);
+        assert_eq!(mapper.translate_page(page).unwrap(), new_frame);
+        let frame = new_frame;
+        let (unmapped, flush) = mapper.unmap(page).unwrap();
+        flush.ignore();
+        assert_eq!(unmapped, frame);
+        assert!(matches!(
+            mapper.translate_page(page),
+            Err(TranslateError::PageNotMapped)
+        ));
+
+        // 4KiB page
+        let p2 = unsafe { &mut *p2_ptr(page, recursive_index) };
+        p2[p2_index].set_frame(table_frame(3), table_flags);
+        mappings.map(
+            Page::from_page_table_indices(r, p4_index, p3_index, p2_index),
+            table_frame(3),
+        );
+
+        let page = Page::<Size4KiB>::from_page_table_indices(p4_index, p3_index, p2_index, r);
+        let frame = PhysFrame::containing_address(PhysAddr::new(0x5000));
+        unsafe {
+            assert!(matches!(
+                mapper.remap(page, frame, table_flags),
+                Err(RemapError::PageNotMapped)
+            ));
+            mapper
+                .map_to(page, frame, table_flags, &mut NoFrames)
+                .unwrap()
+                .ignore();
+        }
+        let new_frame = frame + 1;
+        let (old_frame, flush) = unsafe { mapper.remap(page, new_frame, table_flags) }.unwrap();
+        flush.ignore();
+        assert_eq!(old_frame, frame);
+        assert_eq!(mapper.translate_page(page).unwrap(), new_frame);
+        let frame = new_frame;
+        assert_eq!(
+            mapper.translate_addr(page.start_address() + 0x123u64),
+            Some(frame.start_address() + 0x123u64)
+        );
+    }
+
+    #[test]
+    fn test_mapped_regions() {
+        use crate::structures::paging::PageTableFlags as Flags;
+
+        let table_frame = |n: u64| PhysFrame::containing_address(PhysAddr::new(n * 4096));
+        let index = PageTableIndex::new;
+        let r = index(64);
+        let flags = Flags::PRESENT | Flags::WRITABLE;
+
+        let mut mappings = RecursiveMappings::new(2);
+        let p4 = mappings.map(Page::from_page_table_indices(r, r, r, r), table_frame(0));
+        let p4 = unsafe { &mut *p4 };
+        p4[r].set_frame(table_frame(0), flags);
+        p4[index(1)].set_frame(table_frame(1), flags);
+        mappings.map(
+            Page::from_page_table_indices(r, r, r, index(1)),
+            table_frame(1),
+        );
+        let mut mapper = unsafe { RecursivePageTable::new_unchecked(p4, r) };
+
+        for (p3_index, frame) in [(2, 0x4000_0000), (3, 0x8000_0000)] {
+            let page = Page::<Size1GiB>::from_page_table_indices_1gib(index(1), index(p3_index));
+            let frame = PhysFrame::containing_address(PhysAddr::new(frame));
+            unsafe { mapper.map_to(page, frame, flags, &mut NoFrames) }
+                .unwrap()
+                .ignore();
+        }
+
+        // the recursive entry is skipped
+        let regions = mapper.mapped_regions().collect::<Vec<_>>();
+        assert_eq!(
+            regions,
+            [MappedRegion {
+                start: VirtAddr::new(0x80_8000_0000),
+                len: 0x8000_0000,
+                phys_start: PhysAddr::new(0x4000_0000),
+                page_size: PageSizeKind::Size1GiB,
+                flags: flags | Flags::HUGE_PAGE,
+            }]
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! Abstractions for default-sized and huge virtual memory pages.
 
-use crate::structures::paging::PageTableIndex;
-use crate::VirtAddr;
+use crate::structures::paging::{PageTableIndex, PageTableLevel};
+use crate::{align_down, VirtAddr};
+use core::convert::TryFrom;
 use core::fmt;
+use core::iter::FusedIterator;
+#[cfg(feature = "step_trait")]
+use core::iter::Step;
 use core::marker::PhantomData;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
@@ -51,6 +55,142 @@ impl PageSize for Size1GiB {
     const SIZE_AS_DEBUG_STR: &'static str = "1GiB";
 }
 
+/// The size of a page as a runtime value.
+///
+/// This is useful for code that deals with pages of mixed sizes, e.g. when walking page
+/// tables. See [`AnyPage`] and [`AnyFrame`](super::frame::AnyFrame).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageSizeKind {
+    /// A standard 4KiB page.
+    Size4KiB,
+    /// A “huge” 2MiB page.
+    Size2MiB,
+    /// A “giant” 1GiB page.
+    Size1GiB,
+}
+
+impl PageSizeKind {
+    /// Returns the page size in bytes.
+    #[inline]
+    pub const fn size(self) -> u64 {
+        match self {
+            PageSizeKind::Size4KiB => Size4KiB::SIZE,
+            PageSizeKind::Size2MiB => Size2MiB::SIZE,
+            PageSizeKind::Size1GiB => Size1GiB::SIZE,
+        }
+    }
+
+    /// Returns the page size kind for the given size in bytes, or `None` if it is not a
+    /// supported page size.
+    #[inline]
+    pub const fn from_size(size: u64) -> Option<Self> {
+        match size {
+            4096 => Some(PageSizeKind::Size4KiB),
+            0x20_0000 => Some(PageSizeKind::Size2MiB),
+            0x4000_0000 => Some(PageSizeKind::Size1GiB),
+            _ => None,
+        }
+    }
+}
+
+/// A virtual memory page whose size is only known at runtime.
+///
+/// This is the interchange representation for pages of mixed sizes. It can be converted from
+/// a [`Page`] through `From` and back through `TryFrom`, which fails if the sizes don't match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AnyPage {
+    start_address: VirtAddr,
+    size: PageSizeKind,
+}
+
+impl AnyPage {
+    /// Returns the page of the given size that starts at the given virtual address.
+    ///
+    /// Returns an error if the address is not correctly aligned (i.e. is not a valid page start).
+    #[inline]
+    pub fn from_start_address(
+        address: VirtAddr,
+        size: PageSizeKind,
+    ) -> Result<Self, AddressNotAligned> {
+        if !address.is_aligned(size.size()) {
+            return Err(AddressNotAligned::new(address.as_u64(), size.size()));
+        }
+        Ok(AnyPage::containing_address(address, size))
+    }
+
+    /// Returns the page of the given size that contains the given virtual address.
+    #[inline]
+    pub fn containing_address(address: VirtAddr, size: PageSizeKind) -> Self {
+        AnyPage {
+            start_address: address.align_down(size.size()),
+            size,
+        }
+    }
+
+    /// Returns the start address of the page.
+    #[inline]
+    pub fn start_address(self) -> VirtAddr {
+        self.start_address
+    }
+
+    /// Returns the size of the page.
+    #[inline]
+    pub fn size_kind(self) -> PageSizeKind {
+        self.size
+    }
+
+    /// Returns the size of the page in bytes.
+    #[inline]
+    pub fn size(self) -> u64 {
+        self.size.size()
+    }
+}
+
+impl From<Page<Size4KiB>> for AnyPage {
+    #[inline]
+    fn from(page: Page<Size4KiB>) -> Self {
+        AnyPage {
+            start_address: page.start_address(),
+            size: PageSizeKind::Size4KiB,
+        }
+    }
+}
+
+impl From<Page<Size2MiB>> for AnyPage {
+    #[inline]
+    fn from(page: Page<Size2MiB>) -> Self {
+        AnyPage {
+            start_address: page.start_address(),
+            size: PageSizeKind::Size2MiB,
+        }
+    }
+}
+
+impl From<Page<Size1GiB>> for AnyPage {
+    #[inline]
+    fn from(page: Page<Size1GiB>) -> Self {
+        AnyPage {
+            start_address: page.start_address(),
+            size: PageSizeKind::Size1GiB,
+        }
+    }
+}
+
+impl<S: PageSize> TryFrom<AnyPage> for Page<S> {
+    type Error = PageSizeMismatch;
+
+    #[inline]
+    fn try_from(page: AnyPage) -> Result<Self, Self::Error> {
+        if page.size() != S::SIZE {
+            return Err(PageSizeMismatch);
+        }
+        Ok(Page {
+            start_address: page.start_address,
+            size: PhantomData,
+        })
+    }
+}
+
 /// A virtual memory page.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(C)]
@@ -63,15 +203,18 @@ impl<S: PageSize> Page<S> {
     /// The page size in bytes.
     pub const SIZE: u64 = S::SIZE;
 
-    /// Returns the page that starts at the given virtual address.
-    ///
-    /// Returns an error if the address is not correctly aligned (i.e. is not a valid page start).
-    #[inline]
-    pub fn from_start_address(address: VirtAddr) -> Result<Self, AddressNotAligned> {
-        if !address.is_aligned(S::SIZE) {
-            return Err(AddressNotAligned);
+    const_fn! {
+        /// Returns the page that starts at the given virtual address.
+        ///
+        /// Returns an error if the address is not correctly aligned (i.e. is not a valid page start).
+        /// The error contains the address and the page size.
+        #[inline]
+        pub fn from_start_address(address: VirtAddr) -> Result<Self, AddressNotAligned> {
+            if address.as_u64() & (S::SIZE - 1) != 0 {
+                return Err(AddressNotAligned::new(address.as_u64(), S::SIZE));
+            }
+            Ok(Page::containing_address(address))
         }
-        Ok(Page::containing_address(address))
     }
 
     const_fn! {
@@ -89,12 +232,14 @@ impl<S: PageSize> Page<S> {
         }
     }
 
-    /// Returns the page that contains the given virtual address.
-    #[inline]
-    pub fn containing_address(address: VirtAddr) -> Self {
-        Page {
-            start_address: address.align_down(S::SIZE),
-            size: PhantomData,
+    const_fn! {
+        /// Returns the page that contains the given virtual address.
+        #[inline]
+        pub fn containing_address(address: VirtAddr) -> Self {
+            Page {
+                start_address: VirtAddr::new_truncate(align_down(address.as_u64(), S::SIZE)),
+                size: PhantomData,
+            }
         }
     }
 
@@ -130,8 +275,18 @@ impl<S: PageSize> Page<S> {
         }
     }
 
+    const_fn! {
+        /// Returns the page table index of this page at the given level.
+        #[inline]
+        pub fn page_table_index(self, level: PageTableLevel) -> PageTableIndex {
+            self.start_address().page_table_index(level)
+        }
+    }
+
     const_fn! {
         /// Returns a range of pages, exclusive `end`.
+        ///
+        /// With the `step_trait` feature, the native `start..end` syntax can be used instead.
         #[inline]
         pub fn range(start: Self, end: Self) -> PageRange<S> {
             PageRange { start, end }
@@ -140,6 +295,8 @@ impl<S: PageSize> Page<S> {
 
     const_fn! {
         /// Returns a range of pages, inclusive `end`.
+        ///
+        /// With the `step_trait` feature, the native `start..=end` syntax can be used instead.
         #[inline]
         pub fn range_inclusive(start: Self, end: Self) -> PageRangeInclusive<S> {
             PageRangeInclusive { start, end }
@@ -147,6 +304,38 @@ impl<S: PageSize> Page<S> {
     }
 }
 
+impl<S: PageSize> Page<S> {
+    /// Returns the next page, skipping the non-canonical hole of the address space.
+    ///
+    /// Returns `None` if this is the last page of the address space.
+    #[inline]
+    fn checked_next(self) -> Option<Self> {
+        let start_address = VirtAddr::forward_checked_u64(self.start_address, S::SIZE)?;
+        Some(Page {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    /// Returns the previous page, skipping the non-canonical hole of the address space.
+    ///
+    /// Returns `None` if this is the first page of the address space.
+    #[inline]
+    fn checked_prev(self) -> Option<Self> {
+        let start_address = VirtAddr::backward_checked_u64(self.start_address, S::SIZE)?;
+        Some(Page {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    /// Returns the number of pages from `start` to `end`, or zero if `end` is before `start`.
+    #[inline]
+    fn pages_between(start: Self, end: Self) -> u64 {
+        VirtAddr::steps_between_u64(&start.start_address, &end.start_address).unwrap_or(0) / S::SIZE
+    }
+}
+
 impl<S: NotGiantPageSize> Page<S> {
     const_fn! {
         /// Returns the level 2 page table index of this page.
@@ -159,6 +348,9 @@ impl<S: NotGiantPageSize> Page<S> {
 
 impl Page<Size1GiB> {
     /// Returns the 1GiB memory page with the specified page table indices.
+    ///
+    /// The upper bits of the address are sign extended from bit 47, so a `p4_index` of 256
+    /// or above yields a page in the higher half.
     #[inline]
     pub fn from_page_table_indices_1gib(
         p4_index: PageTableIndex,
@@ -175,6 +367,9 @@ impl Page<Size1GiB> {
 
 impl Page<Size2MiB> {
     /// Returns the 2MiB memory page with the specified page table indices.
+    ///
+    /// The upper bits of the address are sign extended from bit 47, so a `p4_index` of 256
+    /// or above yields a page in the higher half.
     #[inline]
     pub fn from_page_table_indices_2mib(
         p4_index: PageTableIndex,
@@ -193,6 +388,10 @@ impl Page<Size2MiB> {
 
 impl Page<Size4KiB> {
     /// Returns the 4KiB memory page with the specified page table indices.
+    ///
+    /// The upper bits of the address are sign extended from bit 47, so a `p4_index` of 256
+    /// or above yields a page in the higher half. See `from_page_table_indices_2mib` and
+    /// `from_page_table_indices_1gib` for huge pages.
     #[inline]
     pub fn from_page_table_indices(
         p4_index: PageTableIndex,
@@ -237,6 +436,36 @@ impl<S: PageSize> Add<u64> for Page<S> {
     }
 }
 
+/// Stepping advances by the page size and skips the non-canonical hole of the address space.
+#[cfg(feature = "step_trait")]
+impl<S: PageSize> Step for Page<S> {
+    #[inline]
+    fn steps_between(start: &Self, end: &Self) -> Option<usize> {
+        let bytes = VirtAddr::steps_between_u64(&start.start_address, &end.start_address)?;
+        usize::try_from(bytes / S::SIZE).ok()
+    }
+
+    #[inline]
+    fn forward_checked(start: Self, count: usize) -> Option<Self> {
+        let bytes = u64::try_from(count).ok()?.checked_mul(S::SIZE)?;
+        let start_address = VirtAddr::forward_checked_u64(start.start_address, bytes)?;
+        Some(Page {
+            start_address,
+            size: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn backward_checked(start: Self, count: usize) -> Option<Self> {
+        let bytes = u64::try_from(count).ok()?.checked_mul(S::SIZE)?;
+        let start_address = VirtAddr::backward_checked_u64(start.start_address, bytes)?;
+        Some(Page {
+            start_address,
+            size: PhantomData,
+        })
+    }
+}
+
 impl<S: PageSize> AddAssign<u64> for Page<S> {
     #[inline]
     fn add_assign(&mut self, rhs: u64) {
@@ -283,6 +512,39 @@ impl<S: PageSize> PageRange<S> {
     pub fn is_empty(&self) -> bool {
         self.start >= self.end
     }
+
+    /// Returns the number of pages in the range.
+    #[inline]
+    pub(crate) fn len_u64(&self) -> u64 {
+        Page::pages_between(self.start, self.end)
+    }
+
+    /// Returns whether the given page is part of the range.
+    #[inline]
+    pub fn contains(&self, page: Page<S>) -> bool {
+        self.start <= page && page < self.end
+    }
+
+    /// Returns whether this range and the given range have at least one page in common.
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Returns the range of pages that are part of both this range and the given range, or
+    /// `None` if the ranges don't overlap.
+    #[inline]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let intersection = PageRange {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+        if intersection.is_empty() {
+            None
+        } else {
+            Some(intersection)
+        }
+    }
 }
 
 impl<S: PageSize> Iterator for PageRange<S> {
@@ -298,8 +560,43 @@ impl<S: PageSize> Iterator for PageRange<S> {
             None
         }
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.len_u64()) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        usize::try_from(self.len_u64()).unwrap()
+    }
+
+    #[inline]
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl<S: PageSize> DoubleEndedIterator for PageRange<S> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start < self.end {
+            self.end = self.end.checked_prev().unwrap();
+            Some(self.end)
+        } else {
+            None
+        }
+    }
 }
 
+#[cfg(target_pointer_width = "64")]
+impl<S: PageSize> ExactSizeIterator for PageRange<S> {}
+
+impl<S: PageSize> FusedIterator for PageRange<S> {}
+
 impl PageRange<Size2MiB> {
     /// Converts the range of 2MiB pages to a range of 4KiB pages.
     #[inline]
@@ -336,6 +633,43 @@ impl<S: PageSize> PageRangeInclusive<S> {
     pub fn is_empty(&self) -> bool {
         self.start > self.end
     }
+
+    /// Returns the number of pages in the range.
+    #[inline]
+    fn len_u64(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            Page::pages_between(self.start, self.end) + 1
+        }
+    }
+
+    /// Returns whether the given page is part of the range.
+    #[inline]
+    pub fn contains(&self, page: Page<S>) -> bool {
+        self.start <= page && page <= self.end
+    }
+
+    /// Returns whether this range and the given range have at least one page in common.
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Returns the range of pages that are part of both this range and the given range, or
+    /// `None` if the ranges don't overlap.
+    #[inline]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let intersection = PageRangeInclusive {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+        if intersection.is_empty() {
+            None
+        } else {
+            Some(intersection)
+        }
+    }
 }
 
 impl<S: PageSize> Iterator for PageRangeInclusive<S> {
@@ -343,16 +677,68 @@ impl<S: PageSize> Iterator for PageRangeInclusive<S> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.start <= self.end {
+        if self.start < self.end {
             let page = self.start;
-            self.start += 1;
+            self.start = page.checked_next().unwrap();
+            Some(page)
+        } else if self.start == self.end {
+            // make the range empty without stepping past the ends of the address space
+            let page = self.start;
+            match page.checked_next() {
+                Some(next) => self.start = next,
+                None => self.end = page.checked_prev().unwrap(),
+            }
             Some(page)
         } else {
             None
         }
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.len_u64()) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+
+    #[inline]
+    fn count(self) -> usize {
+        usize::try_from(self.len_u64()).unwrap()
+    }
+
+    #[inline]
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
 }
 
+impl<S: PageSize> DoubleEndedIterator for PageRangeInclusive<S> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start < self.end {
+            let page = self.end;
+            self.end = page.checked_prev().unwrap();
+            Some(page)
+        } else if self.start == self.end {
+            // make the range empty without stepping past the ends of the address space
+            let page = self.end;
+            match page.checked_prev() {
+                Some(prev) => self.end = prev,
+                None => self.start = page.checked_next().unwrap(),
+            }
+            Some(page)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl<S: PageSize> ExactSizeIterator for PageRangeInclusive<S> {}
+
+impl<S: PageSize> FusedIterator for PageRangeInclusive<S> {}
+
 impl<S: PageSize> fmt::Debug for PageRangeInclusive<S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("PageRangeInclusive")
@@ -363,12 +749,50 @@ impl<S: PageSize> fmt::Debug for PageRangeInclusive<S> {
 }
 
 /// The given address was not sufficiently aligned.
-#[derive(Debug)]
-pub struct AddressNotAligned;
+///
+/// Contains the offending address and the required alignment, i.e. the page size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressNotAligned {
+    address: u64,
+    alignment: u64,
+}
+
+impl AddressNotAligned {
+    #[inline]
+    pub(crate) const fn new(address: u64, alignment: u64) -> Self {
+        AddressNotAligned { address, alignment }
+    }
+
+    /// Returns the address that was not aligned as a raw `u64`.
+    #[inline]
+    pub const fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the required alignment in bytes.
+    #[inline]
+    pub const fn alignment(&self) -> u64 {
+        self.alignment
+    }
+}
 
 impl fmt::Display for AddressNotAligned {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "the given address was not sufficiently aligned")
+        write!(
+            f,
+            "the address {:#x} is not aligned to {:#x} bytes",
+            self.address, self.alignment
+        )
+    }
+}
+
+/// The size of a page or frame did not match the expected size.
+#[derive(Debug)]
+pub struct PageSizeMismatch;
+
+impl fmt::Display for PageSizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the page size did not match the expected size")
     }
 }
 
@@ -376,6 +800,175 @@ impl fmt::Display for AddressNotAligned {
 mod tests {
     use super::*;
 
+    fn check_inclusive_range_at_top<S: PageSize>() {
+        let last: Page<S> = Page::containing_address(VirtAddr::new(0xffff_ffff_ffff_ffff));
+        let range = Page::range_inclusive(last - 2, last);
+        assert_eq!(range.collect::<Vec<_>>(), [last - 2, last - 1, last]);
+        assert_eq!(range.rev().collect::<Vec<_>>(), [last, last - 1, last - 2]);
+        assert_eq!(range.len(), 3);
+
+        let mut range = Page::range_inclusive(last, last);
+        assert_eq!(range.next(), Some(last));
+        assert_eq!(range.next(), None);
+        assert_eq!(range.next_back(), None);
+        assert!(range.is_empty());
+
+        let mut empty = Page::range_inclusive(last, last - 1);
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.next(), None);
+        assert_eq!(empty.next_back(), None);
+    }
+
+    #[test]
+    pub fn test_inclusive_range_at_top() {
+        check_inclusive_range_at_top::<Size4KiB>();
+        check_inclusive_range_at_top::<Size2MiB>();
+        check_inclusive_range_at_top::<Size1GiB>();
+    }
+
+    #[test]
+    pub fn test_address_not_aligned() {
+        let addr = VirtAddr::new(0x20_1000);
+        assert!(Page::<Size4KiB>::from_start_address(addr).is_ok());
+        let err = Page::<Size2MiB>::from_start_address(addr).unwrap_err();
+        assert_eq!(err.address(), 0x20_1000);
+        assert_eq!(err.alignment(), Size2MiB::SIZE);
+        let err = AnyPage::from_start_address(addr, PageSizeKind::Size1GiB).unwrap_err();
+        assert_eq!(err.alignment(), Size1GiB::SIZE);
+    }
+
+    #[test]
+    pub fn test_any_page_conversions() {
+        let page: Page<Size2MiB> = Page::containing_address(VirtAddr::new(0x4020_0000));
+        let any = AnyPage::from(page);
+        assert_eq!(any.size_kind(), PageSizeKind::Size2MiB);
+        assert_eq!(any.size(), Size2MiB::SIZE);
+        assert_eq!(Page::<Size2MiB>::try_from(any).ok(), Some(page));
+        assert!(Page::<Size4KiB>::try_from(any).is_err());
+        assert_eq!(
+            AnyPage::containing_address(VirtAddr::new(0x4020_1234), PageSizeKind::Size2MiB),
+            any
+        );
+        assert!(
+            AnyPage::from_start_address(VirtAddr::new(0x1000), PageSizeKind::Size2MiB).is_err()
+        );
+        assert_eq!(
+            PageSizeKind::from_size(Size1GiB::SIZE),
+            Some(PageSizeKind::Size1GiB)
+        );
+    }
+
+    #[test]
+    pub fn test_page_range_helpers() {
+        let page = |addr| Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+
+        let range = Page::range(page(0x1000), page(0x5000));
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.count(), 4);
+        assert_eq!(range.last(), Some(page(0x4000)));
+        assert!(range.contains(page(0x4000)));
+        assert!(!range.contains(page(0x5000)));
+
+        let other = Page::range(page(0x4000), page(0x8000));
+        assert!(range.overlaps(&other));
+        assert_eq!(
+            range.intersection(&other),
+            Some(Page::range(page(0x4000), page(0x5000)))
+        );
+        let disjoint = Page::range(page(0x5000), page(0x8000));
+        assert!(!range.overlaps(&disjoint));
+        assert_eq!(range.intersection(&disjoint), None);
+
+        // ranges spanning the non-canonical hole
+        let range = Page::range(page(0x7fff_ffff_e000), page(0xffff_8000_0000_1000));
+        assert_eq!(range.len(), 3);
+        assert_eq!(range.collect::<Vec<_>>().len(), 3);
+        assert_eq!(
+            range.rev().collect::<Vec<_>>(),
+            [
+                page(0xffff_8000_0000_0000),
+                page(0x7fff_ffff_f000),
+                page(0x7fff_ffff_e000)
+            ]
+        );
+
+        // inclusive ranges at the ends of the address space
+        let last = page(0xffff_ffff_ffff_f000);
+        let mut range = Page::range_inclusive(last - 1, last);
+        assert_eq!(range.len(), 2);
+        assert_eq!(range.next(), Some(last - 1));
+        assert_eq!(range.next(), Some(last));
+        assert_eq!(range.next(), None);
+        let mut range = Page::range_inclusive(page(0), page(0x1000));
+        assert_eq!(range.next_back(), Some(page(0x1000)));
+        assert_eq!(range.next_back(), Some(page(0)));
+        assert_eq!(range.next_back(), None);
+        assert!(range.is_empty());
+    }
+
+    #[test]
+    pub fn test_from_page_table_indices() {
+        let p4 = PageTableIndex::new(511);
+        let p3 = PageTableIndex::new(3);
+        let p2 = PageTableIndex::new(42);
+        let p1 = PageTableIndex::new(7);
+
+        let page = Page::from_page_table_indices(p4, p3, p2, p1);
+        assert_eq!(page.start_address(), VirtAddr::new(0xffff_ff80_c540_7000));
+        assert_eq!(
+            (
+                page.p4_index(),
+                page.p3_index(),
+                page.p2_index(),
+                page.p1_index()
+            ),
+            (p4, p3, p2, p1)
+        );
+
+        let page = Page::from_page_table_indices_2mib(p4, p3, p2);
+        assert_eq!(page.start_address(), VirtAddr::new(0xffff_ff80_c540_0000));
+        assert_eq!(
+            (page.p4_index(), page.p3_index(), page.p2_index()),
+            (p4, p3, p2)
+        );
+
+        let page = Page::from_page_table_indices_1gib(p4, p3);
+        assert_eq!(page.start_address(), VirtAddr::new(0xffff_ff80_c000_0000));
+        assert_eq!((page.p4_index(), page.p3_index()), (p4, p3));
+    }
+
+    #[cfg(feature = "step_trait")]
+    #[test]
+    pub fn test_page_step() {
+        let start: Page<Size2MiB> = Page::containing_address(VirtAddr::new(0x4000_0000));
+        let end = Page::containing_address(VirtAddr::new(0x4100_0000));
+        assert_eq!((start..end).count(), 8);
+        assert_eq!(
+            (start..end).step_by(2).nth(1),
+            Some(Page::containing_address(VirtAddr::new(0x4040_0000)))
+        );
+
+        // stepping skips the non-canonical hole
+        let last_lower: Page = Page::containing_address(VirtAddr::new(0x7fff_ffff_f000));
+        let first_higher = Page::containing_address(VirtAddr::new(0xffff_8000_0000_0000));
+        assert_eq!(Step::forward_checked(last_lower, 1), Some(first_higher));
+        assert_eq!(Step::backward_checked(first_higher, 1), Some(last_lower));
+        assert_eq!((last_lower..=first_higher).count(), 2);
+
+        let last: Page = Page::containing_address(VirtAddr::new(0xffff_ffff_ffff_f000));
+        assert_eq!(Step::forward_checked(last, 1), None);
+    }
+
+    #[cfg(feature = "const_fn")]
+    #[test]
+    pub fn test_const_page() {
+        const PAGE: Page = Page::containing_address(VirtAddr::new(0xffff_8000_0000_1234));
+        const RANGE: PageRange = Page::range(PAGE, PAGE);
+
+        assert_eq!(PAGE.start_address(), VirtAddr::new(0xffff_8000_0000_1000));
+        assert!(RANGE.is_empty());
+    }
+
     #[test]
     pub fn test_page_ranges() {
         let page_size = Size4KiB::SIZE;
@@ -1,10 +1,17 @@
 //! Abstractions for page tables and page table entries.
 
+use core::convert::TryFrom;
 use core::fmt;
-use core::ops::{Index, IndexMut};
+use core::ops::{Index, IndexMut, RangeInclusive};
+use core::ptr;
+#[cfg(target_arch = "x86_64")]
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::{compiler_fence, Ordering};
 
 use super::{PageSize, PhysFrame, Size4KiB};
-use crate::addr::PhysAddr;
+#[cfg(feature = "instructions")]
+use crate::addr::PhysAddrTooLarge;
+use crate::addr::{PhysAddr, VirtAddr};
 
 use bitflags::bitflags;
 
@@ -38,6 +45,19 @@ impl PageTableEntry {
         self.entry == 0
     }
 
+    /// Reads the entry with a single volatile read and returns a copy of it.
+    ///
+    /// The CPU updates the `ACCESSED` and `DIRTY` flags of active page tables on its own, even
+    /// while only a shared reference to the table exists. Reading the entry once through this
+    /// method ensures that the flags and the address of the returned copy are consistent and
+    /// that the compiler doesn't merge or omit reads of the entry.
+    #[inline]
+    pub fn read_volatile(&self) -> PageTableEntry {
+        PageTableEntry {
+            entry: unsafe { ptr::read_volatile(&self.entry) },
+        }
+    }
+
     /// Sets this entry to zero.
     #[inline]
     pub fn set_unused(&mut self) {
@@ -53,7 +73,7 @@ impl PageTableEntry {
     /// Returns the physical address mapped by this entry, might be zero.
     #[inline]
     pub fn addr(&self) -> PhysAddr {
-        PhysAddr::new(self.entry & 0x000f_ffff_ffff_f000)
+        PhysAddr::new(self.entry & ADDRESS_MASK)
     }
 
     /// Returns the physical frame mapped by this entry.
@@ -65,13 +85,7 @@ impl PageTableEntry {
     ///    `addr` function must be used)
     #[inline]
     pub fn frame(&self) -> Result<PhysFrame, FrameError> {
-        if !self.flags().contains(PageTableFlags::PRESENT) {
-            Err(FrameError::FrameNotPresent)
-        } else if self.flags().contains(PageTableFlags::HUGE_PAGE) {
-            Err(FrameError::HugeFrame)
-        } else {
-            Ok(PhysFrame::containing_address(self.addr()))
-        }
+        self.frame_with_mask(0)
     }
 
     /// Map the entry to the specified physical address with the specified flags.
@@ -81,6 +95,22 @@ impl PageTableEntry {
         self.entry = (addr.as_u64()) | flags.bits();
     }
 
+    /// Map the entry to the specified physical address with the specified flags, after checking
+    /// that the address is supported by the current CPU (see [`PhysAddr::try_new_checked`]).
+    ///
+    /// The entry is left unchanged if the address is too large.
+    #[cfg(feature = "instructions")]
+    #[inline]
+    pub fn set_addr_checked(
+        &mut self,
+        addr: PhysAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), PhysAddrTooLarge> {
+        let addr = PhysAddr::try_new_checked(addr.as_u64())?;
+        self.set_addr(addr, flags);
+        Ok(())
+    }
+
     /// Map the entry to the specified physical frame with the specified flags.
     #[inline]
     pub fn set_frame(&mut self, frame: PhysFrame, flags: PageTableFlags) {
@@ -88,13 +118,254 @@ impl PageTableEntry {
         self.set_addr(frame.start_address(), flags)
     }
 
+    /// Returns the physical address mapped by this entry with the bits in `mask` cleared.
+    ///
+    /// With memory encryption (e.g. AMD SEV), the encryption bit is part of the address field
+    /// of the entry, so [`addr`](Self::addr) returns an invalid address for encrypted
+    /// mappings. Passing the encryption bit as `mask` strips it.
+    #[inline]
+    pub fn addr_with_mask(&self, mask: u64) -> PhysAddr {
+        PhysAddr::new(self.entry & ADDRESS_MASK & !mask)
+    }
+
+    /// Returns the physical frame mapped by this entry with the bits in `mask` cleared from the
+    /// address.
+    ///
+    /// Returns the same errors as [`frame`](Self::frame). See
+    /// [`addr_with_mask`](Self::addr_with_mask) for the purpose of the mask.
+    #[inline]
+    pub fn frame_with_mask(&self, mask: u64) -> Result<PhysFrame, FrameError> {
+        if !self.flags().contains(PageTableFlags::PRESENT) {
+            Err(FrameError::FrameNotPresent)
+        } else if self.flags().contains(PageTableFlags::HUGE_PAGE) {
+            Err(FrameError::HugeFrame)
+        } else {
+            Ok(PhysFrame::containing_address(self.addr_with_mask(mask)))
+        }
+    }
+
+    /// Map the entry to the specified physical address with the specified flags and
+    /// additionally set the bits in `encryption_mask` in the address field.
+    ///
+    /// This is used for mapping encrypted memory with AMD SEV, where `encryption_mask` is the
+    /// encryption bit (the "C-bit") reported by CPUID leaf 0x8000001F. Passing a mask of `0`
+    /// is equivalent to [`set_addr`](Self::set_addr).
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `encryption_mask` has bits set outside of the address field (bits 12 to 51).
+    #[inline]
+    pub fn set_addr_encrypted(
+        &mut self,
+        addr: PhysAddr,
+        flags: PageTableFlags,
+        encryption_mask: u64,
+    ) {
+        assert_eq!(
+            encryption_mask & !ADDRESS_MASK,
+            0,
+            "encryption mask must be part of the address field"
+        );
+        self.set_addr(addr, flags);
+        self.entry |= encryption_mask;
+    }
+
     /// Sets the flags of this entry.
     #[inline]
     pub fn set_flags(&mut self, flags: PageTableFlags) {
         self.entry = self.addr().as_u64() | flags.bits();
     }
+
+    /// Sets the flags of this entry like [`set_flags`](Self::set_flags), but keeps the
+    /// OS-available bits that are currently set.
+    ///
+    /// Bits 59 to 62 are replaced instead if `flags` contains a protection key.
+    #[inline]
+    pub(crate) fn set_flags_keep_os_bits(&mut self, flags: PageTableFlags) {
+        let mut os_bits = self.entry & (OS_BITS_LOW_MASK | OS_BITS_HIGH_MASK);
+        if flags.bits() & PROTECTION_KEY_MASK != 0 {
+            os_bits &= !PROTECTION_KEY_MASK;
+        }
+        self.set_flags(flags | PageTableFlags::from_bits_truncate(os_bits));
+    }
+
+    /// Atomically clears the given flags and returns the flags of the entry before the update.
+    ///
+    /// The CPU sets the `ACCESSED` and `DIRTY` flags on its own while walking the page tables,
+    /// so a non-atomic read-modify-write could lose such a concurrent update. This method
+    /// performs the update through a single locked instruction instead.
+    ///
+    /// On other architectures than x86_64, where the entries aren't used by the CPU, the flags
+    /// are cleared through a plain read-modify-write.
+    #[inline]
+    pub fn clear_flags_atomic(&mut self, flags: PageTableFlags) -> PageTableFlags {
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: the pointer comes from a unique reference, so it is valid and aligned
+        let old = unsafe { Self::as_atomic(self) }.fetch_and(!flags.bits(), Ordering::SeqCst);
+        #[cfg(not(target_arch = "x86_64"))]
+        let old = {
+            let old = self.entry;
+            self.entry = old & !flags.bits();
+            old
+        };
+        PageTableFlags::from_bits_truncate(old)
+    }
+
+    /// Atomically loads the raw value of the given entry.
+    ///
+    /// Unlike [`read_volatile`](Self::read_volatile), this synchronizes with the atomic
+    /// updates of other CPUs, e.g. through [`compare_exchange`](Self::compare_exchange).
+    ///
+    /// ## Safety
+    ///
+    /// The entry must be valid for reads and 8-byte aligned, and it must only be accessed
+    /// concurrently through these atomic functions or by the CPU's page table walks.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub unsafe fn load_volatile(entry: *const PageTableEntry) -> u64 {
+        Self::as_atomic(entry).load(Ordering::SeqCst)
+    }
+
+    /// Atomically stores the given raw value in the given entry.
+    ///
+    /// The entry is passed as a raw pointer, so that multiple CPUs can update the same table
+    /// concurrently. Like all changes of a mapping, the TLB must be flushed afterwards if
+    /// the entry was present before.
+    ///
+    /// ## Safety
+    ///
+    /// The entry must be valid for writes and 8-byte aligned, and it must only be accessed
+    /// concurrently through these atomic functions or by the CPU's page table walks.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub unsafe fn store_volatile(entry: *mut PageTableEntry, value: u64) {
+        Self::as_atomic(entry).store(value, Ordering::SeqCst)
+    }
+
+    /// Atomically replaces the raw value of the given entry with `new` if it is equal to
+    /// `current`.
+    ///
+    /// Returns the previous value, wrapped in `Ok` if it was replaced and in `Err` otherwise.
+    /// This allows CPUs that fault on the same page at the same time to populate the entry
+    /// only once: each CPU prepares a frame and tries to install it, and the losers free
+    /// their frame and use the entry of the winner instead.
+    ///
+    /// ```
+    /// use x86_64::structures::paging::page_table::{PageTableEntry, PageTableFlags};
+    ///
+    /// let mut entry = PageTableEntry::new();
+    /// let ptr: *mut PageTableEntry = &mut entry;
+    /// let new = 0x5000 | (PageTableFlags::PRESENT | PageTableFlags::WRITABLE).bits();
+    /// assert_eq!(unsafe { PageTableEntry::compare_exchange(ptr, 0, new) }, Ok(0));
+    /// // the entry is already populated
+    /// assert_eq!(unsafe { PageTableEntry::compare_exchange(ptr, 0, 0x6000 | new) }, Err(new));
+    /// ```
+    ///
+    /// ## Safety
+    ///
+    /// The entry must be valid for reads and writes and 8-byte aligned, and it must only be
+    /// accessed concurrently through these atomic functions or by the CPU's page table walks.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub unsafe fn compare_exchange(
+        entry: *mut PageTableEntry,
+        current: u64,
+        new: u64,
+    ) -> Result<u64, u64> {
+        Self::as_atomic(entry).compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+    }
+
+    /// ## Safety
+    ///
+    /// The entry must be valid for the lifetime `'a`, 8-byte aligned and only be accessed
+    /// atomically during that lifetime.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    unsafe fn as_atomic<'a>(entry: *const PageTableEntry) -> &'a AtomicU64 {
+        // SAFETY: `PageTableEntry` is a transparent wrapper around an `u64`, which has the same
+        // size as `AtomicU64`. The caller guarantees the validity and alignment of the entry.
+        // `AtomicU64` allows writes through shared references, so the writes through the
+        // returned reference don't require a unique borrow of the entry.
+        &*(entry as *const AtomicU64)
+    }
+
+    /// Returns the low OS-available bits of this entry (bits 9 to 11).
+    ///
+    /// These bits are ignored by the CPU and can be used freely by the operating system. They
+    /// are the same bits as the `BIT_9` to `BIT_11` flags.
+    #[inline]
+    pub const fn os_bits_low(&self) -> u8 {
+        ((self.entry & OS_BITS_LOW_MASK) >> 9) as u8
+    }
+
+    /// Sets the low OS-available bits of this entry (bits 9 to 11) without changing the
+    /// address or any other flags.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `bits` does not fit into 3 bits.
+    #[inline]
+    pub fn set_os_bits_low(&mut self, bits: u8) {
+        assert!(bits < 1 << 3, "OS bits must fit into 3 bits");
+        self.entry = (self.entry & !OS_BITS_LOW_MASK) | (u64::from(bits) << 9);
+    }
+
+    /// Returns the high OS-available bits of this entry (bits 52 to 62).
+    ///
+    /// These bits are ignored by the CPU and can be used freely by the operating system. They
+    /// are the same bits as the `BIT_52` to `BIT_62` flags. The `NO_EXECUTE` bit is not included.
+    ///
+    /// Note that if protection keys are enabled (`CR4.PKE` or `CR4.PKS`), bits 59 to 62 of a
+    /// leaf entry are interpreted as the protection key of the page, so only the lower 7 bits
+    /// of the returned value are available to the operating system in that case.
+    #[inline]
+    pub const fn os_bits_high(&self) -> u16 {
+        ((self.entry & OS_BITS_HIGH_MASK) >> 52) as u16
+    }
+
+    /// Sets the high OS-available bits of this entry (bits 52 to 62) without changing the
+    /// address, the `NO_EXECUTE` bit, or any other flags.
+    ///
+    /// See [`os_bits_high`](Self::os_bits_high) for the overlap with protection keys.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `bits` does not fit into 11 bits.
+    #[inline]
+    pub fn set_os_bits_high(&mut self, bits: u16) {
+        assert!(bits < 1 << 11, "OS bits must fit into 11 bits");
+        self.entry = (self.entry & !OS_BITS_HIGH_MASK) | (u64::from(bits) << 52);
+    }
+
+    /// Returns the protection key of this entry (bits 59 to 62).
+    ///
+    /// This is only meaningful for entries that map a page, i.e. level 1 entries and entries
+    /// with the `HUGE_PAGE` flag set. If protection keys are disabled (neither `CR4.PKE` nor
+    /// `CR4.PKS` is set) the CPU ignores these bits and they are available to the OS instead.
+    #[inline]
+    pub const fn protection_key(&self) -> ProtectionKey {
+        self.flags().protection_key()
+    }
+
+    /// Sets the protection key of this entry (bits 59 to 62) without changing the address or
+    /// any other flags.
+    ///
+    /// See [`protection_key`](Self::protection_key) for the entries this applies to.
+    #[inline]
+    pub fn set_protection_key(&mut self, key: ProtectionKey) {
+        self.entry = (self.entry & !PROTECTION_KEY_MASK) | (u64::from(key.0) << 59);
+    }
 }
 
+/// Bits 12 to 51 of a page table entry, which contain the physical address.
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Bits 9 to 11 of a page table entry, which are available to the OS.
+const OS_BITS_LOW_MASK: u64 = 0b111 << 9;
+/// Bits 52 to 62 of a page table entry, which are available to the OS.
+const OS_BITS_HIGH_MASK: u64 = 0x7ff << 52;
+/// Bits 59 to 62 of a page table entry, which contain the protection key of a mapped page.
+const PROTECTION_KEY_MASK: u64 = 0xf << 59;
+
 impl fmt::Debug for PageTableEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut f = f.debug_struct("PageTableEntry");
@@ -168,6 +439,155 @@ bitflags! {
     }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    PageTableFlags,
+    u64,
+    "valid page table flags",
+    |flags| flags.bits(),
+    |bits| PageTableFlags::from_bits(bits)
+);
+
+/// Presets for common kinds of mappings.
+///
+/// All presets that map non-executable memory set `NO_EXECUTE`, so the no-execute page
+/// protection feature has to be enabled in the EFER register when using them. None of the
+/// presets set `GLOBAL`, since that requires the `PAGE_GLOBAL` bit in CR4.
+impl PageTableFlags {
+    /// Returns the protection key stored in bits 59 to 62 of these flags.
+    ///
+    /// See [`PageTableEntry::protection_key`] for when the CPU interprets these bits.
+    #[inline]
+    pub const fn protection_key(self) -> ProtectionKey {
+        ProtectionKey(((self.bits() & PROTECTION_KEY_MASK) >> 59) as u8)
+    }
+
+    /// Returns these flags with bits 59 to 62 replaced by the given protection key.
+    ///
+    /// This can be used to map a page with a protection key in a single step:
+    ///
+    /// ```
+    /// use x86_64::structures::paging::{PageTableFlags, ProtectionKey};
+    ///
+    /// let flags = PageTableFlags::user_data().with_protection_key(ProtectionKey::new(3));
+    /// assert_eq!(flags.protection_key(), ProtectionKey::new(3));
+    /// ```
+    #[inline]
+    pub const fn with_protection_key(self, key: ProtectionKey) -> Self {
+        Self::from_bits_truncate((self.bits() & !PROTECTION_KEY_MASK) | ((key.0 as u64) << 59))
+    }
+
+    /// Returns whether bit 7 (`HUGE_PAGE`) is set.
+    ///
+    /// Note that this bit only marks a huge page in level 2 and level 3 entries. In level 1
+    /// entries, the same bit selects the PAT memory type instead, so code that walks page
+    /// tables should use [`for_level`](Self::for_level) to interpret it.
+    #[inline]
+    pub const fn is_huge_page(self) -> bool {
+        self.bits() & Self::HUGE_PAGE.bits() != 0
+    }
+
+    /// Interprets these flags as belonging to an entry of a table of the given level.
+    #[inline]
+    pub const fn for_level(self, level: PageTableLevel) -> LevelPageTableFlags {
+        LevelPageTableFlags { flags: self, level }
+    }
+
+    /// Flags for kernel code: present, read-only and executable.
+    #[inline]
+    pub fn kernel_code() -> Self {
+        Self::PRESENT
+    }
+
+    /// Flags for kernel data: present, writable and not executable.
+    #[inline]
+    pub fn kernel_data() -> Self {
+        Self::PRESENT | Self::WRITABLE | Self::NO_EXECUTE
+    }
+
+    /// Flags for kernel read-only data: present and not executable.
+    #[inline]
+    pub fn kernel_read_only() -> Self {
+        Self::PRESENT | Self::NO_EXECUTE
+    }
+
+    /// Flags for user code: present, accessible from userspace, read-only and executable.
+    #[inline]
+    pub fn user_code() -> Self {
+        Self::PRESENT | Self::USER_ACCESSIBLE
+    }
+
+    /// Flags for user data: present, accessible from userspace, writable and not executable.
+    #[inline]
+    pub fn user_data() -> Self {
+        Self::PRESENT | Self::USER_ACCESSIBLE | Self::WRITABLE | Self::NO_EXECUTE
+    }
+
+    /// Flags for user read-only data: present, accessible from userspace and not executable.
+    #[inline]
+    pub fn user_read_only() -> Self {
+        Self::PRESENT | Self::USER_ACCESSIBLE | Self::NO_EXECUTE
+    }
+
+    /// Flags for memory-mapped I/O: present, writable, not executable and uncached.
+    ///
+    /// Both `NO_CACHE` and `WRITE_THROUGH` are set, which selects the strong uncacheable (UC)
+    /// memory type with the default PAT configuration.
+    #[inline]
+    pub fn mmio() -> Self {
+        Self::PRESENT | Self::WRITABLE | Self::NO_EXECUTE | Self::NO_CACHE | Self::WRITE_THROUGH
+    }
+}
+
+/// The flags of an entry of a table of a known level, created by
+/// [`PageTableFlags::for_level`].
+///
+/// Bit 7 of a page table entry is overloaded: it is the `HUGE_PAGE` bit in level 2 and
+/// level 3 entries, reserved in level 4 entries, and the PAT bit in level 1
+/// entries. This type only interprets it as `HUGE_PAGE` where that is valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelPageTableFlags {
+    flags: PageTableFlags,
+    level: PageTableLevel,
+}
+
+impl LevelPageTableFlags {
+    /// Returns the raw flags.
+    #[inline]
+    pub const fn flags(self) -> PageTableFlags {
+        self.flags
+    }
+
+    /// Returns the level of the table that the entry belongs to.
+    #[inline]
+    pub const fn level(self) -> PageTableLevel {
+        self.level
+    }
+
+    /// Returns whether the entry maps a huge page instead of pointing to a page table.
+    ///
+    /// This is only the case for level 2 and level 3 entries with bit 7 set. For all other
+    /// levels, `false` is returned.
+    #[inline]
+    pub const fn contains_huge(self) -> bool {
+        matches!(self.level, PageTableLevel::Two | PageTableLevel::Three)
+            && self.flags.is_huge_page()
+    }
+
+    /// Returns the flags with the `HUGE_PAGE` bit removed.
+    ///
+    /// The bit is kept in level 1 entries, since it is the PAT bit there.
+    #[inline]
+    pub const fn without_huge(self) -> PageTableFlags {
+        match self.level {
+            PageTableLevel::One => self.flags,
+            _ => PageTableFlags::from_bits_truncate(
+                self.flags.bits() & !PageTableFlags::HUGE_PAGE.bits(),
+            ),
+        }
+    }
+}
+
 /// The number of entries in a page table.
 const ENTRY_COUNT: usize = 512;
 
@@ -180,15 +600,27 @@ const ENTRY_COUNT: usize = 512;
 ///
 /// Note that while this type implements [`Clone`], the users must be careful not to introduce
 /// mutable aliasing by using the cloned page tables.
+///
+/// The bulk updates [`zero`](PageTable::zero), [`clone_range_from`](PageTable::clone_range_from)
+/// and [`clone_from`](Clone::clone_from) can be used on tables that are part of a live page
+/// table hierarchy, e.g. one that is about to be activated. They write the entries with a
+/// single `write_bytes` or `copy_nonoverlapping` call followed by a compiler fence, so the
+/// writes can't be moved past later accesses such as the CR3 write that activates the table.
+/// If the table is part of the currently active hierarchy, the caller must flush the affected
+/// TLB entries afterwards, since the CPU might still use cached translations of the old
+/// entries.
 #[repr(align(4096))]
 #[repr(C)]
-#[derive(Clone)]
 pub struct PageTable {
     entries: [PageTableEntry; ENTRY_COUNT],
 }
 
 impl PageTable {
     /// Creates an empty page table.
+    ///
+    /// This function is `const`, so it can be used to initialize a `static`, e.g. for the
+    /// initial page tables of a kernel. Such tables end up in the zero-initialized `.bss`
+    /// section and are aligned to a frame boundary, so they don't need to be zeroed at runtime.
     #[cfg(feature = "const_fn")]
     #[inline]
     pub const fn new() -> Self {
@@ -209,11 +641,60 @@ impl PageTable {
     }
 
     /// Clears all entries.
+    ///
+    /// See the [type-level documentation](PageTable) for using this on live tables.
     #[inline]
     pub fn zero(&mut self) {
-        for entry in self.entries.iter_mut() {
-            entry.set_unused();
+        // SAFETY: the pointer comes from a mutable reference, so it is valid for writes of
+        // all entries, and a zeroed entry is a valid unused entry
+        unsafe { ptr::write_bytes(self.entries.as_mut_ptr(), 0, ENTRY_COUNT) };
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Copies the entries in the given index range from `other` into this table.
+    ///
+    /// This is useful for sharing the kernel part of an address space, e.g. by copying the
+    /// entries `256..=511` of the active level 4 table into a newly created one. The tables
+    /// the copied entries point to are shared, not copied.
+    ///
+    /// Nothing is copied if the range is empty, i.e. if its start is above its end. See the
+    /// [type-level documentation](PageTable) for using this on live tables.
+    #[inline]
+    pub fn clone_range_from(&mut self, other: &PageTable, range: RangeInclusive<PageTableIndex>) {
+        if range.is_empty() {
+            return;
         }
+        let start = usize::from(*range.start());
+        let count = usize::from(*range.end()) - start + 1;
+        // SAFETY: the indices are below 512 and the tables can't overlap, since `self` is a
+        // mutable reference
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.entries.as_ptr().add(start),
+                self.entries.as_mut_ptr().add(start),
+                count,
+            )
+        };
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Returns the entry that is responsible for the given virtual address, assuming that
+    /// this is a table of the given level.
+    ///
+    /// This is a shorthand for indexing with [`VirtAddr::page_table_index`], which avoids
+    /// picking the index accessor of the wrong level during a manual page table walk.
+    #[inline]
+    pub fn index_for(&self, addr: VirtAddr, level: PageTableLevel) -> &PageTableEntry {
+        &self[addr.page_table_index(level)]
+    }
+
+    /// Returns a mutable reference to the entry that is responsible for the given virtual
+    /// address, assuming that this is a table of the given level.
+    ///
+    /// See [`PageTable::index_for`].
+    #[inline]
+    pub fn index_for_mut(&mut self, addr: VirtAddr, level: PageTableLevel) -> &mut PageTableEntry {
+        &mut self[addr.page_table_index(level)]
     }
 
     /// Returns an iterator over the entries of the page table.
@@ -227,6 +708,86 @@ impl PageTable {
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut PageTableEntry> {
         self.entries.iter_mut()
     }
+
+    /// Returns an iterator over the present entries of the page table and their indices.
+    ///
+    /// An entry is present if its `PRESENT` flag is set, independent of its address, so an
+    /// entry mapping frame 0 is included. Entries that are not present are skipped even if
+    /// they have other flags or OS-specific data set (see [`PageTable::count_used`]).
+    #[inline]
+    pub fn iter_present(&self) -> impl Iterator<Item = (PageTableIndex, &PageTableEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.flags().contains(PageTableFlags::PRESENT))
+            .map(|(i, entry)| (PageTableIndex::new_truncate(i as u16), entry))
+    }
+
+    /// Returns an iterator that allows modifying the present entries of the page table,
+    /// together with their indices.
+    ///
+    /// See [`PageTable::iter_present`] for which entries are considered present.
+    #[inline]
+    pub fn iter_present_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (PageTableIndex, &mut PageTableEntry)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, entry)| entry.flags().contains(PageTableFlags::PRESENT))
+            .map(|(i, entry)| (PageTableIndex::new_truncate(i as u16), entry))
+    }
+
+    /// Returns the index of the first unused entry, or `None` if all entries are used.
+    ///
+    /// An entry is unused if it is zero (see [`PageTableEntry::is_unused`]). Entries that are
+    /// not present but have flags or OS-specific data set are considered used, since they
+    /// might store information such as swap locations.
+    #[inline]
+    pub fn next_free_index(&self) -> Option<PageTableIndex> {
+        self.entries
+            .iter()
+            .position(PageTableEntry::is_unused)
+            .map(|i| PageTableIndex::new_truncate(i as u16))
+    }
+
+    /// Returns the number of used entries, i.e. entries that are not zero.
+    ///
+    /// This includes entries that are not present (see [`PageTable::next_free_index`]).
+    #[inline]
+    pub fn count_used(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_unused())
+            .count()
+    }
+}
+
+impl Clone for PageTable {
+    #[inline]
+    fn clone(&self) -> Self {
+        PageTable {
+            entries: self.entries.clone(),
+        }
+    }
+
+    /// Copies all 512 entries of `source` into this table, without creating a temporary
+    /// copy of the table.
+    ///
+    /// See the [type-level documentation](PageTable) for using this on live tables.
+    #[inline]
+    fn clone_from(&mut self, source: &Self) {
+        // SAFETY: both tables have 512 entries and can't overlap, since `self` is a mutable
+        // reference
+        unsafe {
+            ptr::copy_nonoverlapping(
+                source.entries.as_ptr(),
+                self.entries.as_mut_ptr(),
+                ENTRY_COUNT,
+            )
+        };
+        compiler_fence(Ordering::SeqCst);
+    }
 }
 
 impl Index<usize> for PageTable {
@@ -283,11 +844,13 @@ impl fmt::Debug for PageTable {
 pub struct PageTableIndex(u16);
 
 impl PageTableIndex {
-    /// Creates a new index from the given `u16`. Panics if the given value is >=512.
-    #[inline]
-    pub fn new(index: u16) -> Self {
-        assert!(usize::from(index) < ENTRY_COUNT);
-        Self(index)
+    const_fn! {
+        /// Creates a new index from the given `u16`. Panics if the given value is >=512.
+        #[inline]
+        pub fn new(index: u16) -> Self {
+            assert!(index < ENTRY_COUNT as u16);
+            Self(index)
+        }
     }
 
     /// Creates a new index from the given `u16`. Throws away bits if the value is >=512.
@@ -295,8 +858,78 @@ impl PageTableIndex {
     pub const fn new_truncate(index: u16) -> Self {
         Self(index % ENTRY_COUNT as u16)
     }
+
+    /// Returns the index as `u16`. Same as the `From` implementation, but usable in `const`.
+    #[inline]
+    pub const fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the index as `u64`. Same as the `From` implementation, but usable in `const`.
+    #[inline]
+    pub const fn as_u64(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// Returns the index as `usize`. Same as the `From` implementation, but usable in `const`.
+    #[inline]
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Returns an iterator over all 512 indices of a page table, in ascending order.
+    #[inline]
+    pub fn iter() -> impl DoubleEndedIterator<Item = Self> + ExactSizeIterator + Clone {
+        (0..ENTRY_COUNT as u16).map(Self)
+    }
+
+    /// Adds `rhs` to this index, returning `None` if the result is >=512.
+    #[inline]
+    pub const fn checked_add(self, rhs: u16) -> Option<Self> {
+        match self.0.checked_add(rhs) {
+            Some(index) if index < ENTRY_COUNT as u16 => Some(Self(index)),
+            _ => None,
+        }
+    }
+
+    /// Subtracts `rhs` from this index, returning `None` if the result would be negative.
+    #[inline]
+    pub const fn checked_sub(self, rhs: u16) -> Option<Self> {
+        match self.0.checked_sub(rhs) {
+            Some(index) => Some(Self(index)),
+            None => None,
+        }
+    }
+
+    /// Adds `rhs` to this index, wrapping around at 512.
+    #[inline]
+    pub const fn wrapping_add(self, rhs: u16) -> Self {
+        Self::new_truncate(self.0.wrapping_add(rhs % ENTRY_COUNT as u16))
+    }
+
+    /// Subtracts `rhs` from this index, wrapping around at 0.
+    #[inline]
+    pub const fn wrapping_sub(self, rhs: u16) -> Self {
+        Self::new_truncate(
+            self.0
+                .wrapping_add(ENTRY_COUNT as u16 - rhs % ENTRY_COUNT as u16),
+        )
+    }
 }
 
+#[cfg(feature = "serde")]
+serde_via_int!(
+    PageTableIndex,
+    u16,
+    "a page table index less than 512",
+    |index| index.0,
+    |index| if index < ENTRY_COUNT as u16 {
+        Some(PageTableIndex(index))
+    } else {
+        None
+    }
+);
+
 impl From<PageTableIndex> for u16 {
     #[inline]
     fn from(index: PageTableIndex) -> Self {
@@ -325,6 +958,83 @@ impl From<PageTableIndex> for usize {
     }
 }
 
+impl TryFrom<u16> for PageTableIndex {
+    type Error = PageTableIndexOutOfRange;
+
+    #[inline]
+    fn try_from(index: u16) -> Result<Self, Self::Error> {
+        Self::try_from(u64::from(index))
+    }
+}
+
+impl TryFrom<u64> for PageTableIndex {
+    type Error = PageTableIndexOutOfRange;
+
+    #[inline]
+    fn try_from(index: u64) -> Result<Self, Self::Error> {
+        if index < ENTRY_COUNT as u64 {
+            Ok(Self(index as u16))
+        } else {
+            Err(PageTableIndexOutOfRange(index))
+        }
+    }
+}
+
+/// A passed value was not a valid page table index, i.e. it was >=512.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageTableIndexOutOfRange(u64);
+
+impl fmt::Display for PageTableIndexOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "page table index {} is out of range (must be < 512)",
+            self.0
+        )
+    }
+}
+
+/// A 4-bit protection key, stored in bits 59 to 62 of page table entries that map a page.
+///
+/// Guaranteed to only ever contain 0..16.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ProtectionKey(u8);
+
+impl ProtectionKey {
+    /// Creates a new protection key from the given `u8`. Panics if the given value is >=16.
+    #[inline]
+    pub fn new(key: u8) -> Self {
+        assert!(key < 16);
+        Self(key)
+    }
+
+    /// Creates a new protection key from the given `u8`. Throws away bits if the value is >=16.
+    #[inline]
+    pub const fn new_truncate(key: u8) -> Self {
+        Self(key % 16)
+    }
+
+    /// Returns the bit of the PKRU register that disables all data accesses to pages with
+    /// this key.
+    #[inline]
+    pub const fn access_disable_mask(self) -> u32 {
+        1 << (2 * self.0)
+    }
+
+    /// Returns the bit of the PKRU register that disables writes to pages with this key.
+    #[inline]
+    pub const fn write_disable_mask(self) -> u32 {
+        1 << (2 * self.0 + 1)
+    }
+}
+
+impl From<ProtectionKey> for u8 {
+    #[inline]
+    fn from(key: ProtectionKey) -> Self {
+        key.0
+    }
+}
+
 /// A 12-bit offset into a 4KiB Page.
 ///
 /// This type is returned by the `VirtAddr::page_offset` method.
@@ -334,11 +1044,31 @@ impl From<PageTableIndex> for usize {
 pub struct PageOffset(u16);
 
 impl PageOffset {
-    /// Creates a new offset from the given `u16`. Panics if the passed value is >=4096.
+    const_fn! {
+        /// Creates a new offset from the given `u16`. Panics if the passed value is >=4096.
+        #[inline]
+        pub fn new(offset: u16) -> Self {
+            assert!(offset < (1 << 12));
+            Self(offset)
+        }
+    }
+
+    /// Returns the offset as `u16`. Same as the `From` implementation, but usable in `const`.
+    #[inline]
+    pub const fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the offset as `u64`. Same as the `From` implementation, but usable in `const`.
+    #[inline]
+    pub const fn as_u64(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// Returns the offset as `usize`. Same as the `From` implementation, but usable in `const`.
     #[inline]
-    pub fn new(offset: u16) -> Self {
-        assert!(offset < (1 << 12));
-        Self(offset)
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
     }
 
     /// Creates a new offset from the given `u16`. Throws away bits if the value is >=4096.
@@ -346,6 +1076,36 @@ impl PageOffset {
     pub const fn new_truncate(offset: u16) -> Self {
         Self(offset % (1 << 12))
     }
+
+    /// Adds `rhs` to this offset, returning `None` if the result is >=4096.
+    #[inline]
+    pub const fn checked_add(self, rhs: u16) -> Option<Self> {
+        match self.0.checked_add(rhs) {
+            Some(offset) if offset < (1 << 12) => Some(Self(offset)),
+            _ => None,
+        }
+    }
+
+    /// Subtracts `rhs` from this offset, returning `None` if the result would be negative.
+    #[inline]
+    pub const fn checked_sub(self, rhs: u16) -> Option<Self> {
+        match self.0.checked_sub(rhs) {
+            Some(offset) => Some(Self(offset)),
+            None => None,
+        }
+    }
+
+    /// Adds `rhs` to this offset, wrapping around at 4096.
+    #[inline]
+    pub const fn wrapping_add(self, rhs: u16) -> Self {
+        Self::new_truncate(self.0.wrapping_add(rhs))
+    }
+
+    /// Subtracts `rhs` from this offset, wrapping around at 0.
+    #[inline]
+    pub const fn wrapping_sub(self, rhs: u16) -> Self {
+        Self::new_truncate(self.0.wrapping_sub(rhs))
+    }
 }
 
 impl From<PageOffset> for u16 {
@@ -375,3 +1135,373 @@ impl From<PageOffset> for usize {
         usize::from(offset.0)
     }
 }
+
+impl TryFrom<u16> for PageOffset {
+    type Error = PageOffsetOutOfRange;
+
+    #[inline]
+    fn try_from(offset: u16) -> Result<Self, Self::Error> {
+        Self::try_from(u64::from(offset))
+    }
+}
+
+impl TryFrom<u64> for PageOffset {
+    type Error = PageOffsetOutOfRange;
+
+    #[inline]
+    fn try_from(offset: u64) -> Result<Self, Self::Error> {
+        if offset < (1 << 12) {
+            Ok(Self(offset as u16))
+        } else {
+            Err(PageOffsetOutOfRange(offset))
+        }
+    }
+}
+
+/// A passed value was not a valid page offset, i.e. it was >=4096.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageOffsetOutOfRange(u64);
+
+impl fmt::Display for PageOffsetOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page offset {} is out of range (must be < 4096)", self.0)
+    }
+}
+
+/// A level in the page table hierarchy, from the level 1 table (which maps 4KiB pages) up
+/// to the level 4 table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageTableLevel {
+    /// The level 1 table, whose entries map 4KiB pages.
+    One = 1,
+    /// The level 2 table, whose entries map 2MiB pages or point to level 1 tables.
+    Two,
+    /// The level 3 table, whose entries map 1GiB pages or point to level 2 tables.
+    Three,
+    /// The level 4 table, whose entries point to level 3 tables.
+    Four,
+}
+
+impl PageTableLevel {
+    /// Returns the next lower level or `None` for level 1.
+    #[inline]
+    pub const fn next_lower_level(self) -> Option<Self> {
+        match self {
+            PageTableLevel::Four => Some(PageTableLevel::Three),
+            PageTableLevel::Three => Some(PageTableLevel::Two),
+            PageTableLevel::Two => Some(PageTableLevel::One),
+            PageTableLevel::One => None,
+        }
+    }
+
+    /// Returns the size of the virtual address space that is covered by a table of this level.
+    #[inline]
+    pub const fn table_address_space_alignment(self) -> u64 {
+        1u64 << (self as u8 * 9 + 12)
+    }
+
+    /// Returns the size of the virtual address space that is covered by a single entry of a
+    /// table of this level.
+    #[inline]
+    pub const fn entry_address_space_alignment(self) -> u64 {
+        1u64 << ((self as u8 - 1) * 9 + 12)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_layout() {
+        assert_eq!(core::mem::size_of::<PageTable>(), 4096);
+        assert_eq!(core::mem::align_of::<PageTable>(), 4096);
+    }
+
+    #[test]
+    pub fn test_index_for() {
+        let mut table = PageTable::new();
+        // indices 1, 2, 3 and 4 for levels 4 to 1
+        let addr = VirtAddr::new((1 << 39) | (2 << 30) | (3 << 21) | (4 << 12) | 0x123);
+        table
+            .index_for_mut(addr, PageTableLevel::Three)
+            .set_flags(PageTableFlags::PRESENT);
+        assert!(table[2].flags().contains(PageTableFlags::PRESENT));
+        assert!(table
+            .index_for(addr, PageTableLevel::Three)
+            .flags()
+            .contains(PageTableFlags::PRESENT));
+        assert!(table.index_for(addr, PageTableLevel::Four).is_unused());
+        assert!(core::ptr::eq(
+            table.index_for(addr, PageTableLevel::One),
+            &table[4]
+        ));
+    }
+
+    #[cfg(feature = "const_fn")]
+    #[test]
+    pub fn test_static_table() {
+        static TABLE: PageTable = PageTable::new();
+
+        assert!(crate::VirtAddr::from_ptr(&TABLE).is_aligned(4096u64));
+        assert_eq!(TABLE.count_used(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        assert_tokens(&PageTableIndex::new(511), &[Token::U16(511)]);
+        assert_de_tokens_error::<PageTableIndex>(
+            &[Token::U16(512)],
+            "invalid value: integer `512`, expected a page table index less than 512",
+        );
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        assert_tokens(&flags, &[Token::U64(flags.bits())]);
+        // bit 12 is part of the address
+        assert_de_tokens_error::<PageTableFlags>(
+            &[Token::U64(1 << 12)],
+            "invalid value: integer `4096`, expected valid page table flags",
+        );
+    }
+
+    #[test]
+    pub fn test_huge_page_for_level() {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE;
+        assert!(flags.is_huge_page());
+        assert!(!PageTableFlags::PRESENT.is_huge_page());
+
+        assert!(!flags.for_level(PageTableLevel::One).contains_huge());
+        assert!(flags.for_level(PageTableLevel::Two).contains_huge());
+        assert!(flags.for_level(PageTableLevel::Three).contains_huge());
+        assert!(!flags.for_level(PageTableLevel::Four).contains_huge());
+
+        assert_eq!(flags.for_level(PageTableLevel::One).without_huge(), flags);
+        assert_eq!(
+            flags.for_level(PageTableLevel::Two).without_huge(),
+            PageTableFlags::PRESENT
+        );
+    }
+
+    #[test]
+    pub fn test_present_and_used_entries() {
+        let mut table = PageTable::new();
+        assert_eq!(table.iter_present().count(), 0);
+        assert_eq!(table.count_used(), 0);
+        assert_eq!(table.next_free_index(), Some(PageTableIndex::new(0)));
+
+        // present entry mapping frame 0
+        table[0].set_addr(PhysAddr::new(0), PageTableFlags::PRESENT);
+        // not present, but flags set and address zero
+        table[1].set_flags(PageTableFlags::WRITABLE | PageTableFlags::BIT_9);
+        table[5].set_addr(PhysAddr::new(0x5000), PageTableFlags::PRESENT);
+
+        let present: Vec<_> = table.iter_present().map(|(i, _)| u16::from(i)).collect();
+        assert_eq!(present, [0, 5]);
+        assert_eq!(table.count_used(), 3);
+        assert_eq!(table.next_free_index(), Some(PageTableIndex::new(2)));
+
+        for (_, entry) in table.iter_present_mut() {
+            entry.set_unused();
+        }
+        assert_eq!(table.count_used(), 1);
+        assert_eq!(table.next_free_index(), Some(PageTableIndex::new(0)));
+
+        for entry in table.iter_mut() {
+            entry.set_flags(PageTableFlags::BIT_10);
+        }
+        assert_eq!(table.next_free_index(), None);
+    }
+
+    #[test]
+    pub fn test_os_bits() {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let mut entry = PageTableEntry::new();
+        entry.set_addr(PhysAddr::new(0x1234_5000), flags);
+
+        entry.set_os_bits_low(0b101);
+        entry.set_os_bits_high(0x7ff);
+        assert_eq!(entry.os_bits_low(), 0b101);
+        assert_eq!(entry.os_bits_high(), 0x7ff);
+        assert_eq!(entry.addr(), PhysAddr::new(0x1234_5000));
+        assert!(entry.flags().contains(flags));
+        assert!(entry
+            .flags()
+            .contains(PageTableFlags::BIT_9 | PageTableFlags::BIT_11));
+        assert!(!entry.flags().contains(PageTableFlags::BIT_10));
+
+        // `Mapper::update_flags` keeps the OS bits that are set in the entry
+        entry.set_flags_keep_os_bits(flags | PageTableFlags::USER_ACCESSIBLE);
+        assert_eq!(entry.os_bits_low(), 0b101);
+        assert_eq!(entry.os_bits_high(), 0x7ff);
+
+        // unless the new flags contain a protection key, which replaces bits 59 to 62
+        let mut pkey_entry = entry.clone();
+        pkey_entry.set_flags_keep_os_bits(flags.with_protection_key(ProtectionKey::new(3)));
+        assert_eq!(pkey_entry.protection_key(), ProtectionKey::new(3));
+        assert_eq!(pkey_entry.os_bits_high(), 0x7f | (3 << 7));
+        assert_eq!(pkey_entry.os_bits_low(), 0b101);
+
+        entry.set_os_bits_high(0);
+        entry.set_os_bits_low(0);
+        assert_eq!(entry.flags(), flags | PageTableFlags::USER_ACCESSIBLE);
+        assert_eq!(entry.addr(), PhysAddr::new(0x1234_5000));
+    }
+
+    #[test]
+    pub fn test_index_arithmetic() {
+        assert_eq!(PageTableIndex::iter().len(), ENTRY_COUNT);
+        let indices: Vec<u16> = PageTableIndex::iter().map(u16::from).collect();
+        assert_eq!(indices, (0..512).collect::<Vec<_>>());
+
+        let last = PageTableIndex::new(511);
+        assert_eq!(last.checked_add(1), None);
+        assert_eq!(last.checked_add(u16::MAX), None);
+        assert_eq!(last.wrapping_add(1), PageTableIndex::new(0));
+        assert_eq!(last.wrapping_add(u16::MAX), PageTableIndex::new(510));
+        let first = PageTableIndex::new(0);
+        assert_eq!(first.checked_sub(1), None);
+        assert_eq!(first.checked_add(511), Some(last));
+        assert_eq!(first.wrapping_sub(1), last);
+        assert_eq!(first.wrapping_sub(513), last);
+        assert_eq!(last.checked_sub(511), Some(first));
+
+        let offset = PageOffset::new(4095);
+        assert_eq!(offset.checked_add(1), None);
+        assert_eq!(offset.wrapping_add(1), PageOffset::new(0));
+        assert_eq!(PageOffset::new(0).checked_sub(1), None);
+        assert_eq!(PageOffset::new(0).wrapping_sub(1), offset);
+    }
+
+    #[test]
+    pub fn test_protection_key() {
+        let flags = PageTableFlags::user_data().with_protection_key(ProtectionKey::new(0xa));
+        let mut entry = PageTableEntry::new();
+        entry.set_addr(PhysAddr::new(0x2000), flags);
+        assert_eq!(entry.protection_key(), ProtectionKey::new(0xa));
+
+        entry.set_protection_key(ProtectionKey::new(0x5));
+        assert_eq!(entry.protection_key(), ProtectionKey::new(0x5));
+        assert_eq!(entry.addr(), PhysAddr::new(0x2000));
+        assert_eq!(
+            entry.flags(),
+            PageTableFlags::user_data() | PageTableFlags::BIT_59 | PageTableFlags::BIT_61
+        );
+
+        entry.set_protection_key(ProtectionKey::default());
+        assert_eq!(entry.flags(), PageTableFlags::user_data());
+        assert_eq!(ProtectionKey::new(15).write_disable_mask(), 1 << 31);
+    }
+
+    #[test]
+    pub fn test_zero_and_clone() {
+        let mut source = PageTable::new();
+        for (i, entry) in source.iter_mut().enumerate() {
+            entry.set_addr(PhysAddr::new(i as u64 * 0x1000), PageTableFlags::PRESENT);
+        }
+
+        let mut table = PageTable::new();
+        table.clone_range_from(&source, PageTableIndex::new(256)..=PageTableIndex::new(511));
+        assert_eq!(table.count_used(), 256);
+        assert!(table[255].is_unused());
+        assert_eq!(table[300].addr(), PhysAddr::new(300 * 0x1000));
+
+        table.clone_from(&source);
+        assert_eq!(table.count_used(), 512);
+        assert_eq!(table[1].addr(), PhysAddr::new(0x1000));
+
+        table.zero();
+        assert_eq!(table.count_used(), 0);
+
+        // empty ranges copy nothing
+        table.clone_range_from(&source, PageTableIndex::new(10)..=PageTableIndex::new(9));
+        assert_eq!(table.count_used(), 0);
+
+        table.clone_range_from(&source, PageTableIndex::new(511)..=PageTableIndex::new(511));
+        assert_eq!(table.count_used(), 1);
+        assert_eq!(table[511].addr(), PhysAddr::new(511 * 0x1000));
+    }
+
+    #[test]
+    pub fn test_index_conversions() {
+        #[cfg(feature = "const_fn")]
+        {
+            const RECURSIVE_INDEX: PageTableIndex = PageTableIndex::new(510);
+            const RECURSIVE_OFFSET: u64 = RECURSIVE_INDEX.as_u64() << 39;
+            assert_eq!(RECURSIVE_OFFSET, 0xff00_0000_0000);
+        }
+
+        assert_eq!(
+            PageTableIndex::try_from(511u16),
+            Ok(PageTableIndex::new(511))
+        );
+        assert_eq!(
+            PageTableIndex::try_from(512u64),
+            Err(PageTableIndexOutOfRange(512))
+        );
+        assert_eq!(PageOffset::try_from(4095u64), Ok(PageOffset::new(4095)));
+        assert_eq!(
+            PageOffset::try_from(4096u16),
+            Err(PageOffsetOutOfRange(4096))
+        );
+    }
+
+    #[test]
+    pub fn test_encrypted_addr() {
+        let c_bit = 1 << 47;
+        let mut entry = PageTableEntry::new();
+        entry.set_addr_encrypted(PhysAddr::new(0x3000), PageTableFlags::PRESENT, c_bit);
+        assert_eq!(entry.addr(), PhysAddr::new(0x3000 | c_bit));
+        assert_eq!(entry.addr_with_mask(c_bit), PhysAddr::new(0x3000));
+        assert_eq!(
+            entry.frame_with_mask(c_bit),
+            Ok(PhysFrame::containing_address(PhysAddr::new(0x3000)))
+        );
+
+        // updating the flags keeps the encryption bit
+        entry.set_flags(PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        assert_eq!(entry.addr(), PhysAddr::new(0x3000 | c_bit));
+    }
+
+    #[cfg(feature = "instructions")]
+    #[test]
+    pub fn test_set_addr_checked() {
+        let bits = PhysAddr::implemented_bits();
+        let mut entry = PageTableEntry::new();
+        assert!(entry
+            .set_addr_checked(PhysAddr::new(0x5000), PageTableFlags::PRESENT)
+            .is_ok());
+        assert_eq!(entry.addr(), PhysAddr::new(0x5000));
+
+        if bits < 52 {
+            let too_large = PhysAddr::new(1 << bits);
+            assert!(entry
+                .set_addr_checked(too_large, PageTableFlags::PRESENT)
+                .is_err());
+            assert_eq!(entry.addr(), PhysAddr::new(0x5000));
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    pub fn test_atomic_accessors() {
+        let mut table = PageTable::new();
+        let entry: *mut PageTableEntry = &mut table[5];
+        let present = PageTableFlags::PRESENT.bits();
+        unsafe {
+            PageTableEntry::store_volatile(entry, 0x5000 | present);
+            assert_eq!(PageTableEntry::load_volatile(entry), 0x5000 | present);
+            assert_eq!(
+                PageTableEntry::compare_exchange(entry, 0, 0x6000 | present),
+                Err(0x5000 | present)
+            );
+            assert_eq!(
+                PageTableEntry::compare_exchange(entry, 0x5000 | present, 0),
+                Ok(0x5000 | present)
+            );
+        }
+        assert!(table[5].is_unused());
+    }
+}